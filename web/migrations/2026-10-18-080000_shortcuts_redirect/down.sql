@@ -0,0 +1,3 @@
+DELETE FROM settings WHERE title = 'default_redirect';
+
+ALTER TABLE shortcuts DROP COLUMN redirect;
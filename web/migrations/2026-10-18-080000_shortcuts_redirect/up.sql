@@ -0,0 +1,3 @@
+ALTER TABLE shortcuts ADD COLUMN redirect VARCHAR;
+
+INSERT INTO settings(title, content) VALUES ('default_redirect', '"Temporary"');
@@ -0,0 +1,9 @@
+CREATE TABLE shortcut_hits (
+  id          SERIAL PRIMARY KEY,
+  shortcut    VARCHAR NOT NULL,
+  team_slug   VARCHAR NOT NULL,
+  user_mail   VARCHAR,
+  created_at  TIMESTAMP NOT NULL
+);
+
+CREATE INDEX shortcut_hits_shortcut_team_slug ON shortcut_hits (shortcut, team_slug);
@@ -0,0 +1,17 @@
+CREATE TABLE shortcut_versions (
+  id          SERIAL PRIMARY KEY,
+  shortcut    VARCHAR NOT NULL,
+  team_slug   VARCHAR NOT NULL,
+  version     INTEGER NOT NULL,
+  action      VARCHAR NOT NULL,
+  old_url     VARCHAR,
+  new_url     VARCHAR,
+  redirect    VARCHAR,
+  description VARCHAR,
+  tags        TEXT[] NOT NULL DEFAULT '{}',
+  user_mail   VARCHAR NOT NULL,
+  created_at  TIMESTAMP NOT NULL,
+  -- Set when the team is deleted, its history is kept but no longer listed, even to a new team of the same slug
+  archived_at TIMESTAMP,
+  UNIQUE (shortcut, team_slug, version)
+);
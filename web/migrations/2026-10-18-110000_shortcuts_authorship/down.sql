@@ -0,0 +1,5 @@
+ALTER TABLE shortcuts
+  DROP COLUMN created_by,
+  DROP COLUMN created_at,
+  DROP COLUMN updated_by,
+  DROP COLUMN updated_at;
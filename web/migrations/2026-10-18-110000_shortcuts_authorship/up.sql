@@ -0,0 +1,7 @@
+ALTER TABLE shortcuts
+  ADD COLUMN created_by VARCHAR,
+  ADD COLUMN created_at TIMESTAMP,
+  ADD COLUMN updated_by VARCHAR,
+  ADD COLUMN updated_at TIMESTAMP;
+
+CREATE INDEX shortcuts_created_by ON shortcuts (created_by);
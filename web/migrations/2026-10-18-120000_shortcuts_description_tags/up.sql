@@ -0,0 +1,5 @@
+ALTER TABLE shortcuts
+  ADD COLUMN description VARCHAR,
+  ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX shortcuts_tags ON shortcuts USING GIN (tags);
@@ -0,0 +1,9 @@
+CREATE TABLE shortcut_aliases (
+  alias     VARCHAR NOT NULL,
+  team_slug VARCHAR NOT NULL,
+  shortcut  VARCHAR NOT NULL,
+  FOREIGN KEY (shortcut, team_slug) REFERENCES shortcuts(shortcut, team_slug) ON DELETE CASCADE,
+  PRIMARY KEY (alias, team_slug)
+);
+
+CREATE INDEX shortcut_aliases_shortcut_team_slug ON shortcut_aliases (shortcut, team_slug);
@@ -0,0 +1,11 @@
+CREATE TABLE user_tokens (
+  id            SERIAL PRIMARY KEY,
+  user_mail     VARCHAR NOT NULL,
+  name          VARCHAR NOT NULL,
+  hash          VARCHAR NOT NULL UNIQUE,
+  scopes        TEXT[] NOT NULL DEFAULT '{}',
+  created_at    TIMESTAMP NOT NULL,
+  last_used_at  TIMESTAMP,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE,
+  UNIQUE (user_mail, name)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE sessions (
+  id            VARCHAR PRIMARY KEY,
+  value         VARCHAR NOT NULL,
+  created_at    TIMESTAMP NOT NULL,
+  last_seen_at  TIMESTAMP NOT NULL
+);
+
+CREATE INDEX sessions_last_seen_at ON sessions (last_seen_at);
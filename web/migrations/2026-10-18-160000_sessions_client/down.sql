@@ -0,0 +1,4 @@
+DROP INDEX sessions_value;
+
+ALTER TABLE sessions DROP COLUMN ip;
+ALTER TABLE sessions DROP COLUMN user_agent;
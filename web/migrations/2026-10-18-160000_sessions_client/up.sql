@@ -0,0 +1,4 @@
+ALTER TABLE sessions ADD COLUMN user_agent VARCHAR;
+ALTER TABLE sessions ADD COLUMN ip VARCHAR;
+
+CREATE INDEX sessions_value ON sessions (value);
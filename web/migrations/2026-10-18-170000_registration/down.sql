@@ -0,0 +1,3 @@
+DELETE FROM settings WHERE title = 'registration';
+
+ALTER TABLE users DROP COLUMN pwd_is_temporary;
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN pwd_is_temporary BOOLEAN NOT NULL DEFAULT false;
+
+INSERT INTO settings(title, content) VALUES ('registration', '{"enabled": false, "allowed_domains": []}');
@@ -0,0 +1 @@
+DROP TABLE pwd_resets;
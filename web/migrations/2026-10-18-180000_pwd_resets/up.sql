@@ -0,0 +1,9 @@
+CREATE TABLE pwd_resets (
+  hash        VARCHAR PRIMARY KEY,
+  user_mail   VARCHAR NOT NULL,
+  created_at  TIMESTAMP NOT NULL,
+  used_at     TIMESTAMP,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE
+);
+
+CREATE INDEX pwd_resets_user_mail ON pwd_resets (user_mail, created_at);
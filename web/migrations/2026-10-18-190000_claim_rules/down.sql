@@ -0,0 +1 @@
+DELETE FROM settings WHERE title = 'claim_rules';
@@ -0,0 +1 @@
+INSERT INTO settings(title, content) VALUES ('claim_rules', '[]');
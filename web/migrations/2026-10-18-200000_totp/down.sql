@@ -0,0 +1,5 @@
+DELETE FROM settings WHERE title = 'second_factor';
+
+DROP TABLE second_factor_logins;
+DROP TABLE recovery_codes;
+DROP TABLE totps;
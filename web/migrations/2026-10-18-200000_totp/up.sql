@@ -0,0 +1,25 @@
+CREATE TABLE totps (
+  user_mail   VARCHAR PRIMARY KEY,
+  secret      VARCHAR NOT NULL,
+  enabled     BOOLEAN NOT NULL DEFAULT false,
+  last_step   BIGINT NOT NULL DEFAULT 0,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE
+);
+
+CREATE TABLE recovery_codes (
+  hash        VARCHAR PRIMARY KEY,
+  user_mail   VARCHAR NOT NULL,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_codes_user_mail ON recovery_codes (user_mail);
+
+CREATE TABLE second_factor_logins (
+  hash        VARCHAR PRIMARY KEY,
+  user_mail   VARCHAR NOT NULL,
+  created_at  TIMESTAMP NOT NULL,
+  attempts    SMALLINT NOT NULL DEFAULT 0,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE
+);
+
+INSERT INTO settings(title, content) VALUES ('second_factor', '{"required": false}');
@@ -0,0 +1,5 @@
+UPDATE settings
+SET content = (
+  content::jsonb || jsonb_build_object('login', COALESCE(content::jsonb -> 'login', '{}'::jsonb) || '{"read_private": false}'::jsonb)
+)::text
+WHERE title = 'features';
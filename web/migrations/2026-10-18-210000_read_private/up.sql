@@ -0,0 +1,6 @@
+-- Reading without login is allowed once read_private is false, keep requiring it
+UPDATE settings
+SET content = (
+  content::jsonb || jsonb_build_object('login', COALESCE(content::jsonb -> 'login', '{}'::jsonb) || '{"read_private": true}'::jsonb)
+)::text
+WHERE title = 'features';
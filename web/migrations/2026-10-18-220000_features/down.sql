@@ -0,0 +1,10 @@
+UPDATE settings
+SET content = jsonb_build_object(
+  'login', jsonb_build_object(
+    'simple', false,
+    'google', false,
+    'read_private', COALESCE((content::jsonb #> '{login,read_private}')::boolean, true)
+  ),
+  'teams', false
+)::text
+WHERE title = 'features';
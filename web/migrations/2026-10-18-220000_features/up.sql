@@ -0,0 +1,11 @@
+-- Login methods and teams were always on, they are enforced now so keep them on
+UPDATE settings
+SET content = jsonb_build_object(
+  'login', jsonb_build_object(
+    'simple', true,
+    'google', true,
+    'read_private', COALESCE((content::jsonb #> '{login,read_private}')::boolean, true)
+  ),
+  'teams', true
+)::text
+WHERE title = 'features';
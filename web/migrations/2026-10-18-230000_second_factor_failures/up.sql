@@ -0,0 +1,9 @@
+-- Wrong codes of any login of a user, new logins don't give more guesses
+CREATE TABLE second_factor_failures (
+  id          SERIAL PRIMARY KEY,
+  user_mail   VARCHAR NOT NULL,
+  failed_at   TIMESTAMP NOT NULL,
+  FOREIGN KEY (user_mail) REFERENCES users(mail) ON DELETE CASCADE
+);
+
+CREATE INDEX second_factor_failures_user_mail ON second_factor_failures (user_mail, failed_at);
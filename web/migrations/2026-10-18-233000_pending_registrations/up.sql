@@ -0,0 +1,9 @@
+-- Self registrations waiting for their mailed link to be opened, the user is only created then
+CREATE TABLE pending_registrations (
+  hash        VARCHAR PRIMARY KEY,
+  mail        VARCHAR NOT NULL,
+  pwd         VARCHAR NOT NULL,
+  created_at  TIMESTAMP NOT NULL
+);
+
+CREATE INDEX pending_registrations_mail ON pending_registrations (mail, created_at);
@@ -0,0 +1,84 @@
+//! JSON API for scripts and tools, everything the web UI can do under `/go/api/v1`
+mod openapi;
+mod shortcuts;
+mod teams;
+mod users;
+
+pub use openapi::*;
+pub use shortcuts::*;
+pub use teams::*;
+pub use users::*;
+
+use rocket::{http::Status, Request};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::errors::AppError;
+
+pub const API_BASE: &str = "/go/api/v1";
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 200;
+
+/// `?page=2&per_page=20`, pages start at 1
+#[derive(FromForm, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub per_page: usize,
+    /// Items on all pages
+    pub total: usize,
+}
+
+impl<T> Page<T> {
+    pub fn of(all: Vec<T>, pagination: &Pagination) -> Result<Page<T>, AppError> {
+        let page = pagination.page.unwrap_or(1);
+        let per_page = pagination.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page == 0 || per_page == 0 || per_page > MAX_PER_PAGE {
+            return Err(AppError::BadRequest);
+        }
+
+        let total = all.len();
+        let items = all
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect();
+
+        Ok(Page {
+            items,
+            page,
+            per_page,
+            total,
+        })
+    }
+}
+
+/// Body of every error
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// Same bodies as the errors returned by the routes, e.g. when a guard or the body parsing fails
+#[catch(default)]
+pub fn api_error(status: Status, _: &Request) -> (Status, Value) {
+    let error = match status.code {
+        400 | 422 => AppError::BadRequest,
+        401 | 403 => AppError::Unauthorized,
+        404 => AppError::NotFound,
+        409 => AppError::Conflict,
+        _ => AppError::ServiceError,
+    };
+    let (_, body): (Status, Value) = error.into();
+
+    (status, body)
+}
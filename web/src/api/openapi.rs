@@ -0,0 +1,97 @@
+use rocket::serde::json::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::api::*;
+use crate::guards::SESSION_COOKIE;
+use crate::login::{
+    __path_forgot_pwd, __path_register, __path_reset_pwd, __path_second_factor_login, __path_simple_login,
+};
+
+/// Generated from the routes and their types, the checked in `openapi.json` must be updated with it
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "GO Slash API",
+        version = "1",
+        description = "Shortcuts, teams and users, authenticated with a personal token (`Authorization: Bearer go_...`) \
+            or the session returned by the login",
+        license(name = "MIT", url = "https://choosealicense.com/licenses/mit/"),
+    ),
+    paths(
+        simple_login,
+        second_factor_login,
+        register,
+        forgot_pwd,
+        reset_pwd,
+        api_list_shortcuts,
+        api_get_shortcut,
+        api_put_shortcut,
+        api_delete_shortcut,
+        api_list_teams,
+        api_get_team,
+        api_list_team_shortcuts,
+        api_create_team,
+        api_patch_team,
+        api_delete_team,
+        api_kick_user,
+        api_put_user_link_capability,
+        api_delete_user_link_capability,
+        api_put_user_team_acceptation,
+        api_get_user,
+        api_join_team,
+        api_leave_team,
+        api_put_user_team_ranks,
+        api_list_user_tokens,
+        api_create_user_token,
+        api_revoke_user_token,
+        api_put_user_pwd,
+        api_enroll_user_totp,
+        api_confirm_user_totp,
+        api_disable_user_totp,
+        api_list_user_sessions,
+        api_revoke_user_session,
+        api_list_users,
+        api_put_user_capability,
+        api_delete_user_capability,
+        api_revoke_user_sessions,
+        api_create_user_temporary_pwd,
+        api_reset_user_totp,
+    ),
+    modifiers(&ApiAuth),
+    security(("personal_token" = []), ("session_cookie" = []), ("session_header" = [])),
+)]
+pub struct ApiDoc;
+
+/// A personal token as bearer, or the session returned by the login as a cookie or in the
+/// `Authorization` header
+struct ApiAuth;
+
+impl Modify for ApiAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "personal_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("go_...")
+                    .description(Some("Personal token created at `go/go/user/tokens`"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        );
+        components.add_security_scheme(
+            "session_header",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("Authorization"))),
+        );
+    }
+}
+
+#[get("/go/api/v1/openapi.json")]
+pub fn api_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
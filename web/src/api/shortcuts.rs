@@ -0,0 +1,188 @@
+use rocket::{http::Status, serde::json::Json, State};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api::{ErrorBody, Page, Pagination};
+use crate::errors::AppError;
+use crate::shortcuts::{
+    parse_shortcut_path_buff, Shortcut, ShortcutAlias, ShortcutFilter, ShortcutOrder, Url,
+};
+use crate::users::{Reader, User};
+use crate::{DbConn, DbPool};
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct ShortcutWithAliases {
+    #[serde(flatten)]
+    pub shortcut: Shortcut,
+    pub aliases: Vec<String>,
+}
+
+/// `?q=jira&tag=oncall&page=2`, all optional
+#[derive(FromForm, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct ShortcutSearch {
+    /// Searched in names, aliases, urls, descriptions and tags
+    pub q: Option<String>,
+    pub sort: Option<ShortcutOrder>,
+    pub tag: Option<String>,
+    pub created_by: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+impl ShortcutWithAliases {
+    fn of(found: Vec<Shortcut>, conn: &mut DbConn) -> Result<Vec<ShortcutWithAliases>, AppError> {
+        let aliases = ShortcutAlias::of(&found, conn)?;
+
+        Ok(found
+            .into_iter()
+            .map(|shortcut| ShortcutWithAliases {
+                aliases: aliases
+                    .iter()
+                    .filter(|a| a.shortcut == shortcut.shortcut && a.team_slug == shortcut.team_slug)
+                    .map(|a| a.alias.clone())
+                    .collect(),
+                shortcut,
+            })
+            .collect())
+    }
+
+    /// Case insensitive search in the name, aliases, url, description and tags
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let shortcut = &self.shortcut;
+
+        shortcut.shortcut.to_lowercase().contains(&query)
+            || shortcut.url.to_lowercase().contains(&query)
+            || shortcut
+                .description
+                .as_ref()
+                .is_some_and(|d| d.to_lowercase().contains(&query))
+            || shortcut.tags.iter().any(|t| t.contains(&query))
+            || self.aliases.iter().any(|a| a.to_lowercase().contains(&query))
+    }
+}
+
+#[utoipa::path(
+    tag = "shortcuts",
+    params(ShortcutSearch),
+    responses(
+        (status = 200, body = Page<ShortcutWithAliases>),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/shortcuts?<search..>")]
+pub fn api_list_shortcuts(
+    search: ShortcutSearch,
+    reader: Reader,
+    pool: &State<DbPool>,
+) -> Result<Json<Page<ShortcutWithAliases>>, (Status, Value)> {
+    let Reader(user) = reader;
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let filter = ShortcutFilter {
+        author: search.created_by,
+        tag: search.tag,
+    };
+    let shortcuts = Shortcut::sorted(&user, search.sort.unwrap_or_default(), &filter, &mut conn)?;
+    let mut shortcuts = ShortcutWithAliases::of(shortcuts, &mut conn)?;
+    if let Some(q) = search.q {
+        shortcuts.retain(|s| s.matches(&q));
+    }
+
+    let pagination = Pagination {
+        page: search.page,
+        per_page: search.per_page,
+    };
+    Ok(Json(Page::of(shortcuts, &pagination)?))
+}
+
+#[utoipa::path(
+    tag = "shortcuts",
+    params(("team" = Option<String>, Query, description = "Slug of the team, the global team if missing")),
+    responses(
+        (status = 200, body = ShortcutWithAliases),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/shortcuts/<shortcut..>?<team>")]
+pub fn api_get_shortcut(
+    shortcut: PathBuf,
+    team: Option<String>,
+    reader: Reader,
+    pool: &State<DbPool>,
+) -> Result<Json<ShortcutWithAliases>, (Status, Value)> {
+    let Reader(user) = reader;
+    let shortcut = parse_shortcut_path_buff(&shortcut)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let found = Shortcut::find(shortcut, &team.unwrap_or_default(), &user, &mut conn)?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(ShortcutWithAliases::of(vec![found], &mut conn)?.remove(0)))
+}
+
+/// Create or update, `aliases` replace the current ones when given
+#[utoipa::path(
+    tag = "shortcuts",
+    params(("team" = Option<String>, Query, description = "Slug of the team, the global team if missing")),
+    request_body = Url,
+    responses(
+        (status = 200, body = ShortcutWithAliases),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody, description = "Name or alias already used in the team"),
+    ),
+)]
+#[put("/go/api/v1/shortcuts/<shortcut..>?<team>", data = "<data>")]
+pub fn api_put_shortcut(
+    shortcut: PathBuf,
+    team: Option<String>,
+    data: Json<Url>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<ShortcutWithAliases>, (Status, Value)> {
+    let shortcut = parse_shortcut_path_buff(&shortcut)?;
+
+    data.check()?;
+    let (new_shortcut, aliases) = data
+        .into_inner()
+        .into_new_shortcut(shortcut, team.unwrap_or_default());
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let saved = Shortcut::upsert(new_shortcut, aliases, &user, &mut conn)?;
+
+    Ok(Json(ShortcutWithAliases::of(vec![saved], &mut conn)?.remove(0)))
+}
+
+#[utoipa::path(
+    tag = "shortcuts",
+    params(("team" = Option<String>, Query, description = "Slug of the team, the global team if missing")),
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/shortcuts/<shortcut..>?<team>")]
+pub fn api_delete_shortcut(
+    shortcut: PathBuf,
+    team: Option<String>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let shortcut = parse_shortcut_path_buff(&shortcut)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Shortcut::delete(shortcut, &team.unwrap_or_default(), &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
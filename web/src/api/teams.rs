@@ -0,0 +1,252 @@
+use rocket::{http::Status, serde::json::Json, State};
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::api::{ErrorBody, Page, Pagination};
+use crate::errors::AppError;
+use crate::guards::TeamsEnabled;
+use crate::shortcuts::Shortcut;
+use crate::teams::{
+    NewTeam, PatchableTeam, Team, TeamCapability, TeamForOptUser, TeamWithUserLinks,
+};
+use crate::users::{Reader, User};
+use crate::DbPool;
+
+#[utoipa::path(
+    tag = "teams",
+    params(Pagination),
+    responses(
+        (status = 200, body = Page<TeamForOptUser>, description = "The global team first, then by title"),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/teams?<pagination..>")]
+pub fn api_list_teams(
+    pagination: Pagination,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<Page<TeamForOptUser>>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let teams = Team::all_with_user_link(&user.mail, &mut conn)?;
+
+    Ok(Json(Page::of(teams, &pagination)?))
+}
+
+#[utoipa::path(
+    tag = "teams",
+    responses(
+        (status = 200, body = TeamWithUserLinks),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/teams/<slug>")]
+pub fn api_get_team(
+    slug: String,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<TeamWithUserLinks>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let team = Team::with_all_user_links(&slug, &user, &mut conn)?.ok_or(AppError::NotFound)?;
+
+    Ok(Json(team))
+}
+
+#[utoipa::path(
+    tag = "teams",
+    params(Pagination),
+    responses(
+        (status = 200, body = Page<Shortcut>),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/teams/<slug>/shortcuts?<pagination..>")]
+pub fn api_list_team_shortcuts(
+    slug: String,
+    pagination: Pagination,
+    _teams: TeamsEnabled,
+    reader: Reader,
+    pool: &State<DbPool>,
+) -> Result<Json<Page<Shortcut>>, (Status, Value)> {
+    let Reader(user) = reader;
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let team = Team::find(&slug, &user, &mut conn)?.ok_or(AppError::NotFound)?;
+    let shortcuts = Shortcut::of_team(&team, &user, &mut conn)?;
+
+    Ok(Json(Page::of(shortcuts, &pagination)?))
+}
+
+#[utoipa::path(
+    tag = "teams",
+    request_body = NewTeam,
+    responses(
+        (status = 201, body = Team, description = "Not accepted yet if the user can only create teams with validation"),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[post("/go/api/v1/teams", data = "<new_team>")]
+pub fn api_create_team(
+    new_team: Json<NewTeam>,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<(Status, Json<Team>), (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let (team, _) = Team::create(new_team.into_inner(), &user, &mut conn)?;
+
+    Ok((Status::Created, Json(team)))
+}
+
+#[utoipa::path(
+    tag = "teams",
+    request_body = PatchableTeam,
+    responses(
+        (status = 200, body = Team),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[patch("/go/api/v1/teams/<slug>", data = "<patchable_team>")]
+pub fn api_patch_team(
+    slug: String,
+    patchable_team: Json<PatchableTeam>,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<Team>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let team = Team::update(patchable_team.into_inner(), &slug, &user, &mut conn)?;
+
+    Ok(Json(team))
+}
+
+#[utoipa::path(
+    tag = "teams",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/teams/<slug>")]
+pub fn api_delete_team(
+    slug: String,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Team::delete(&slug, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "teams",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/teams/<slug>/users/<mail>")]
+pub fn api_kick_user(
+    slug: String,
+    mail: String,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Team::kick_user(&slug, &mail, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "teams",
+    params(("capability" = TeamCapability, Path)),
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[put("/go/api/v1/teams/<slug>/users/<mail>/capabilities/<capability>")]
+pub fn api_put_user_link_capability(
+    slug: String,
+    mail: String,
+    capability: String,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let capability = TeamCapability::from_str(&capability).map_err(|_| AppError::BadRequest)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Team::add_user_capability(&mail, &slug, capability, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "teams",
+    params(("capability" = TeamCapability, Path)),
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/teams/<slug>/users/<mail>/capabilities/<capability>")]
+pub fn api_delete_user_link_capability(
+    slug: String,
+    mail: String,
+    capability: String,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let capability = TeamCapability::from_str(&capability).map_err(|_| AppError::BadRequest)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Team::remove_user_capability(&mail, &slug, capability, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "teams",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[put("/go/api/v1/teams/<slug>/users/<mail>/is_accepted/<acceptation>")]
+pub fn api_put_user_team_acceptation(
+    slug: String,
+    mail: String,
+    acceptation: bool,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Team::set_acceptation_user(&mail, &slug, &acceptation, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
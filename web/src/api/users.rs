@@ -0,0 +1,445 @@
+use rocket::{http::Status, serde::json::Json, State};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::api::{ErrorBody, Page, Pagination};
+use crate::errors::AppError;
+use crate::guards::{SessionId, TeamsEnabled};
+use crate::settings::{second_factor_policy, teams_enabled};
+use crate::users::{
+    ActiveSession, Capability, CreatedToken, NewToken, PwdChange, RecoveryCodes, Sessions, TemporaryPwd,
+    TotpCode, TotpEnrollment, User, UserTeam, UserTeamLink, UserToken,
+};
+use crate::{AppConfig, DbPool};
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, body = User),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/user")]
+pub fn api_get_user(user: User) -> Json<User> {
+    Json(user)
+}
+
+/// Join `team`, the global team if missing
+#[utoipa::path(
+    tag = "users",
+    request_body = UserTeamLink,
+    responses(
+        (status = 201, body = UserTeam, description = "Not accepted yet if the team is private"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[post("/go/api/v1/user/teams?<team>", data = "<team_user_link>")]
+pub fn api_join_team(
+    team: Option<String>,
+    team_user_link: Json<UserTeamLink>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<(Status, Json<UserTeam>), (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let team = team.unwrap_or_default();
+    if !team.is_empty() {
+        teams_enabled(&mut conn)?;
+    }
+
+    let user_team = user.join_team(&team, team_user_link.rank, &mut conn)?;
+
+    Ok((Status::Created, Json(user_team)))
+}
+
+/// Leave `team`, the global team if missing
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/user/teams?<team>")]
+pub fn api_leave_team(
+    team: Option<String>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let team = team.unwrap_or_default();
+    if !team.is_empty() {
+        teams_enabled(&mut conn)?;
+    }
+
+    user.leave_team(&team, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body(content = HashMap<String, u16>, description = "Rank by team slug"),
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[put("/go/api/v1/user/teams/ranks", data = "<team_ranks>")]
+pub fn api_put_user_team_ranks(
+    team_ranks: Json<HashMap<String, u16>>,
+    _teams: TeamsEnabled,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    user.set_team_ranks(team_ranks.into_inner(), &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, body = Vec<UserToken>, description = "Newest first"),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/user/tokens")]
+pub fn api_list_user_tokens(user: User, pool: &State<DbPool>) -> Result<Json<Vec<UserToken>>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Ok(Json(UserToken::of(&user, &mut conn)?))
+}
+
+/// The secret is only returned here, scoped tokens can't manage tokens
+#[utoipa::path(
+    tag = "users",
+    request_body = NewToken,
+    responses(
+        (status = 201, body = CreatedToken),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 409, body = ErrorBody, description = "Name already used by another token"),
+    ),
+)]
+#[post("/go/api/v1/user/tokens", data = "<new_token>")]
+pub fn api_create_user_token(
+    new_token: Json<NewToken>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<(Status, Json<CreatedToken>), (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let created = UserToken::create(new_token.into_inner(), &user, &mut conn)?;
+
+    Ok((Status::Created, Json(created)))
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/user/tokens/<id>")]
+pub fn api_revoke_user_token(id: i32, user: User, pool: &State<DbPool>) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    UserToken::revoke(id, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// Set a password for the simple login, the current one is required if there is one.
+/// The other sessions of the user are ended
+#[utoipa::path(
+    tag = "users",
+    request_body = PwdChange,
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody, description = "Wrong current password"),
+    ),
+)]
+#[put("/go/api/v1/user/pwd", data = "<change>")]
+pub fn api_put_user_pwd(
+    change: Json<PwdChange>,
+    user: User,
+    session_id: Option<SessionId>,
+    sessions: &State<Sessions>,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    user.change_pwd(
+        change.into_inner(),
+        &config.password_hashing,
+        &config.simple_login_salt2,
+        &mut conn,
+    )?;
+    sessions.end_all_of(&user.mail, session_id.as_ref(), &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// Start adding an authenticator, replacing one not confirmed yet
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 201, body = TotpEnrollment),
+        (status = 401, body = ErrorBody),
+        (status = 409, body = ErrorBody, description = "An authenticator is already enabled"),
+    ),
+)]
+#[post("/go/api/v1/user/totp")]
+pub fn api_enroll_user_totp(user: User, pool: &State<DbPool>) -> Result<(Status, Json<TotpEnrollment>), (Status, Value)> {
+    user.should_be_unscoped()?;
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let enrollment = User::enroll_totp(&user.mail, &mut conn)?;
+
+    Ok((Status::Created, Json(enrollment)))
+}
+
+/// Confirm the enrolled authenticator with one of its codes, password logins ask for one from then on
+#[utoipa::path(
+    tag = "users",
+    request_body = TotpCode,
+    responses(
+        (status = 200, body = RecoveryCodes, description = "Only given once"),
+        (status = 400, body = ErrorBody, description = "Wrong code"),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody, description = "No authenticator enrolled"),
+        (status = 409, body = ErrorBody, description = "Already confirmed"),
+    ),
+)]
+#[put("/go/api/v1/user/totp", data = "<code>")]
+pub fn api_confirm_user_totp(
+    code: Json<TotpCode>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<RecoveryCodes>, (Status, Value)> {
+    user.should_be_unscoped()?;
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let recovery_codes = User::confirm_totp(&user.mail, &code.code, &mut conn)?;
+
+    Ok(Json(recovery_codes))
+}
+
+/// Remove the authenticator with one of its codes or a recovery code
+#[utoipa::path(
+    tag = "users",
+    request_body = TotpCode,
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody, description = "Wrong code"),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody, description = "2FA is required"),
+    ),
+)]
+#[delete("/go/api/v1/user/totp", data = "<code>")]
+pub fn api_disable_user_totp(
+    code: Json<TotpCode>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let policy = second_factor_policy(&mut conn)?;
+    user.disable_totp(&code.code, &policy, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, body = Vec<ActiveSession>, description = "Last seen first"),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/user/sessions")]
+pub fn api_list_user_sessions(
+    user: User,
+    session_id: Option<SessionId>,
+    sessions: &State<Sessions>,
+    pool: &State<DbPool>,
+) -> Result<Json<Vec<ActiveSession>>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Ok(Json(sessions.of(&user, session_id.as_ref(), &mut conn)?))
+}
+
+/// Log out the session with this `id`, as listed
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/user/sessions/<id>")]
+pub fn api_revoke_user_session(
+    id: String,
+    user: User,
+    sessions: &State<Sessions>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    sessions.revoke(&id, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(Pagination),
+    responses(
+        (status = 200, body = Page<User>),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[get("/go/api/v1/users?<pagination..>")]
+pub fn api_list_users(
+    pagination: Pagination,
+    _user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<Page<User>>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Ok(Json(Page::of(User::all(&mut conn)?, &pagination)?))
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(("capability" = Capability, Path)),
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[put("/go/api/v1/users/<mail>/capabilities/<capability>")]
+pub fn api_put_user_capability(
+    mail: String,
+    capability: String,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let capability = Capability::from_str(&capability).map_err(|_| AppError::BadRequest)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    User::add_capability(&mail, capability, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(("capability" = Capability, Path)),
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/users/<mail>/capabilities/<capability>")]
+pub fn api_delete_user_capability(
+    mail: String,
+    capability: String,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let capability = Capability::from_str(&capability).map_err(|_| AppError::BadRequest)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    User::remove_capability(&mail, capability, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// Log the user out everywhere
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+    ),
+)]
+#[delete("/go/api/v1/users/<mail>/sessions")]
+pub fn api_revoke_user_sessions(
+    mail: String,
+    user: User,
+    sessions: &State<Sessions>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    sessions.revoke_all_of(&mail, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// Replace the password of the user by a random one they should change after logging in,
+/// they are logged out everywhere
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 201, body = TemporaryPwd),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    ),
+)]
+#[post("/go/api/v1/users/<mail>/temporary_pwd")]
+pub fn api_create_user_temporary_pwd(
+    mail: String,
+    user: User,
+    sessions: &State<Sessions>,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<(Status, Json<TemporaryPwd>), (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let temporary_pwd = User::set_temporary_pwd(
+        &mail,
+        &user,
+        &config.password_hashing,
+        &config.simple_login_salt1,
+        &mut conn,
+    )?;
+    sessions.end_all_of(&mail, None, &mut conn)?;
+
+    Ok((Status::Created, Json(temporary_pwd)))
+}
+
+/// Remove the authenticator of a user who lost it, and their recovery codes
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 204),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody, description = "No authenticator"),
+    ),
+)]
+#[delete("/go/api/v1/users/<mail>/totp")]
+pub fn api_reset_user_totp(mail: String, user: User, pool: &State<DbPool>) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    User::reset_totp(&mail, &user, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
@@ -13,6 +13,8 @@ pub enum AppError {
     Guard,
     NotFound,
     ServiceError,
+    Conflict,
+    TooManyRequests,
 }
 
 impl From<RustbreakError> for AppError {
@@ -36,6 +38,20 @@ impl From<r2d2::Error> for AppError {
     }
 }
 
+impl From<argon2::Error> for AppError {
+    fn from(e: argon2::Error) -> Self {
+        error!("{:?}", e);
+        AppError::ServiceError
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        error!("{:?}", e);
+        AppError::ServiceError
+    }
+}
+
 
 impl From<AppError> for (Status, Value) {
     fn from(e: AppError) -> Self {
@@ -65,6 +81,14 @@ impl From<AppError> for (Status, Value) {
                 Status::InternalServerError,
                 json!({"error": "Wow that's weird :/"}),
             ),
+            AppError::Conflict => (
+                Status::Conflict,
+                json!({"error": "Name already used."}),
+            ),
+            AppError::TooManyRequests => (
+                Status::TooManyRequests,
+                json!({"error": "Too many attempts, try again later."}),
+            ),
         }
     }
 }
@@ -81,6 +105,8 @@ impl From<AppError> for (Status, Template) {
             AppError::Guard => (Status::InternalServerError, Template::render("error", "")),
             AppError::NotFound => (Status::NotFound, Template::render("error", "")),
             AppError::ServiceError => (Status::InternalServerError, Template::render("error", "")),
+            AppError::Conflict => (Status::Conflict, Template::render("error", "")),
+            AppError::TooManyRequests => (Status::TooManyRequests, Template::render("error", "")),
         }
     }
 }
\ No newline at end of file
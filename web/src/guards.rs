@@ -8,17 +8,24 @@ use rocket::{
     response::Responder,
     Response,
 };
-use rocket_dyn_templates::Template;
-use serde_json::json;
 
 use crate::{
+    api::API_BASE,
     errors::{
         AppError,
     },
-    users::Sessions,
+    settings::teams_enabled,
+    users::{SessionClient, Sessions},
 };
 
-use crate::AppConfig;
+use crate::{
+    login::login_template,
+    services::{
+        ldap::LdapService,
+        oidc::{OidcLogin, OidcService},
+    },
+    AppConfig, DbPool,
+};
 
 pub const SESSION_COOKIE: &str = "go_session_id";
 
@@ -58,13 +65,55 @@ fn session_from_headers(headers: &HeaderMap) -> Option<SessionId> {
     Some(SessionId(cookie.value().to_string()))
 }
 
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SessionClient {
+    type Error = serde_json::Value;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SessionClient {
+            user_agent: req.headers().get_one("User-Agent").map(str::to_string),
+            ip: req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
+
+/// Personal access token sent as `Authorization: Bearer <token>`
+pub fn bearer_token<'r>(headers: &'r HeaderMap) -> Option<&'r str> {
+    headers
+        .get("Authorization")
+        .find_map(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+
+/// Routes of teams other than the global one, hidden when teams are turned off in the features
+pub struct TeamsEnabled;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TeamsEnabled {
+    type Error = serde_json::Value;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool: Outcome<&State<DbPool>, Self::Error> = req
+            .guard::<&State<DbPool>>()
+            .await
+            .map_error(|_| AppError::Guard.into());
+        let pool = try_outcome!(pool);
+
+        match pool.get().map_err(AppError::from).and_then(|mut conn| teams_enabled(&mut conn)) {
+            Ok(()) => Outcome::Success(TeamsEnabled),
+            Err(e) => Outcome::Error(e.into()),
+        }
+    }
+}
 
+/// The OIDC login started in the session, stored as its value until the provider calls back
 #[derive(Clone)]
-pub struct NonceOIDC(pub String);
+pub struct PendingOidcLogin(pub OidcLogin);
 
 
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for NonceOIDC {
+impl<'r> FromRequest<'r> for PendingOidcLogin {
     type Error = serde_json::Value;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
@@ -74,14 +123,31 @@ impl<'r> FromRequest<'r> for NonceOIDC {
             .map_error(|_| AppError::Guard.into());
         let sessions = try_outcome!(sessions);
 
+        let pool: Outcome<&State<DbPool>, Self::Error> = req
+            .guard::<&State<DbPool>>()
+            .await
+            .map_error(|_| AppError::Guard.into());
+        let pool = try_outcome!(pool);
+
         let session_id = try_outcome!(req.guard::<SessionId>().await);
 
-        match sessions.is_logged_in(&session_id.0) {
-            None => {
+        let value = pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|mut conn| sessions.is_logged_in(&session_id.0, &mut conn));
+        match value {
+            Ok(Some(value)) => match serde_json::from_str(&value) {
+                Ok(login) => Outcome::Success(PendingOidcLogin(login)),
+                Err(_) => {
+                    error!("No OIDC login in this session.");
+                    Outcome::Error(AppError::Unauthorized.into())
+                }
+            },
+            Ok(None) => {
                 error!("Wrong session_id.");
                 Outcome::Error(AppError::Unauthorized.into())
             }
-            Some(nonce) => Outcome::Success(NonceOIDC(nonce)),
+            Err(err) => Outcome::Error(err.into()),
         }
     }
 }
@@ -100,6 +166,10 @@ impl Fairing for UnauthorizedAsLogin {
     }
 
     async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        // API clients always get JSON errors
+        if request.uri().path().starts_with(API_BASE) {
+            return;
+        }
         if let Some(accept) = request.accept() && !accept.media_types().any(|x| x ==&MediaType::HTML) {
             return;
         }
@@ -127,10 +197,31 @@ impl Fairing for UnauthorizedAsLogin {
             }
         };
 
-        let template = Template::render(
-            "login",
-            json!({ "context": json!({ "simple_salt": conf.simple_login_salt1 }).to_string() }),
-        );
+        let pool = match request.guard::<&State<DbPool>>().await {
+            Outcome::Success(x) => x,
+            _ => {
+                error!("UnauthorizedAsLogin fail getting State<DbPool>");
+                return;
+            }
+        };
+
+        let oidc_service = match request.guard::<&State<OidcService>>().await {
+            Outcome::Success(x) => x,
+            _ => {
+                error!("UnauthorizedAsLogin fail getting State<OidcService>");
+                return;
+            }
+        };
+
+        let ldap_service = match request.guard::<&State<LdapService>>().await {
+            Outcome::Success(x) => x,
+            _ => {
+                error!("UnauthorizedAsLogin fail getting State<LdapService>");
+                return;
+            }
+        };
+
+        let template = login_template(conf, oidc_service, ldap_service, pool);
 
         match template.respond_to(request) {
             Ok(res) => response.merge(res),
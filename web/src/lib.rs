@@ -16,6 +16,7 @@ use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
 use rocket::{fairing::AdHoc, fs::FileServer, http::Status, routes, Build, Config, Rocket};
 use rocket_dyn_templates::Template;
 
+pub mod api;
 pub mod guards;
 pub mod errors;
 pub mod users;
@@ -27,29 +28,65 @@ mod views;
 pub mod settings;
 pub mod login;
 
+use api::{
+    api_create_team, api_delete_shortcut, api_delete_team, api_delete_user_capability,
+    api_delete_user_link_capability, api_error, api_get_shortcut, api_get_team, api_get_user,
+    api_join_team, api_kick_user, api_leave_team, api_list_shortcuts, api_list_team_shortcuts,
+    api_list_teams, api_list_users, api_openapi, api_patch_team, api_put_shortcut,
+    api_put_user_capability, api_put_user_link_capability, api_put_user_team_acceptation,
+    api_put_user_team_ranks, api_list_user_tokens, api_create_user_token, api_revoke_user_token,
+    api_list_user_sessions, api_revoke_user_session, api_revoke_user_sessions, api_put_user_pwd,
+    api_create_user_temporary_pwd, api_enroll_user_totp, api_confirm_user_totp, api_disable_user_totp,
+    api_reset_user_totp, API_BASE,
+};
 use teams::{
     create_team, delete_team, delete_user_link_capability, kick_user, list_teams, patch_team,
     put_user_link_capability, put_user_team_acceptation, show_team,
 };
-use settings::{patch_settings, get_settings};
-use login::{google_login, login as go_login, login_redirect_google, simple_login};
-use shortcuts::{delete_shortcut, get_shortcut, index, put_shortcut};
+use settings::{
+    patch_settings, get_settings, put_settings_claim_rules, put_settings_default_redirect, put_settings_registration,
+    put_settings_second_factor,
+};
+use login::{login as go_login, login_redirect_oidc, logout, oidc_login, register, confirm_registration, second_factor_login, simple_login,
+    forgot_pwd, reset_pwd, reset_pwd_form};
+use shortcuts::{
+    delete_shortcut, get_shortcut, get_shortcut_history, get_shortcuts_stats, index, put_shortcut,
+    revert_shortcut, Hits,
+};
     use users::{
         delete_user_capability, join_global_team, join_team, leave_global_team, leave_team,
-        list_users, put_user_capability, put_user_team_ranks,
+        list_user_sessions, list_user_tokens, list_users, user_password, put_user_capability, put_user_team_ranks,
     };
 
 
 use crate::{
-    guards::UnauthorizedAsLogin, users::Sessions, services::oidc::OidcService,
+    guards::UnauthorizedAsLogin, users::{PasswordHashing, ProxyAuth, Sessions},
+    services::{ldap::LdapService, mailer::Mailer, oidc::OidcService},
 };
 
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
+/// How long a shutdown waits for the mails still queued
+const MAIL_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 pub struct AppConfig {
     pub simple_login_salt1: String,
+    /// Only checks the legacy password hashes, they are upgraded on login
     pub simple_login_salt2: String,
+    pub password_hashing: PasswordHashing,
+    /// Where the app is reached, for the links sent by mail
+    pub public_url: String,
+    /// Trust the users told by a reverse proxy, on top of sessions and tokens
+    pub proxy_auth: Option<ProxyAuth>,
+}
+
+/// Outside services the app talks to
+pub struct Services {
+    pub oidc: OidcService,
+    pub mailer: Mailer,
+    /// Checked first by the simple login when configured
+    pub ldap: LdapService,
 }
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
@@ -64,7 +101,7 @@ pub fn server(
     config: AppConfig,
     run_migration: bool,
     cli_colors: bool,
-    oidc_service: OidcService,
+    services: Services,
 ) -> Rocket<Build> {
     let db_manager: ConnectionManager<PgConnection> = ConnectionManager::new(db_url);
     let db_pool = Pool::builder().max_size(15).build(db_manager).unwrap();
@@ -90,11 +127,28 @@ pub fn server(
                 get_shortcut,
                 put_shortcut,
                 delete_shortcut,
+                get_shortcuts_stats,
+                get_shortcut_history,
+                revert_shortcut,
                 go_login,
                 list_users,
+                list_user_tokens,
+                list_user_sessions,
+                user_password,
                 get_settings,
                 patch_settings,
+                put_settings_default_redirect,
+                put_settings_registration,
+                put_settings_claim_rules,
+                put_settings_second_factor,
                 simple_login,
+                second_factor_login,
+                register,
+                confirm_registration,
+                logout,
+                forgot_pwd,
+                reset_pwd_form,
+                reset_pwd,
                 health_check,
                 put_user_team_ranks,
                 list_teams,
@@ -108,20 +162,94 @@ pub fn server(
                 show_team,
                 put_user_capability,
                 delete_user_capability,
-                google_login,
-                login_redirect_google,
+                oidc_login,
+                login_redirect_oidc,
                 kick_user,
                 put_user_link_capability,
                 delete_user_link_capability,
                 put_user_team_acceptation
             ],
         )
+        .mount(
+            "/",
+            routes![
+                api_list_shortcuts,
+                api_get_shortcut,
+                api_put_shortcut,
+                api_delete_shortcut,
+                api_list_teams,
+                api_get_team,
+                api_list_team_shortcuts,
+                api_create_team,
+                api_patch_team,
+                api_delete_team,
+                api_kick_user,
+                api_put_user_link_capability,
+                api_delete_user_link_capability,
+                api_put_user_team_acceptation,
+                api_get_user,
+                api_join_team,
+                api_leave_team,
+                api_put_user_team_ranks,
+                api_list_user_tokens,
+                api_create_user_token,
+                api_revoke_user_token,
+                api_put_user_pwd,
+                api_enroll_user_totp,
+                api_confirm_user_totp,
+                api_disable_user_totp,
+                api_list_user_sessions,
+                api_revoke_user_session,
+                api_list_users,
+                api_put_user_capability,
+                api_delete_user_capability,
+                api_revoke_user_sessions,
+                api_create_user_temporary_pwd,
+                api_reset_user_totp,
+                api_openapi
+            ],
+        )
+        .register(API_BASE, catchers![api_error])
         .mount("/public", FileServer::from("./public"))
         .manage(sessions)
         .manage(config)
-        .manage(oidc_service)
+        .manage(services.oidc)
+        .manage(services.mailer)
+        .manage(services.ldap)
         .manage(db_pool)
+        .manage(Hits::default())
         .attach(Template::fairing())
+        .attach(AdHoc::on_liftoff("Shortcut hits flush", |rocket| {
+            Box::pin(async move {
+                if let (Some(hits), Some(pool)) = (rocket.state::<Hits>(), rocket.state::<DbPool>()) {
+                    hits.flush_periodically(pool.clone());
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Expired sessions purge", |rocket| {
+            Box::pin(async move {
+                if let (Some(sessions), Some(pool)) = (rocket.state::<Sessions>(), rocket.state::<DbPool>()) {
+                    sessions.purge_periodically(pool.clone());
+                }
+            })
+        }))
+        .attach(AdHoc::on_shutdown("Shortcut hits flush", |rocket| {
+            Box::pin(async move {
+                if let (Some(hits), Some(pool)) = (rocket.state::<Hits>(), rocket.state::<DbPool>()) {
+                    let hits = hits.clone();
+                    let pool = pool.clone();
+                    let _ = rocket::tokio::task::spawn_blocking(move || hits.flush_with(&pool)).await;
+                }
+            })
+        }))
+        .attach(AdHoc::on_shutdown("Queued mails", |rocket| {
+            Box::pin(async move {
+                if let Some(mailer) = rocket.state::<Mailer>() {
+                    let mailer = mailer.clone();
+                    let _ = rocket::tokio::task::spawn_blocking(move || mailer.flush(MAIL_FLUSH_TIMEOUT)).await;
+                }
+            })
+        }))
         .attach(UnauthorizedAsLogin {})
         .attach(AdHoc::on_response("HTTP code", |_, res| {
             Box::pin(async move {
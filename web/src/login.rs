@@ -10,94 +10,426 @@ use rocket_dyn_templates::Template;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha256::digest;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
+    api::ErrorBody,
     users::MAIL_REGEX,
-    guards::{NonceOIDC, SessionId, SESSION_COOKIE},
+    guards::{PendingOidcLogin, SessionId, SESSION_COOKIE},
     errors::{
         AppError,
     },
-    settings::default_capabilities,
-    users::{Sessions, User, UserWithPwd},
+    settings::{claim_rules, default_capabilities, features, registration, second_factor_policy, Features},
+    users::{is_pwd_reset_token, ForgottenPwd, PwdReset, SecondFactorCode, SessionClient, Sessions, TotpEnrollment, User, UserWithPwd},
     schema::users,
-    services::oidc::OidcService,
+    services::{
+        ldap::{LdapService, LdapUser, LDAP_PROVIDER},
+        mailer::Mailer,
+        oidc::{OidcService, OidcServiceError},
+    },
     AppConfig, DbConn, DbPool,
 };
 
 #[get("/go/login")]
-pub fn login(conf: &State<AppConfig>) -> Result<Template, (Status, Template)> {
-    Ok(Template::render(
+pub fn login(
+    conf: &State<AppConfig>,
+    oidc_service: &State<OidcService>,
+    ldap_service: &State<LdapService>,
+    pool: &State<DbPool>,
+) -> Result<Template, (Status, Template)> {
+    Ok(login_template(conf, oidc_service, ldap_service, pool))
+}
+
+/// The registration form is only shown when it is enabled, with a button per identity provider.
+/// With LDAP, passwords are sent as typed to be bound with.
+/// The login methods turned off in the features aren't shown, no salt hides the mail and password form
+pub fn login_template(
+    conf: &AppConfig,
+    oidc_service: &OidcService,
+    ldap_service: &LdapService,
+    pool: &DbPool,
+) -> Template {
+    let (features, registration) = pool
+        .get()
+        .map_err(AppError::from)
+        .and_then(|mut conn| Ok((features(&mut conn)?, registration(&mut conn)?.enabled)))
+        .unwrap_or_else(|_| (Features::default(), false));
+
+    let simple_salt = features.login.simple.then_some(&conf.simple_login_salt1);
+    let providers = if features.login.google { oidc_service.providers() } else { vec![] };
+
+    Template::render(
         "login",
-        json!({ "context": json!({ "simple_salt": conf.simple_login_salt1 }).to_string() }),
-    ))
+        json!({ "context": json!({
+            "simple_salt": simple_salt,
+            "registration": features.login.simple && registration,
+            "providers": providers,
+            "ldap": ldap_service.is_enabled(),
+        }).to_string() }),
+    )
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct LoginSuccessfull {
     pub token: String,
+    /// Set by an admin, to be changed
+    #[serde(default)]
+    pub pwd_is_temporary: bool,
+    /// Only given once, when the login confirmed the enrollment of an authenticator
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery_codes: Vec<String>,
+}
+
+/// The password was right but a code of an authenticator is needed too
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct SecondFactorRequired {
+    /// To post along the code to `/go/login/second_factor`
+    pub token: String,
+    /// When 2FA is required of a user without authenticator, the one to add. Its first code confirms it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrollment: Option<TotpEnrollment>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginStep {
+    Done(LoginSuccessfull),
+    SecondFactor(SecondFactorRequired),
+}
+
+impl LoginStep {
+    /// `done` once logged in, `202 Accepted` while a code is expected
+    fn status(&self, done: Status) -> Status {
+        match self {
+            LoginStep::Done(_) => done,
+            LoginStep::SecondFactor(_) => Status::Accepted,
+        }
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct Credentials {
     mail: String,
     pwd: String,
 }
 
+/// Checked against the LDAP directory first when there is one, `pwd` being sent as typed then.
+/// Otherwise it is hashed by the browser with the simple login salt.
+/// Users with an authenticator, or all of them when 2FA is required, then post a code to `/go/login/second_factor`
+#[utoipa::path(
+    tag = "login",
+    request_body = Credentials,
+    responses(
+        (status = 200, body = LoginSuccessfull, description = "`token` is the session to send along the next requests"),
+        (status = 202, body = SecondFactorRequired),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+    ),
+    security(()),
+)]
 #[post("/go/login", data = "<credentials>")]
-pub fn simple_login(
+pub async fn simple_login(
     credentials: Json<Credentials>,
+    client: SessionClient,
     sessions: &State<Sessions>,
     config: &State<AppConfig>,
+    ldap_service: &State<LdapService>,
 
     pool: &State<DbPool>,
-) -> Result<Json<LoginSuccessfull>, (Status, Value)> {
+) -> Result<(Status, Json<LoginStep>), (Status, Value)> {
     let credentials = credentials.into_inner();
     if !MAIL_REGEX.is_match(&credentials.mail) {
         return Err((Status::BadRequest, json!({"error": "Wrong mail format."})));
     }
+    simple_login_enabled(&mut pool.get().map_err(AppError::from)?)?;
 
-    let pwd = digest(format!("{}{}", credentials.pwd, config.simple_login_salt2));
+    let mut pwd = credentials.pwd;
+    if ldap_service.is_enabled() {
+        match ldap_service.authenticate(&credentials.mail, &pwd).await {
+            Ok(Some(ldap_user)) => {
+                let mut conn = pool.get().map_err(AppError::from)?;
+                ldap_user_logged_in(&credentials.mail, &ldap_user, &mut conn)?;
+                let step = pwd_checked(&credentials.mail, &client, sessions, &mut conn)?;
+                return Ok((step.status(Status::Ok), Json(step)));
+            }
+            Ok(None) => {}
+            Err(e) => error!("{e:?} => LDAP login of {} failed, checking its password", credentials.mail),
+        }
+        // Users outside of the directory keep their passwords, hashed like the browser does without LDAP
+        pwd = digest(format!("{pwd}{}", config.simple_login_salt1));
+    }
 
     let mut conn = pool.get().map_err(AppError::from)?;
-    let mail_pwd_match: i64 = users::table
-        .select(count(users::mail))
-        .filter(users::pwd.eq(&pwd))
-        .find(&credentials.mail)
-        .first(&mut conn)
-        .map_err(AppError::from)?;
+    let pwd_match = config.password_hashing.check(
+        &credentials.mail,
+        &pwd,
+        &config.simple_login_salt2,
+        &mut conn,
+    )?;
 
-    if mail_pwd_match != 1 {
+    if !pwd_match {
         return Err((
             Status::Unauthorized,
             json!({ "error": "Wrong credentials." }),
         ));
     };
 
-    let token = Uuid::new_v4();
-    sessions.put(&token.simple().to_string(), &credentials.mail);
+    let step = pwd_checked(&credentials.mail, &client, sessions, &mut conn)?;
+    Ok((step.status(Status::Ok), Json(step)))
+}
+
+/// Finish a login which returned `202` with a code of the authenticator, or one of the recovery codes
+#[utoipa::path(
+    tag = "login",
+    request_body = SecondFactorCode,
+    responses(
+        (status = 200, body = LoginSuccessfull, description = "With the recovery codes when it confirmed the enrollment"),
+        (status = 400, body = ErrorBody, description = "Unknown or expired login, or too many codes posted"),
+        (status = 401, body = ErrorBody, description = "Wrong code"),
+        (status = 429, body = ErrorBody, description = "Too many wrong codes for this user lately, whatever the login"),
+    ),
+    security(()),
+)]
+#[post("/go/login/second_factor", data = "<second_factor>")]
+pub fn second_factor_login(
+    second_factor: Json<SecondFactorCode>,
+    client: SessionClient,
+    sessions: &State<Sessions>,
+    pool: &State<DbPool>,
+) -> Result<Json<LoginSuccessfull>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    simple_login_enabled(&mut conn)?;
+
+    let (mail, recovery_codes) = match User::finish_second_factor(&second_factor, &mut conn) {
+        Err(AppError::BadRequest) => {
+            return Err((
+                Status::BadRequest,
+                json!({ "error": "This login expired, please log in again." }),
+            ))
+        }
+        Err(AppError::Unauthorized) => {
+            return Err((Status::Unauthorized, json!({ "error": "Wrong code." })))
+        }
+        result => result?,
+    };
+
+    let mut login = start_session(&mail, &client, sessions, &mut conn)?;
+    login.recovery_codes = recovery_codes.map(|codes| codes.recovery_codes).unwrap_or_default();
+    Ok(Json(login))
+}
+
+/// Register a simple login user, mailed a link to open within a day to confirm the mail is theirs.
+/// The user is only created then, and logs in like the others
+#[utoipa::path(
+    tag = "login",
+    request_body = Credentials,
+    responses(
+        (status = 202, description = "Confirmation link mailed"),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody, description = "Mail domain not allowed"),
+        (status = 409, body = ErrorBody, description = "Registration disabled or mail already used"),
+        (status = 429, body = ErrorBody, description = "Too many links mailed to this address"),
+    ),
+    security(()),
+)]
+#[post("/go/register", data = "<credentials>")]
+pub fn register(
+    credentials: Json<Credentials>,
+    mailer: &State<Mailer>,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let credentials = credentials.into_inner();
+    if !MAIL_REGEX.is_match(&credentials.mail) {
+        return Err((Status::BadRequest, json!({"error": "Wrong mail format."})));
+    }
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    simple_login_enabled(&mut conn)?;
+    let registration = registration(&mut conn)?;
+    User::register(
+        &credentials.mail,
+        &credentials.pwd,
+        &registration,
+        &config.password_hashing,
+        &config.public_url,
+        mailer,
+        &mut conn,
+    )?;
+
+    Ok(Status::Accepted)
+}
+
+/// Link mailed by the registration, the user then logs in with the password they registered
+#[allow(clippy::result_large_err)]
+#[get("/go/register/confirm?<token>")]
+pub fn confirm_registration(token: &str, pool: &State<DbPool>) -> Result<Redirect, (Status, Template)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    simple_login_enabled(&mut conn)?;
+    let registration = registration(&mut conn)?;
+
+    match User::confirm_registration(token, &registration, &mut conn) {
+        Ok(_) => Ok(Redirect::to("/go/login")),
+        Err(AppError::BadRequest) => Err(error_page(Status::BadRequest, "Invalid or expired confirmation link.")),
+        Err(AppError::Conflict) => Err(error_page(Status::Conflict, "This mail is already registered, log in instead.")),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Mail a reset link when the address is the one of a user, the response is the same otherwise
+#[utoipa::path(
+    tag = "login",
+    request_body = ForgottenPwd,
+    responses(
+        (status = 202),
+        (status = 400, body = ErrorBody),
+    ),
+    security(()),
+)]
+#[post("/go/password/forgot", data = "<forgotten>")]
+pub fn forgot_pwd(
+    forgotten: Json<ForgottenPwd>,
+    mailer: &State<Mailer>,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let forgotten = forgotten.into_inner();
+    if !MAIL_REGEX.is_match(&forgotten.mail) {
+        return Err((Status::BadRequest, json!({"error": "Wrong mail format."})));
+    }
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    simple_login_enabled(&mut conn)?;
+    if let Err(e) = User::send_pwd_reset(&forgotten.mail, &config.public_url, mailer, &mut conn) {
+        error!("Password reset of {} failed: {e:?}", forgotten.mail);
+    }
+
+    Ok(Status::Accepted)
+}
+
+/// The token is written in the page, anything else than one of ours is refused
+#[get("/go/password/reset?<token>")]
+pub fn reset_pwd_form(token: String, config: &State<AppConfig>) -> (Status, Template) {
+    if !is_pwd_reset_token(&token) {
+        return error_page(Status::BadRequest, "Invalid or expired reset link.");
+    }
 
-    Ok(Json(LoginSuccessfull {
-        token: token.simple().to_string(),
+    (
+        Status::Ok,
+        Template::render(
+            "reset",
+            json!({ "context": json!({
+                "simple_salt": config.simple_login_salt1,
+                "token": token,
+            }).to_string() }),
+        ),
+    )
+}
+
+/// Set a new password with the token of a mailed link, every session of the user is ended
+#[utoipa::path(
+    tag = "login",
+    request_body = PwdReset,
+    responses(
+        (status = 204),
+        (status = 400, body = ErrorBody, description = "Invalid, used or expired token"),
+    ),
+    security(()),
+)]
+#[post("/go/password/reset", data = "<reset>")]
+pub fn reset_pwd(
+    reset: Json<PwdReset>,
+    sessions: &State<Sessions>,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    simple_login_enabled(&mut conn)?;
+
+    let mail = match User::reset_pwd(reset.into_inner(), &config.password_hashing, &mut conn) {
+        Err(AppError::BadRequest) => {
+            return Err((
+                Status::BadRequest,
+                json!({ "error": "Invalid or expired reset link." }),
+            ))
+        }
+        result => result?,
+    };
+    sessions.end_all_of(&mail, None, &mut conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// Mail and password logins, with their registration and password resets, can be turned off in the features
+fn simple_login_enabled(conn: &mut DbConn) -> Result<(), AppError> {
+    if !features(conn)?.login.simple {
+        return Err(AppError::Disable);
+    }
+    Ok(())
+}
+
+/// Logged in unless a code of an authenticator is needed, enrolling one first when 2FA is required
+fn pwd_checked(
+    mail: &str,
+    client: &SessionClient,
+    sessions: &Sessions,
+    conn: &mut DbConn,
+) -> Result<LoginStep, AppError> {
+    let enrollment = if User::has_totp(mail, conn)? {
+        None
+    } else if second_factor_policy(conn)?.required {
+        Some(User::enroll_totp(mail, conn)?)
+    } else {
+        return Ok(LoginStep::Done(start_session(mail, client, sessions, conn)?));
+    };
+
+    Ok(LoginStep::SecondFactor(SecondFactorRequired {
+        token: User::start_second_factor(mail, conn)?,
+        enrollment,
     }))
 }
 
-#[get("/go/login/google")]
-pub async fn google_login(
+fn start_session(
+    mail: &str,
+    client: &SessionClient,
+    sessions: &Sessions,
+    conn: &mut DbConn,
+) -> Result<LoginSuccessfull, AppError> {
+    let token = Uuid::new_v4().simple().to_string();
+    sessions.put(&token, mail, client, conn)?;
+
+    Ok(LoginSuccessfull {
+        token,
+        pwd_is_temporary: User::has_temporary_pwd(mail, conn)?,
+        recovery_codes: vec![],
+    })
+}
+
+#[get("/go/login/<provider>")]
+pub async fn oidc_login(
+    provider: &str,
     user: Option<User>,
+    client: SessionClient,
     sessions: &State<Sessions>,
     oidc_service: &State<OidcService>,
     cookies: &CookieJar<'_>,
+    pool: &State<DbPool>,
 ) -> Result<Redirect, (Status, Template)> {
     if user.is_some() {
         return Err(AppError::BadRequest.into());
     }
+    let mut conn = pool.get().map_err(AppError::from)?;
+    if !features(&mut conn)?.login.google {
+        return Err(oidc_error(OidcServiceError::Disabled));
+    }
 
-    let (auth_url, nonce) = oidc_service.redirect().map_err(AppError::from)?;
+    let (auth_url, login) = oidc_service.redirect(provider).map_err(oidc_error)?;
+    let login = serde_json::to_string(&login).map_err(|_| AppError::ServiceError)?;
 
     let token = Uuid::new_v4();
     let token = token.simple().to_string();
-    sessions.put(&token, &nonce);
+    sessions.put(&token, &login, &client, &mut conn)?;
 
     cookies.add(
         Cookie::build((SESSION_COOKIE,token))
@@ -106,28 +438,51 @@ pub async fn google_login(
             .same_site(SameSite::Lax)
     );
 
-    Ok(Redirect::permanent(auth_url))
+    // Not cached by the browser, each login has its own state and PKCE challenge
+    Ok(Redirect::to(auth_url))
 }
 
+/// Callback of the provider, with either a `code` and the `state` given to it, or an `error`
 #[allow(clippy::too_many_arguments)]
-#[get("/go/login/redirect/google?<code>")]
-pub async fn login_redirect_google(
-    code: String,
-    nonce: NonceOIDC,
+#[get("/go/login/redirect/<provider>?<code>&<state>&<error>")]
+pub async fn login_redirect_oidc(
+    provider: &str,
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    login: PendingOidcLogin,
     session_id: SessionId,
+    client: SessionClient,
+    cookies: &CookieJar<'_>,
 
     oidc_service: &State<OidcService>,
     sessions: &State<Sessions>,
     pool: &State<DbPool>,
 ) -> Result<Redirect, (Status, Template)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    // A login can only be completed once, whatever the outcome
+    sessions.delete(&session_id.0, &mut conn)?;
+    if !features(&mut conn)?.login.google {
+        return Err(oidc_error(OidcServiceError::Disabled));
+    }
+
+    if let Some(error) = error {
+        return Err(error_page(
+            Status::Forbidden,
+            &format!("The identity provider refused the login: {error}."),
+        ));
+    }
+    let (Some(code), Some(state)) = (code, state) else {
+        return Err(oidc_error(OidcServiceError::InvalidState));
+    };
+
     let token_res = oidc_service
-        .retrieve_token(&code, &nonce.0)
+        .retrieve_token(provider, &code, &state, &login.0)
         .await
-        .map_err(AppError::from)?;
+        .map_err(oidc_error)?;
 
     let mail = token_res.mail;
 
-    let mut conn = pool.get().map_err(AppError::from)?;
     let matching_user: i64 = users::table
         .select(count(users::mail))
         .find(&mail)
@@ -137,10 +492,87 @@ pub async fn login_redirect_google(
     if matching_user == 0 {
         register_user(&mail, &mut conn)?;
     }
+    let rules = claim_rules(&mut conn)?;
+    User::apply_claim_rules(&mail, provider, &token_res.claims, &rules, &mut conn)?;
+
+    // A new session, the one of the login could have been planted in the browser
+    let session = start_session(&mail, &client, sessions, &mut conn)?;
+    cookies.add(
+        Cookie::build((SESSION_COOKIE, session.token))
+            .path("/")
+            .secure(true)
+            .same_site(SameSite::Lax),
+    );
+
+    Ok(Redirect::to("/"))
+}
+
+fn oidc_error(e: OidcServiceError) -> (Status, Template) {
+    match e {
+        OidcServiceError::Disabled => error_page(
+            Status::Conflict,
+            "Login with an identity provider is disabled, use your mail and password.",
+        ),
+        OidcServiceError::UnknownProvider(_) => error_page(Status::NotFound, "Unknown identity provider."),
+        OidcServiceError::InvalidState => error_page(
+            Status::BadRequest,
+            "This login doesn't match the one started from this browser, please log in again.",
+        ),
+        OidcServiceError::UnverifiedEmail => error_page(
+            Status::Forbidden,
+            "The identity provider didn't verify your mail, verify it there before logging in.",
+        ),
+        e => {
+            error!("{:?} => OIDC login failed", e);
+            error_page(
+                Status::BadGateway,
+                "The identity provider didn't let us log you in, please try again.",
+            )
+        }
+    }
+}
+
+fn error_page(status: Status, message: &str) -> (Status, Template) {
+    (status, Template::render("error", json!({ "message": message })))
+}
 
-    sessions.put(&session_id.0, &mail);
+/// End the session of the request, if any
+#[post("/go/logout")]
+pub fn logout(
+    session_id: Option<SessionId>,
+    sessions: &State<Sessions>,
+    cookies: &CookieJar<'_>,
+    pool: &State<DbPool>,
+) -> Result<Redirect, (Status, Value)> {
+    if let Some(session_id) = session_id {
+        let mut conn = pool.get().map_err(AppError::from)?;
+        sessions.delete(&session_id.0, &mut conn)?;
+    }
+
+    cookies.remove(Cookie::build(SESSION_COOKIE).path("/"));
+
+    Ok(Redirect::to("/go/login"))
+}
+
+/// Registered on their first login, then given what claim rules grant to their entry
+fn ldap_user_logged_in(mail: &str, ldap_user: &LdapUser, conn: &mut DbConn) -> Result<(), AppError> {
+    let matching_user: i64 = users::table
+        .select(count(users::mail))
+        .find(mail)
+        .first(conn)
+        .map_err(AppError::from)?;
+
+    if matching_user == 0 {
+        register_user(mail, conn)?;
+    }
 
-    Ok(Redirect::permanent("/".to_string()))
+    let claims = ldap_user
+        .attributes
+        .iter()
+        .map(|(name, values)| (name.clone(), json!(values)))
+        .collect();
+    let rules = claim_rules(conn)?;
+    User::apply_claim_rules(mail, LDAP_PROVIDER, &claims, &rules, conn)
 }
 
 fn register_user(mail: &str, conn: &mut DbConn) -> Result<(), AppError> {
@@ -1,14 +1,19 @@
 #![feature(let_chains)]
 #[macro_use]
 extern crate rocket;
+use chrono::TimeDelta;
 use log::warn;
-use openidconnect::{
-    core::{CoreClient, CoreProviderMetadata},
-    reqwest, ClientId, ClientSecret, IssuerUrl, RedirectUrl,
-};
-use std::env;
+use std::{env, str::FromStr};
 
-use go_web::{users::Sessions, server, services::oidc::OidcService, AppConfig};
+use go_web::{
+    users::{PasswordHashing, ProxyAuth, SessionExpiry, Sessions}, server,
+    services::{
+        ldap::{LdapConfig, LdapService},
+        mailer::Mailer,
+        oidc::{OidcProviderConfig, OidcService},
+    },
+    AppConfig, Services,
+};
 
 #[launch]
 async fn rocket() -> _ {
@@ -33,6 +38,35 @@ async fn rocket() -> _ {
     let simple_login_salt1 = env::var("SALT1").expect("expect env var SALT1");
     let simple_login_salt2 = env::var("SALT2").expect("expect env var SALT2");
 
+    let default_hashing = PasswordHashing::default();
+    let password_hashing = PasswordHashing {
+        memory_kib: number_var("ARGON2_MEMORY_KIB").unwrap_or(default_hashing.memory_kib),
+        iterations: number_var("ARGON2_ITERATIONS").unwrap_or(default_hashing.iterations),
+        parallelism: number_var("ARGON2_PARALLELISM").unwrap_or(default_hashing.parallelism),
+    };
+
+    let session_expiry = SessionExpiry {
+        idle: hours_var("SESSION_IDLE_HOURS").unwrap_or(SessionExpiry::default().idle),
+        absolute: hours_var("SESSION_MAX_AGE_HOURS").unwrap_or(SessionExpiry::default().absolute),
+    };
+
+    let public_url = env::var("PUBLIC_URL")
+        .or_else(|_| env::var("HOSTNAME").map(|hostname| format!("http://{hostname}")))
+        .unwrap_or_else(|_| format!("http://{addr}:{port}"));
+    let public_url = public_url.trim_end_matches('/').to_string();
+
+    let oidc_providers = oidc_providers();
+    if oidc_providers.is_empty() {
+        warn!("No OIDC provider configured, only the simple login is available");
+    }
+    let oidc_service = OidcService::discover(&oidc_providers, &public_url).await;
+
+    let proxy_auth = env::var("PROXY_AUTH_HEADER").ok().map(|header| {
+        let trusted_proxies = env::var("PROXY_AUTH_TRUSTED_PROXIES")
+            .expect("expect env var PROXY_AUTH_TRUSTED_PROXIES with PROXY_AUTH_HEADER");
+        ProxyAuth::new(&header, &trusted_proxies).expect("expect valid PROXY_AUTH_TRUSTED_PROXIES")
+    });
+
     let run_migrations = env::var("RUN_MIGRATIONS");
     let run_migrations = matches!(run_migrations, Ok(run_migrations) if run_migrations == "true");
 
@@ -42,17 +76,64 @@ async fn rocket() -> _ {
         port,
         &addr,
         &db_url,
-        Sessions::default(),
+        Sessions::postgres(session_expiry),
         AppConfig {
             simple_login_salt1,
             simple_login_salt2,
+            password_hashing,
+            public_url,
+            proxy_auth,
         },
         run_migrations,
         false,
-        google_oidc_client().await,
+        Services {
+            oidc: oidc_service,
+            mailer: mailer(),
+            ldap: ldap_service(),
+        },
     )
 }
 
+fn ldap_service() -> LdapService {
+    let Ok(url) = env::var("LDAP_URL") else {
+        return LdapService::default();
+    };
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("expect env var {name} with LDAP_URL"));
+
+    LdapService::new(LdapConfig {
+        url,
+        bind_dn: var("LDAP_BIND_DN"),
+        base_dn: var("LDAP_BASE_DN"),
+        user_filter: env::var("LDAP_USER_FILTER").unwrap_or_else(|_| "(mail={mail})".to_string()),
+    })
+}
+
+fn mailer() -> Mailer {
+    if let Ok(smtp_url) = env::var("SMTP_URL") {
+        let from = env::var("MAIL_FROM").expect("expect env var MAIL_FROM with SMTP_URL");
+        return Mailer::smtp(&smtp_url, &from).expect("expect valid SMTP_URL and MAIL_FROM");
+    }
+    if let Ok(path) = env::var("MAIL_FILE") {
+        return Mailer::file(path);
+    }
+
+    warn!("No SMTP_URL nor MAIL_FILE, mails are only logged");
+    Mailer::log()
+}
+
+fn number_var<T: FromStr>(name: &str) -> Option<T> {
+    let number = env::var(name).ok()?;
+    Some(
+        number
+            .parse()
+            .unwrap_or_else(|_| panic!("expect env var {name} to be a number")),
+    )
+}
+
+fn hours_var(name: &str) -> Option<TimeDelta> {
+    number_var(name).map(TimeDelta::hours)
+}
+
 fn logger() {
     fern::Dispatch::new()
         // Perform allocation-free log formatting
@@ -72,27 +153,46 @@ fn logger() {
         .unwrap();
 }
 
-async fn google_oidc_client() -> OidcService {
-    let client_id =
-        env::var("OAUTH_GOOGLE_CLIENT_ID").expect("expect env var OAUTH_GOOGLE_CLIENT_ID");
-    let client_secret =
-        env::var("OAUTH_GOOGLE_CLIENT_SECRET").expect("expect env var OAUTH_GOOGLE_CLIENT_SECRET");
-    let hostname = env::var("HOSTNAME").expect("expect env var SALT2");
-
-    let provider_metadata = CoreProviderMetadata::discover_async(
-        IssuerUrl::new("https://accounts.google.com".to_string()).unwrap(),
-        reqwest::async_http_client,
-    )
-    .await
-    .unwrap();
-    let client = CoreClient::from_provider_metadata(
-        provider_metadata,
-        ClientId::new(client_id),
-        Some(ClientSecret::new(client_secret)),
-    )
-    .set_redirect_uri(
-        RedirectUrl::new(format!("http://{hostname}/go/login/redirect/google")).unwrap(),
-    );
+/// `OIDC_PROVIDERS=keycloak,google` then `OIDC_KEYCLOAK_ISSUER_URL`, `OIDC_KEYCLOAK_CLIENT_ID`...
+/// for each of them, Google alone with `OAUTH_GOOGLE_CLIENT_ID` and `OAUTH_GOOGLE_CLIENT_SECRET` otherwise.
+/// None when neither is set
+fn oidc_providers() -> Vec<OidcProviderConfig> {
+    let Ok(ids) = env::var("OIDC_PROVIDERS") else {
+        let Ok(client_id) = env::var("OAUTH_GOOGLE_CLIENT_ID") else {
+            return vec![];
+        };
+        return vec![OidcProviderConfig {
+            id: "google".to_string(),
+            name: "Google".to_string(),
+            issuer_url: "https://accounts.google.com".to_string(),
+            client_id,
+            client_secret: env::var("OAUTH_GOOGLE_CLIENT_SECRET")
+                .expect("expect env var OAUTH_GOOGLE_CLIENT_SECRET"),
+            scopes: vec![],
+            trust_unverified_email: false,
+        }];
+    };
 
-    OidcService::new(client)
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let var = |name: &str| {
+                let name = format!("OIDC_{}_{name}", id.to_uppercase());
+                env::var(&name).unwrap_or_else(|_| panic!("expect env var {name}"))
+            };
+            OidcProviderConfig {
+                id: id.to_string(),
+                name: env::var(format!("OIDC_{}_NAME", id.to_uppercase())).unwrap_or_else(|_| id.to_string()),
+                issuer_url: var("ISSUER_URL"),
+                client_id: var("CLIENT_ID"),
+                client_secret: var("CLIENT_SECRET"),
+                scopes: env::var(format!("OIDC_{}_SCOPES", id.to_uppercase()))
+                    .map(|scopes| scopes.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default(),
+                trust_unverified_email: env::var(format!("OIDC_{}_TRUST_UNVERIFIED_EMAIL", id.to_uppercase()))
+                    .is_ok_and(|trust| trust == "true"),
+            }
+        })
+        .collect()
 }
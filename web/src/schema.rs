@@ -1,3 +1,56 @@
+table! {
+    pending_registrations (hash) {
+        hash -> Varchar,
+        mail -> Varchar,
+        pwd -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    pwd_resets (hash) {
+        hash -> Varchar,
+        user_mail -> Varchar,
+        created_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    recovery_codes (hash) {
+        hash -> Varchar,
+        user_mail -> Varchar,
+    }
+}
+
+table! {
+    second_factor_failures (id) {
+        id -> Int4,
+        user_mail -> Varchar,
+        failed_at -> Timestamp,
+    }
+}
+
+table! {
+    second_factor_logins (hash) {
+        hash -> Varchar,
+        user_mail -> Varchar,
+        created_at -> Timestamp,
+        attempts -> Int2,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Varchar,
+        value -> Varchar,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+    }
+}
+
 table! {
     settings (title) {
         title -> Text,
@@ -5,11 +58,54 @@ table! {
     }
 }
 
+table! {
+    shortcut_aliases (alias, team_slug) {
+        alias -> Varchar,
+        team_slug -> Varchar,
+        shortcut -> Varchar,
+    }
+}
+
+table! {
+    shortcut_hits (id) {
+        id -> Int4,
+        shortcut -> Varchar,
+        team_slug -> Varchar,
+        user_mail -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    shortcut_versions (id) {
+        id -> Int4,
+        shortcut -> Varchar,
+        team_slug -> Varchar,
+        version -> Int4,
+        action -> Varchar,
+        old_url -> Nullable<Varchar>,
+        new_url -> Nullable<Varchar>,
+        redirect -> Nullable<Varchar>,
+        description -> Nullable<Varchar>,
+        tags -> Array<Text>,
+        user_mail -> Varchar,
+        created_at -> Timestamp,
+        archived_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     shortcuts (shortcut, team_slug) {
         shortcut -> Varchar,
         team_slug -> Varchar,
         url -> Varchar,
+        redirect -> Nullable<Varchar>,
+        created_by -> Nullable<Varchar>,
+        created_at -> Nullable<Timestamp>,
+        updated_by -> Nullable<Varchar>,
+        updated_at -> Nullable<Timestamp>,
+        description -> Nullable<Varchar>,
+        tags -> Array<Text>,
     }
 }
 
@@ -22,11 +118,33 @@ table! {
     }
 }
 
+table! {
+    totps (user_mail) {
+        user_mail -> Varchar,
+        secret -> Varchar,
+        enabled -> Bool,
+        last_step -> Int8,
+    }
+}
+
+table! {
+    user_tokens (id) {
+        id -> Int4,
+        user_mail -> Varchar,
+        name -> Varchar,
+        hash -> Varchar,
+        scopes -> Array<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     users (mail) {
         mail -> Varchar,
         pwd -> Nullable<Varchar>,
         capabilities -> Array<Text>,
+        pwd_is_temporary -> Bool,
     }
 }
 
@@ -40,14 +158,32 @@ table! {
     }
 }
 
+joinable!(pwd_resets -> users (user_mail));
+joinable!(recovery_codes -> users (user_mail));
+joinable!(second_factor_failures -> users (user_mail));
+joinable!(second_factor_logins -> users (user_mail));
+joinable!(shortcut_aliases -> teams (team_slug));
 joinable!(shortcuts -> teams (team_slug));
+joinable!(totps -> users (user_mail));
+joinable!(user_tokens -> users (user_mail));
 joinable!(users_teams -> teams (team_slug));
 joinable!(users_teams -> users (user_mail));
 
 allow_tables_to_appear_in_same_query!(
+    pending_registrations,
+    pwd_resets,
+    recovery_codes,
+    second_factor_failures,
+    second_factor_logins,
+    sessions,
     settings,
+    shortcut_aliases,
+    shortcut_hits,
+    shortcut_versions,
     shortcuts,
     teams,
+    totps,
+    user_tokens,
     users,
     users_teams,
 );
@@ -0,0 +1,112 @@
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::{collections::HashMap, time::Duration};
+
+/// How claim rules name LDAP logins, the claims being the attributes of the user entry
+pub const LDAP_PROVIDER: &str = "ldap";
+
+/// Result code of a bind with a wrong DN or password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Where the simple login checks the credentials of users first.
+/// `{mail}` and `{user}`, the part of the mail before `@`, are replaced in `bind_dn` and `user_filter`
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    /// Like `ldaps://ldap.mail.com`
+    pub url: String,
+    /// Like `uid={user},ou=people,dc=mail,dc=com`, or `{mail}` for Active Directory
+    pub bind_dn: String,
+    /// Where the entry of the user is searched once bound
+    pub base_dn: String,
+    /// Like `(mail={mail})`, should find the entry of the bound user only
+    pub user_filter: String,
+}
+
+/// Disabled without config, simple login users only have passwords then
+#[derive(Default)]
+pub struct LdapService {
+    config: Option<LdapConfig>,
+}
+
+/// The entry of a user, its attributes by name like `memberOf` holding its groups
+#[derive(Debug, PartialEq, Clone)]
+pub struct LdapUser {
+    pub dn: String,
+    pub attributes: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LdapServiceError {
+    Disabled,
+    Connection(String),
+    Search(String),
+}
+
+impl From<ldap3::LdapError> for LdapServiceError {
+    fn from(e: ldap3::LdapError) -> Self {
+        LdapServiceError::Connection(e.to_string())
+    }
+}
+
+impl LdapService {
+    pub fn new(config: LdapConfig) -> Self {
+        Self { config: Some(config) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// Bind as the user then read its entry, `None` when the credentials are wrong
+    pub async fn authenticate(&self, mail: &str, pwd: &str) -> Result<Option<LdapUser>, LdapServiceError> {
+        let config = self.config.as_ref().ok_or(LdapServiceError::Disabled)?;
+        // Servers take binds without password as anonymous ones
+        if pwd.is_empty() {
+            return Ok(None);
+        }
+
+        let user = mail.split_once('@').map_or(mail, |(user, _)| user);
+        let bind_dn = config
+            .bind_dn
+            .replace("{mail}", &dn_escape(mail))
+            .replace("{user}", &dn_escape(user));
+        let filter = config
+            .user_filter
+            .replace("{mail}", &ldap_escape(mail))
+            .replace("{user}", &ldap_escape(user));
+
+        let settings = LdapConnSettings::new().set_conn_timeout(Duration::from_secs(5));
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &config.url).await?;
+        ldap3::drive!(conn);
+
+        let bind = ldap.simple_bind(&bind_dn, pwd).await?;
+        if bind.rc == INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        bind.success()?;
+
+        let (entries, _) = ldap
+            .search(&config.base_dn, Scope::Subtree, &filter, vec!["*", "memberOf"])
+            .await?
+            .success()
+            .map_err(|e| LdapServiceError::Search(e.to_string()))?;
+        if let Err(e) = ldap.unbind().await {
+            warn!("LDAP unbind failed: {e}");
+        }
+
+        let mut entries = entries.into_iter().map(SearchEntry::construct);
+        let (Some(entry), None) = (entries.next(), entries.next()) else {
+            error!("{filter} doesn't match one entry under {}", config.base_dn);
+            return Ok(None);
+        };
+        // When bound with a DN, the entry must be the one of the user, not anyone with that mail
+        if bind_dn.contains('=') && !entry.dn.eq_ignore_ascii_case(&bind_dn) {
+            error!("{filter} matches {} but {bind_dn} is bound", entry.dn);
+            return Ok(None);
+        }
+
+        Ok(Some(LdapUser {
+            dn: entry.dn,
+            attributes: entry.attrs,
+        }))
+    }
+}
@@ -1,10 +1,41 @@
-use crate::{errors::AppError, services::oidc::OidcServiceError};
+use crate::{
+    errors::AppError,
+    services::{ldap::LdapServiceError, mailer::MailerError, oidc::OidcServiceError},
+};
 
+pub mod ldap;
+pub mod mailer;
 pub mod oidc;
 
 impl From<OidcServiceError> for AppError {
     fn from(e: OidcServiceError) -> Self {
+        match e {
+            OidcServiceError::Disabled => AppError::Disable,
+            OidcServiceError::UnknownProvider(_) => AppError::NotFound,
+            OidcServiceError::InvalidState => AppError::BadRequest,
+            e => {
+                error!("{:?} => AppError::ServiceError", e);
+                AppError::ServiceError
+            }
+        }
+    }
+}
+
+impl From<MailerError> for AppError {
+    fn from(e: MailerError) -> Self {
         error!("{:?} => AppError::ServiceError", e);
         AppError::ServiceError
     }
 }
+
+impl From<LdapServiceError> for AppError {
+    fn from(e: LdapServiceError) -> Self {
+        match e {
+            LdapServiceError::Disabled => AppError::Disable,
+            e => {
+                error!("{:?} => AppError::ServiceError", e);
+                AppError::ServiceError
+            }
+        }
+    }
+}
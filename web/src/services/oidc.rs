@@ -1,33 +1,222 @@
 use openidconnect::{
-    core::{CoreClient, CoreIdTokenClaims, CoreIdTokenVerifier, CoreResponseType},
+    core::{
+        CoreAuthDisplay, CoreAuthPrompt, CoreErrorResponseType, CoreGenderClaim, CoreJsonWebKey,
+        CoreJsonWebKeyType, CoreJsonWebKeyUse, CoreJweContentEncryptionAlgorithm, CoreJwsSigningAlgorithm,
+        CoreProviderMetadata, CoreResponseType, CoreRevocableToken, CoreRevocationErrorResponse,
+        CoreTokenIntrospectionResponse, CoreTokenType,
+    },
     reqwest::async_http_client,
-    AuthenticationFlow, AuthorizationCode, CsrfToken, Nonce, OAuth2TokenResponse, Scope,
+    AdditionalClaims, AuthenticationFlow, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    EmptyExtraTokenFields, IdTokenClaims, IdTokenFields, IssuerUrl, Nonce, OAuth2TokenResponse,
+    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, StandardErrorResponse, StandardTokenResponse,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use subtle::ConstantTimeEq;
 
-#[cfg_attr(feature = "mock", faux::create)]
+/// The claims of ID tokens outside of the standard ones, like `groups` or `hd`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OtherClaims {
+    #[serde(flatten)]
+    pub claims: HashMap<String, Value>,
+}
+
+impl AdditionalClaims for OtherClaims {}
+
+/// A `CoreClient` keeping the non standard claims of ID tokens
+pub type OidcClient = openidconnect::Client<
+    OtherClaims,
+    CoreAuthDisplay,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreAuthPrompt,
+    StandardErrorResponse<CoreErrorResponseType>,
+    StandardTokenResponse<
+        IdTokenFields<
+            OtherClaims,
+            EmptyExtraTokenFields,
+            CoreGenderClaim,
+            CoreJweContentEncryptionAlgorithm,
+            CoreJwsSigningAlgorithm,
+            CoreJsonWebKeyType,
+        >,
+        CoreTokenType,
+    >,
+    CoreTokenType,
+    CoreTokenIntrospectionResponse,
+    CoreRevocableToken,
+    CoreRevocationErrorResponse,
+>;
+
+/// An identity provider users can log in with, at `/go/login/<id>`
+pub struct OidcProvider {
+    pub id: String,
+    /// Shown on its login button
+    pub name: String,
+    client: OidcClient,
+    /// Asked for besides `openid` and `email`
+    scopes: Vec<String>,
+    /// Log in with mails the provider didn't verify, only for the ones users can't set themselves
+    trust_unverified_email: bool,
+}
+
+/// Where to find a provider, its endpoints are discovered from `issuer_url`
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub id: String,
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Some providers only send claims like `groups` when asked for their scope
+    pub scopes: Vec<String>,
+    /// Accept ID tokens without `email_verified`, or with it `false`
+    pub trust_unverified_email: bool,
+}
+
+/// For the login buttons
+#[derive(Serialize, Debug, PartialEq)]
+pub struct OidcProviderLink {
+    pub id: String,
+    pub name: String,
+}
+
+/// The configured providers, keyed by their id
+#[derive(Default)]
 pub struct OidcService {
-    client: CoreClient,
+    providers: Vec<OidcProvider>,
+}
+
+/// Kept in the session between the redirection to the provider and its callback
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct OidcLogin {
+    /// Id of the provider the user was sent to, the only one whose callback can complete it
+    pub provider: String,
+    pub nonce: String,
+    /// CSRF token, sent back by the provider as `state`
+    pub state: String,
+    pub pkce_verifier: String,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum OidcServiceError {
     Ko,
+    /// No provider is configured
+    Disabled,
+    UnknownProvider(String),
+    Discovery(String),
+    /// The callback doesn't match the login started in this session
+    InvalidState,
+    MissingIdToken,
+    /// The provider didn't verify the mail, anyone could have set it
+    UnverifiedEmail,
 }
-// #[cfg_attr(test, mockable)]
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct TokenRes {
     pub mail: String,
+    /// Every claim of the ID token, standard ones included, by name
+    pub claims: HashMap<String, Value>,
 }
 
-#[cfg_attr(feature = "mock", faux::methods)]
 impl OidcService {
-    pub fn new(client: CoreClient) -> Self {
-        Self { client }
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `client` should redirect to `/go/login/redirect/<id>`
+    pub fn with_provider(self, id: &str, name: &str, client: OidcClient) -> Self {
+        self.with(OidcProvider {
+            id: id.to_string(),
+            name: name.to_string(),
+            client,
+            scopes: vec![],
+            trust_unverified_email: false,
+        })
+    }
+
+    fn with(mut self, provider: OidcProvider) -> Self {
+        self.providers.retain(|other| other.id != provider.id);
+        self.providers.push(provider);
+        self
     }
 
-    pub fn redirect(&self) -> Result<(String, String), OidcServiceError> {
-        let (auth_url, _csrf_token, nonce) = self
+    /// Fetch the metadata of each provider, `public_url` is where they redirect the users back.
+    /// The ones that can't be reached are left out, not to prevent the app from starting
+    pub async fn discover(configs: &[OidcProviderConfig], public_url: &str) -> Self {
+        let mut service = Self::new();
+
+        for config in configs {
+            match Self::client(config, public_url).await {
+                Ok(client) => {
+                    service = service.with(OidcProvider {
+                        id: config.id.clone(),
+                        name: config.name.clone(),
+                        client,
+                        scopes: config.scopes.clone(),
+                        trust_unverified_email: config.trust_unverified_email,
+                    })
+                }
+                Err(e) => error!("{:?}, login with {} is disabled", e, config.id),
+            }
+        }
+
+        service
+    }
+
+    async fn client(config: &OidcProviderConfig, public_url: &str) -> Result<OidcClient, OidcServiceError> {
+        let discovery_error = |e: String| OidcServiceError::Discovery(format!("{}: {e}", config.id));
+
+        let issuer_url = IssuerUrl::new(config.issuer_url.clone()).map_err(|e| discovery_error(e.to_string()))?;
+        let metadata = CoreProviderMetadata::discover_async(issuer_url, async_http_client)
+            .await
+            .map_err(|e| discovery_error(format!("{e:?}")))?;
+        let redirect_url = RedirectUrl::new(format!("{public_url}/go/login/redirect/{}", config.id))
+            .map_err(|e| discovery_error(e.to_string()))?;
+
+        Ok(OidcClient::from_provider_metadata(
+            metadata,
+            ClientId::new(config.client_id.clone()),
+            Some(ClientSecret::new(config.client_secret.clone())),
+        )
+        .set_redirect_uri(redirect_url))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.providers.is_empty()
+    }
+
+    pub fn providers(&self) -> Vec<OidcProviderLink> {
+        self.providers
+            .iter()
+            .map(|provider| OidcProviderLink {
+                id: provider.id.clone(),
+                name: provider.name.clone(),
+            })
+            .collect()
+    }
+
+    fn provider(&self, id: &str) -> Result<&OidcProvider, OidcServiceError> {
+        if !self.is_enabled() {
+            return Err(OidcServiceError::Disabled);
+        }
+
+        self.providers
+            .iter()
+            .find(|provider| provider.id == id)
+            .ok_or_else(|| OidcServiceError::UnknownProvider(id.to_string()))
+    }
+
+    /// Where to send the user, and what to keep to check the callback
+    pub fn redirect(&self, provider_id: &str) -> Result<(String, OidcLogin), OidcServiceError> {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let provider = self.provider(provider_id)?;
+        let mut request = provider
             .client
             .authorize_url(
                 AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
@@ -35,38 +224,55 @@ impl OidcService {
                 Nonce::new_random,
             )
             .add_scope(Scope::new("email".to_string()))
-            .url();
+            .set_pkce_challenge(pkce_challenge);
+        for scope in &provider.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (auth_url, state, nonce) = request.url();
 
-        Ok((auth_url.to_string(), nonce.secret().to_string()))
+        Ok((
+            auth_url.to_string(),
+            OidcLogin {
+                provider: provider.id.clone(),
+                nonce: nonce.secret().to_string(),
+                state: state.secret().to_string(),
+                pkce_verifier: pkce_verifier.secret().to_string(),
+            },
+        ))
     }
 
     pub async fn retrieve_token(
         &self,
+        provider_id: &str,
         code: &str,
-        nonce: &str,
+        state: &str,
+        login: &OidcLogin,
     ) -> Result<TokenRes, OidcServiceError> {
-        let token_response = self
+        let provider = self.provider(provider_id)?;
+        if login.provider != provider.id || !bool::from(state.as_bytes().ct_eq(login.state.as_bytes())) {
+            return Err(OidcServiceError::InvalidState);
+        }
+
+        let token_response = provider
             .client
             .exchange_code(AuthorizationCode::new(code.to_string()))
+            .set_pkce_verifier(PkceCodeVerifier::new(login.pkce_verifier.clone()))
             .request_async(async_http_client)
             .await
             .map_err(|e| {
-                error!("Fail getting token from google: {:?}", e);
+                error!("Fail getting token from {}: {:?}", provider.id, e);
                 OidcServiceError::Ko
             })?;
 
-        println!(
-            "Google returned access token:\n{}\n",
-            token_response.access_token().secret()
-        );
-        println!("Google returned scopes: {:?}", token_response.scopes());
-
-        let id_token_verifier: CoreIdTokenVerifier = self.client.id_token_verifier();
-        let id_token_claims: &CoreIdTokenClaims = token_response
-            .extra_fields()
-            .id_token()
-            .expect("Server did not return an ID token")
-            .claims(&id_token_verifier, &Nonce::new(nonce.to_string()))
+        debug!("{} returned scopes: {:?}", provider.id, token_response.scopes());
+
+        let id_token_verifier = provider.client.id_token_verifier();
+        let Some(id_token) = token_response.extra_fields().id_token() else {
+            error!("{} did not return an ID token", provider.id);
+            return Err(OidcServiceError::MissingIdToken);
+        };
+        let id_token_claims: &IdTokenClaims<OtherClaims, CoreGenderClaim> = id_token
+            .claims(&id_token_verifier, &Nonce::new(login.nonce.clone()))
             .map_err(|e| {
                 error!(
                     "Fail getting id_token from token_response: {:?} because {:?}",
@@ -75,17 +281,27 @@ impl OidcService {
                 OidcServiceError::Ko
             })?;
 
+        if id_token_claims.email_verified() != Some(true) && !provider.trust_unverified_email {
+            error!("{} did not verify the mail {:?}", provider.id, id_token_claims.email());
+            return Err(OidcServiceError::UnverifiedEmail);
+        }
+
         let mail = match id_token_claims.email() {
             Some(mail) => mail.to_string(),
             None => {
                 error!(
-                    "Google user do not have mail and it's weird... token_response: {:?}",
-                    token_response
+                    "{} user do not have mail... token_response: {:?}",
+                    provider.id, token_response
                 );
                 return Err(OidcServiceError::Ko);
             }
         };
 
-        Ok(TokenRes { mail })
+        let claims = match serde_json::to_value(id_token_claims) {
+            Ok(Value::Object(claims)) => claims.into_iter().collect(),
+            _ => HashMap::new(),
+        };
+
+        Ok(TokenRes { mail, claims })
     }
 }
@@ -3,17 +3,27 @@ use rocket_dyn_templates::Template;
 use serde_json::{json, Value};
 
 use crate::{
-    settings::{patch_features, PatchableFeatures},
-    users::{Capability, User},
+    settings::{
+        features, patch_features, put_claim_rules, put_default_redirect, put_registration, put_second_factor_policy,
+        Features, PatchableFeatures, Registration, SecondFactorPolicy,
+    },
+    shortcuts::RedirectMode,
+    users::{Capability, ClaimRule, User},
     errors::AppError,
     DbPool,
 };
 
 #[get("/go/settings")]
-pub fn get_settings(user: User) -> Result<Template, (Status, Template)> {
+pub fn get_settings(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Template)> {
     user.should_have_capability(Capability::Features)?;
 
-    Ok(Template::render("settings", json!({})))
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let features = features(&mut conn)?;
+
+    Ok(Template::render(
+        "settings",
+        json!({ "features_str": json!(features).to_string() }),
+    ))
 }
 
 #[patch("/go/settings", data = "<new_features>")]
@@ -21,11 +31,69 @@ pub fn patch_settings(
     new_features: Json<PatchableFeatures>,
     user: User,
     pool: &State<DbPool>,
+) -> Result<Json<Features>, (Status, Value)> {
+    user.should_have_capability(Capability::Features)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let features = patch_features(new_features.into_inner(), &mut conn)?;
+
+    Ok(Json(features))
+}
+
+#[put("/go/settings/default_redirect", data = "<redirect>")]
+pub fn put_settings_default_redirect(
+    redirect: Json<RedirectMode>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    user.should_have_capability(Capability::Features)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    put_default_redirect(redirect.into_inner(), &mut conn)?;
+
+    Ok(Status::Ok)
+}
+
+#[put("/go/settings/registration", data = "<registration>")]
+pub fn put_settings_registration(
+    registration: Json<Registration>,
+    user: User,
+    pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
     user.should_have_capability(Capability::Features)?;
 
     let mut conn = pool.get().map_err(AppError::from)?;
-    patch_features(new_features.into_inner(), &mut conn)?;
+    put_registration(registration.into_inner(), &mut conn)?;
+
+    Ok(Status::Ok)
+}
+
+/// Replace the rules granting capabilities and teams from the claims of identity providers
+#[put("/go/settings/claim_rules", data = "<claim_rules>")]
+pub fn put_settings_claim_rules(
+    claim_rules: Json<Vec<ClaimRule>>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    user.should_have_capability(Capability::Features)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    put_claim_rules(claim_rules.into_inner(), &mut conn)?;
+
+    Ok(Status::Ok)
+}
+
+/// Require password users to log in with a code of an authenticator too
+#[put("/go/settings/second_factor", data = "<policy>")]
+pub fn put_settings_second_factor(
+    policy: Json<SecondFactorPolicy>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    user.should_have_capability(Capability::UsersAdmin)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+    put_second_factor_policy(policy.into_inner(), &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -5,17 +5,65 @@ pub use controller::*;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use utoipa::ToSchema;
 
-use crate::users::Capability;
+use crate::users::{Capability, ClaimRule};
 use crate::errors::AppError;
+use crate::shortcuts::RedirectMode;
 use crate::schema::settings;
 use crate::DbConn;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
-pub struct FeaturesOld {}
+/// Switches of the instance, edited from `go/go/settings`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Features {
+    #[serde(default)]
+    pub login: LoginFeatures,
+    #[serde(default = "enabled")]
+    pub teams: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct LoginFeatures {
+    /// Mail and password logins, with their registration and password resets
+    #[serde(default = "enabled")]
+    pub simple: bool,
+    /// Logins with the OpenID Connect providers, Google being the first one supported
+    #[serde(default = "enabled")]
+    pub google: bool,
+    /// Browsing and resolving shortcuts takes a login, otherwise the ones of public teams are readable by anyone
+    #[serde(default = "enabled")]
+    pub read_private: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            login: LoginFeatures::default(),
+            teams: true,
+        }
+    }
+}
+
+impl Default for LoginFeatures {
+    fn default() -> Self {
+        LoginFeatures {
+            simple: true,
+            google: true,
+            read_private: true,
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
 
 pub const DEFAULT_CAPABILITIES: &str = "default_capabilities";
 pub const FEATURES: &str = "features";
+pub const DEFAULT_REDIRECT: &str = "default_redirect";
+pub const REGISTRATION: &str = "registration";
+pub const CLAIM_RULES: &str = "claim_rules";
+pub const SECOND_FACTOR: &str = "second_factor";
 
 #[derive(AsChangeset, Queryable, Identifiable, Debug)]
 #[diesel(table_name = settings, primary_key(title))]
@@ -25,7 +73,7 @@ pub struct Setting {
 }
 
 
-pub fn get_global_features(conn: &mut DbConn) -> Result<FeaturesOld, AppError> {
+pub fn features(conn: &mut DbConn) -> Result<Features, AppError> {
     let features: Setting = settings::table
         .find(FEATURES)
         .first(conn)
@@ -52,13 +100,141 @@ pub fn default_capabilities(conn: &mut DbConn) -> Result<Vec<Capability>, AppErr
     })
 }
 
-pub fn patch_features(_new_features: PatchableFeatures, conn: &mut DbConn) -> Result<usize, AppError> {
-    let features = get_global_features(conn)?;
+pub fn default_redirect(conn: &mut DbConn) -> Result<RedirectMode, AppError> {
+    let default_redirect: Setting = settings::table
+        .find(DEFAULT_REDIRECT)
+        .first(conn)
+        .map_err(AppError::from)?;
+
+    serde_json::from_str(&default_redirect.content).map_err(|e| {
+        error!(
+            "Can't parse default_redirect {:?} : {}",
+            default_redirect, e
+        );
+        AppError::Db
+    })
+}
+
+pub fn put_default_redirect(redirect: RedirectMode, conn: &mut DbConn) -> Result<usize, AppError> {
+    diesel::update(settings::table)
+        .set(settings::content.eq(json!(redirect).to_string()))
+        .filter(settings::title.eq(DEFAULT_REDIRECT))
+        .execute(conn)
+        .map_err(AppError::from)
+}
+
+/// Team routes answer `404` once teams are turned off, only the global team is left then
+pub fn teams_enabled(conn: &mut DbConn) -> Result<(), AppError> {
+    if !features(conn)?.teams {
+        return Err(AppError::NotFound);
+    }
+    Ok(())
+}
+
+pub fn patch_features(new_features: PatchableFeatures, conn: &mut DbConn) -> Result<Features, AppError> {
+    let mut features = features(conn)?;
+    features.patch(new_features);
 
     diesel::update(settings::table)
         .set(settings::content.eq(json!(features).to_string()))
         .filter(settings::title.eq(FEATURES))
         .execute(conn)
+        .map_err(AppError::from)?;
+
+    Ok(features)
+}
+
+/// Self-service registration of simple login users
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Registration {
+    pub enabled: bool,
+    /// Mail domains allowed to register, any when empty
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+impl Registration {
+    pub fn allows(&self, mail: &str) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+        let domain = mail.rsplit_once('@').map(|(_, domain)| domain.to_lowercase());
+        domain.is_some_and(|domain| self.allowed_domains.contains(&domain))
+    }
+}
+
+pub fn registration(conn: &mut DbConn) -> Result<Registration, AppError> {
+    let registration: Setting = settings::table
+        .find(REGISTRATION)
+        .first(conn)
+        .map_err(AppError::from)?;
+
+    serde_json::from_str(&registration.content).map_err(|e| {
+        error!("Can't parse registration {:?} : {}", registration, e);
+        AppError::Db
+    })
+}
+
+pub fn put_registration(mut registration: Registration, conn: &mut DbConn) -> Result<usize, AppError> {
+    registration.allowed_domains = registration
+        .allowed_domains
+        .iter()
+        .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect();
+
+    diesel::update(settings::table)
+        .set(settings::content.eq(json!(registration).to_string()))
+        .filter(settings::title.eq(REGISTRATION))
+        .execute(conn)
+        .map_err(AppError::from)
+}
+
+/// Applied on every login with an identity provider
+pub fn claim_rules(conn: &mut DbConn) -> Result<Vec<ClaimRule>, AppError> {
+    let claim_rules: Setting = settings::table
+        .find(CLAIM_RULES)
+        .first(conn)
+        .map_err(AppError::from)?;
+
+    serde_json::from_str(&claim_rules.content).map_err(|e| {
+        error!("Can't parse claim_rules {:?} : {}", claim_rules, e);
+        AppError::Db
+    })
+}
+
+pub fn put_claim_rules(claim_rules: Vec<ClaimRule>, conn: &mut DbConn) -> Result<usize, AppError> {
+    diesel::update(settings::table)
+        .set(settings::content.eq(json!(claim_rules).to_string()))
+        .filter(settings::title.eq(CLAIM_RULES))
+        .execute(conn)
+        .map_err(AppError::from)
+}
+
+/// Whether password users must log in with a code of an authenticator too
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Default, Debug)]
+pub struct SecondFactorPolicy {
+    /// Users without authenticator enroll one on their next login
+    pub required: bool,
+}
+
+pub fn second_factor_policy(conn: &mut DbConn) -> Result<SecondFactorPolicy, AppError> {
+    let policy: Setting = settings::table
+        .find(SECOND_FACTOR)
+        .first(conn)
+        .map_err(AppError::from)?;
+
+    serde_json::from_str(&policy.content).map_err(|e| {
+        error!("Can't parse second_factor {:?} : {}", policy, e);
+        AppError::Db
+    })
+}
+
+pub fn put_second_factor_policy(policy: SecondFactorPolicy, conn: &mut DbConn) -> Result<usize, AppError> {
+    diesel::update(settings::table)
+        .set(settings::content.eq(json!(policy).to_string()))
+        .filter(settings::title.eq(SECOND_FACTOR))
+        .execute(conn)
         .map_err(AppError::from)
 }
 
@@ -73,5 +249,16 @@ pub struct PatchableLoginFeature {
     pub simple: Option<bool>,
     pub google: Option<bool>,
     pub read_private: Option<bool>,
-    pub write_private: Option<bool>,
+}
+
+impl Features {
+    /// Only the flags sent are changed
+    pub fn patch(&mut self, patch: PatchableFeatures) {
+        if let Some(login) = patch.login {
+            self.login.simple = login.simple.unwrap_or(self.login.simple);
+            self.login.google = login.google.unwrap_or(self.login.google);
+            self.login.read_private = login.read_private.unwrap_or(self.login.read_private);
+        }
+        self.teams = patch.teams.unwrap_or(self.teams);
+    }
 }
@@ -0,0 +1,110 @@
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::errors::AppError;
+use crate::schema::{shortcut_aliases, shortcuts};
+use crate::shortcuts::Shortcut;
+use crate::DbConn;
+
+/// Alternate name of a shortcut of the same team, resolved by [`Shortcut::first`]
+#[derive(Queryable, Insertable, Serialize, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = shortcut_aliases)]
+pub struct ShortcutAlias {
+    pub alias: String,
+    pub team_slug: String,
+    pub shortcut: String,
+}
+
+impl ShortcutAlias {
+    /// Aliases of any of `shortcuts`
+    pub fn of(shortcuts: &[Shortcut], conn: &mut DbConn) -> Result<Vec<ShortcutAlias>, AppError> {
+        let names: Vec<&str> = shortcuts.iter().map(|s| s.shortcut.as_str()).collect();
+
+        Ok(db::of(&names, conn)?
+            .into_iter()
+            .filter(|alias| {
+                shortcuts
+                    .iter()
+                    .any(|s| s.shortcut == alias.shortcut && s.team_slug == alias.team_slug)
+            })
+            .collect())
+    }
+
+    /// Whether `name` is already an alias in the team, so it can't be used as a shortcut name
+    pub(super) fn exists(name: &str, team_slug_name: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        Ok(db::find(name, team_slug_name, conn)?.is_some())
+    }
+
+    /// Replace the aliases of `canonical`, conflicts with names already used in its team
+    /// by another shortcut or as an alias of another shortcut
+    pub(super) fn replace(canonical: &Shortcut, names: Vec<String>, conn: &mut DbConn) -> Result<(), AppError> {
+        let mut names: Vec<String> = names
+            .into_iter()
+            .map(|name| name.trim().trim_matches('/').to_string())
+            .filter(|name| !name.is_empty() && name != &canonical.shortcut)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        for name in &names {
+            let of_another = db::find(name, &canonical.team_slug, conn)?
+                .is_some_and(|existing| existing.shortcut != canonical.shortcut);
+            if of_another || db::is_shortcut(name, &canonical.team_slug, conn)? {
+                return Err(AppError::Conflict);
+            }
+        }
+
+        db::delete_of(canonical, conn)?;
+        db::insert(
+            &names
+                .into_iter()
+                .map(|name| ShortcutAlias {
+                    alias: name,
+                    team_slug: canonical.team_slug.clone(),
+                    shortcut: canonical.shortcut.clone(),
+                })
+                .collect::<Vec<_>>(),
+            conn,
+        )?;
+        Ok(())
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn of(names: &[&str], conn: &mut DbConn) -> Result<Vec<ShortcutAlias>, diesel::result::Error> {
+        shortcut_aliases::table
+            .filter(shortcut_aliases::shortcut.eq_any(names))
+            .order(shortcut_aliases::alias.asc())
+            .load::<ShortcutAlias>(conn)
+    }
+
+    pub fn find(
+        name: &str,
+        team_slug_name: &str,
+        conn: &mut DbConn,
+    ) -> Result<Option<ShortcutAlias>, diesel::result::Error> {
+        shortcut_aliases::table
+            .find((name, team_slug_name))
+            .first::<ShortcutAlias>(conn)
+            .optional()
+    }
+
+    pub fn is_shortcut(name: &str, team_slug_name: &str, conn: &mut DbConn) -> Result<bool, diesel::result::Error> {
+        diesel::select(diesel::dsl::exists(shortcuts::table.find((name, team_slug_name)))).get_result(conn)
+    }
+
+    pub fn delete_of(canonical: &Shortcut, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(shortcut_aliases::table)
+            .filter(shortcut_aliases::shortcut.eq(&canonical.shortcut))
+            .filter(shortcut_aliases::team_slug.eq(&canonical.team_slug))
+            .execute(conn)
+    }
+
+    pub fn insert(aliases: &[ShortcutAlias], conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(shortcut_aliases::table)
+            .values(aliases)
+            .execute(conn)
+    }
+}
@@ -1,34 +1,38 @@
-use lazy_static::lazy_static;
 use log::error;
-use regex::Regex;
 use rocket::serde::{json::Json, Deserialize};
 use rocket::{http::Status, response::Redirect, State};
 use rocket_dyn_templates::Template;
 use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
 
+use crate::settings::default_redirect;
 use crate::shortcuts::{
-    NewShortcut, Shortcut
+    is_web_url, Hits, NewShortcut, RedirectMode, Shortcut, ShortcutAlias, ShortcutFilter, ShortcutOrder,
+    ShortcutStats, ShortcutVersion,
 };
 use crate::teams::{
       Team,
 };
-use crate::users::User;
+use crate::users::{Reader, User};
 use crate::errors::AppError;
 use crate::views::IndexContext;
 use crate::{DbPool};
 
-lazy_static! {
-    static ref URL_REGEX: Regex =
-        Regex::new(r#"https?://(www\.)?[-a-zA-Z0-9()@:%_\+.~#?&//=]{1,256}"#,).unwrap();
-}
-
-#[get("/")]
-pub fn index(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Template)> {
+#[get("/?<sort>&<filter..>")]
+pub fn index(
+    sort: Option<ShortcutOrder>,
+    filter: ShortcutFilter,
+    reader: Reader,
+    pool: &State<DbPool>,
+) -> Result<Template, (Status, Template)> {
+    let Reader(user) = reader;
     let mut conn = pool.get().map_err(AppError::from)?;
 
     let teams = Team::all_with_shortcut_write(&user, &mut conn)?;
-    let shortcuts = Shortcut::sorted(&user, &mut conn)?;
+    let shortcuts = Shortcut::sorted(&user, sort.unwrap_or_default(), &filter, &mut conn)?;
+    let stats = ShortcutStats::of(&shortcuts, &mut conn)?;
+    let aliases = ShortcutAlias::of(&shortcuts, &mut conn)?;
 
     Ok(Template::render(
         "index",
@@ -40,6 +44,9 @@ pub fn index(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Temp
                  user,
                 team: None,
                 teams: teams,
+                stats,
+                aliases,
+                suggestions: vec![],
             }).to_string()
         }),
     ))
@@ -59,10 +66,12 @@ pub enum ShortcutRes {
 pub fn get_shortcut(
     shortcut: PathBuf,
     no_redirect: Option<bool>,
-    user: User,
+    reader: Reader,
 
+    hits: &State<Hits>,
     pool: &State<DbPool>,
 ) -> Result<ShortcutRes, (Status, Template)> {
+    let Reader(user) = reader;
     let shortcut = parse_shortcut_path_buff(&shortcut)?;
 
     let mut conn = pool.get().map_err(AppError::from)?;
@@ -72,49 +81,137 @@ pub fn get_shortcut(
     let admin_teams = Team::all_with_shortcut_write(&user, &mut conn)?;
 
     Ok(match shortcut_found {
-        Some(shortcut_found) => {
+        Some((shortcut_found, matched)) => {
             if let Some(true) = no_redirect {
+                let aliases = ShortcutAlias::of(std::slice::from_ref(&shortcut_found), &mut conn)?;
                 ShortcutRes::Ok(Template::render(
                     "index",
                     json!({
                         "mail": &user.mail,
                         "context": json!(IndexContext {
                             shortcut: Some(shortcut_found),
-                            shortcuts:  Shortcut::sorted(&user, &mut conn)?,
+                            shortcuts:  Shortcut::sorted(&user, ShortcutOrder::Name, &ShortcutFilter::default(), &mut conn)?,
                             user,
                             team: None,
                             teams: admin_teams,
+                            stats: vec![],
+                            aliases,
+                            suggestions: vec![],
                         }).to_string()
                     }),
                 ))
             } else {
-                ShortcutRes::Redirect(Redirect::permanent(shortcut_found.url))
+                hits.record(&shortcut_found, &user);
+
+                let target = shortcut_found.target(&shortcut[matched.len()..]);
+                let mode = match shortcut_found.redirect {
+                    Some(mode) => mode,
+                    None => default_redirect(&mut conn)?,
+                };
+
+                match mode {
+                    RedirectMode::Found => ShortcutRes::Redirect(Redirect::found(target)),
+                    RedirectMode::Temporary => ShortcutRes::Redirect(Redirect::temporary(target)),
+                    RedirectMode::Moved => ShortcutRes::Redirect(Redirect::moved(target)),
+                    RedirectMode::Permanent => ShortcutRes::Redirect(Redirect::permanent(target)),
+                    // Linked from the page, where a `javascript:` URL would run once clicked
+                    RedirectMode::Interstitial if !is_web_url(&target) => return Err(AppError::BadRequest.into()),
+                    RedirectMode::Interstitial => ShortcutRes::Ok(Template::render(
+                        "redirect",
+                        json!({
+                            "mail": &user.mail,
+                            "shortcut": shortcut_found.shortcut,
+                            "url": target,
+                        }),
+                    )),
+                }
             }
         }
-        None => ShortcutRes::NotFound(Template::render(
-            "index",
-            json!({
-                "mail":&user.mail,
-                "not_found": true,
-                "context": json!(IndexContext {
-                    shortcut: Some(Shortcut {
-                        shortcut: shortcut.to_string(),
-                        team_slug:"".to_string(),
-                        url:"".to_string()
-                    }),
-                    shortcuts: Shortcut::sorted(&user, &mut conn)?,
-                    user,
-                    team: None,
-                    teams: admin_teams,
-                }).to_string()
-            }),
-        )),
+        None => {
+            let suggestions = Shortcut::suggestions(shortcut, &user, &mut conn)?;
+
+            ShortcutRes::NotFound(Template::render(
+                "index",
+                json!({
+                    "mail":&user.mail,
+                    "not_found": true,
+                    "context": json!(IndexContext {
+                        shortcut: Some(Shortcut {
+                            shortcut: shortcut.to_string(),
+                            team_slug:"".to_string(),
+                            url:"".to_string(),
+                            redirect: None,
+                            created_by: None,
+                            created_at: None,
+                            updated_by: None,
+                            updated_at: None,
+                            description: None,
+                            tags: vec![],
+                        }),
+                        shortcuts: Shortcut::sorted(&user, ShortcutOrder::Name, &ShortcutFilter::default(), &mut conn)?,
+                        user,
+                        team: None,
+                        teams: admin_teams,
+                        stats: vec![],
+                        aliases: vec![],
+                        suggestions,
+                    }).to_string()
+                }),
+            ))
+        }
     })
 }
 
-#[derive(Deserialize)]
+#[get("/go/shortcuts/stats?<sort>&<filter..>")]
+pub fn get_shortcuts_stats(
+    sort: Option<ShortcutOrder>,
+    filter: ShortcutFilter,
+    user: User,
+    hits: &State<Hits>,
+    pool: &State<DbPool>,
+) -> Result<Json<Vec<ShortcutStats>>, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    hits.flush(&mut conn)?;
+
+    let shortcuts = Shortcut::sorted(&user, sort.unwrap_or_default(), &filter, &mut conn)?;
+
+    Ok(Json(ShortcutStats::of(&shortcuts, &mut conn)?))
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct Url {
     url: String,
+    redirect: Option<RedirectMode>,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Replace the current aliases if any
+    aliases: Option<Vec<String>>,
+}
+
+impl Url {
+    pub fn check(&self) -> Result<(), (Status, Value)> {
+        if !is_web_url(&self.url) {
+            return Err((Status::BadRequest, json!({"error": "Wrong URL format."})));
+        }
+        Ok(())
+    }
+
+    /// The shortcut to save along with its aliases
+    pub fn into_new_shortcut(self, shortcut_name: &str, team_slug_name: String) -> (NewShortcut, Option<Vec<String>>) {
+        (
+            NewShortcut {
+                shortcut: shortcut_name.to_string(),
+                url: self.url,
+                team_slug: team_slug_name,
+                redirect: self.redirect,
+                description: self.description.filter(|d| !d.trim().is_empty()),
+                tags: self.tags,
+            },
+            self.aliases,
+        )
+    }
 }
 
 #[put("/<shortcut..>?<team>", data = "<data>")]
@@ -127,25 +224,18 @@ pub fn put_shortcut(
 ) -> Result<Status, (Status, Value)> {
     let shortcut = parse_shortcut_path_buff(&shortcut)?;
 
-    let data = data.into_inner();
-    let url = data.url;
-    if !URL_REGEX.is_match(&url) {
-        return Err((Status::BadRequest, json!({"error": "Wrong URL format."})));
-    }
-
     let team_slug_name = if let Some(team) = team {
         team
     } else {
         "".to_string()
     };
 
+    data.check()?;
+    let (new_shortcut, aliases) = data.into_inner().into_new_shortcut(shortcut, team_slug_name);
+
     let mut conn = pool.get().map_err(AppError::from)?;
 
-   Shortcut::upsert(
-        NewShortcut { shortcut: shortcut.to_string(), url: url, team_slug: team_slug_name },
-        &user,
-        &mut conn,
-    )?;
+    Shortcut::upsert(new_shortcut, aliases, &user, &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -179,7 +269,40 @@ pub fn delete_shortcut(
     ))
 }
 
-fn parse_shortcut_path_buff(shortcut: &'_ Path) -> Result<&'_ str, AppError> {
+#[get("/go/shortcuts/history/<shortcut..>?<team>")]
+pub fn get_shortcut_history(
+    shortcut: PathBuf,
+    team: Option<String>,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Json<Vec<ShortcutVersion>>, (Status, Value)> {
+    let shortcut = parse_shortcut_path_buff(&shortcut)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    let versions = ShortcutVersion::of(shortcut, &team.unwrap_or_default(), &user, &mut conn)?;
+
+    Ok(Json(versions))
+}
+
+#[post("/go/shortcuts/revert/<shortcut..>?<team>&<version>")]
+pub fn revert_shortcut(
+    shortcut: PathBuf,
+    team: Option<String>,
+    version: i32,
+    user: User,
+    pool: &State<DbPool>,
+) -> Result<Status, (Status, Value)> {
+    let shortcut = parse_shortcut_path_buff(&shortcut)?;
+
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    ShortcutVersion::revert(shortcut, &team.unwrap_or_default(), version, &user, &mut conn)?;
+
+    Ok(Status::Ok)
+}
+
+pub(crate) fn parse_shortcut_path_buff(shortcut: &'_ Path) -> Result<&'_ str, AppError> {
     match shortcut.to_str() {
         Some(shortcut) => Ok(shortcut),
         None => {
@@ -0,0 +1,216 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    dsl::{count_star, max},
+    prelude::*,
+};
+use rocket::tokio::{task::spawn_blocking, time::interval};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::errors::AppError;
+use crate::schema::shortcut_hits;
+use crate::shortcuts::Shortcut;
+use crate::users::User;
+use crate::{DbConn, DbPool};
+
+const FLUSH_PERIOD: Duration = Duration::from_secs(5);
+const INSERT_CHUNK: usize = 1000;
+/// Hits kept while they can't be written, the oldest ones are dropped beyond
+const MAX_PENDING: usize = 100_000;
+
+/// Shortcut uses not yet written, recording is only a push so it doesn't slow down the redirect
+#[derive(Clone)]
+pub struct Hits {
+    pending: Arc<Mutex<Pending>>,
+    max_pending: usize,
+}
+
+#[derive(Default)]
+struct Pending {
+    hits: VecDeque<NewHit>,
+    /// Since the last flush, for being over `max_pending`
+    dropped: usize,
+}
+
+impl Default for Hits {
+    fn default() -> Self {
+        Self::with_max_pending(MAX_PENDING)
+    }
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[diesel(table_name = shortcut_hits)]
+pub struct NewHit {
+    pub shortcut: String,
+    pub team_slug: String,
+    pub user_mail: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Serialize, PartialEq, Eq, Debug)]
+pub struct ShortcutStats {
+    pub shortcut: String,
+    pub team_slug: String,
+    pub hits: i64,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+impl Hits {
+    pub fn with_max_pending(max_pending: usize) -> Self {
+        Self {
+            pending: Arc::default(),
+            max_pending,
+        }
+    }
+
+    /// Anonymous uses are counted without a user
+    pub fn record(&self, shortcut: &Shortcut, user: &User) {
+        let mut pending = self.lock();
+        pending.hits.push_back(NewHit {
+            shortcut: shortcut.shortcut.clone(),
+            team_slug: shortcut.team_slug.clone(),
+            user_mail: (!user.is_anonymous()).then(|| user.mail.clone()),
+            created_at: Utc::now().naive_utc(),
+        });
+        pending.drop_oldest(self.max_pending);
+    }
+
+    /// Hits that couldn't be written are kept for the next flush, up to `max_pending`
+    pub fn flush(&self, conn: &mut DbConn) -> Result<usize, AppError> {
+        let (hits, dropped) = {
+            let mut pending = self.lock();
+            (Vec::from(std::mem::take(&mut pending.hits)), std::mem::take(&mut pending.dropped))
+        };
+        if dropped > 0 {
+            error!("Dropped {dropped} shortcut hits, over the {} waiting to be written", self.max_pending);
+        }
+
+        let mut inserted = 0;
+        while inserted < hits.len() {
+            let chunk = &hits[inserted..hits.len().min(inserted + INSERT_CHUNK)];
+            if let Err(e) = db::insert(chunk, conn) {
+                let mut pending = self.lock();
+                let recorded_since = std::mem::take(&mut pending.hits);
+                pending.hits = hits.into_iter().skip(inserted).chain(recorded_since).collect();
+                pending.drop_oldest(self.max_pending);
+                error!("Keeping {} shortcut hits for the next flush", pending.hits.len());
+                return Err(e.into());
+            }
+            inserted += chunk.len();
+        }
+        Ok(inserted)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pending> {
+        match self.pending.lock() {
+            Ok(p) => p,
+            Err(p) => p.into_inner(),
+        }
+    }
+
+    /// Write pending hits every [`FLUSH_PERIOD`] in the background
+    pub fn flush_periodically(&self, pool: DbPool) {
+        let hits = self.clone();
+        rocket::tokio::spawn(async move {
+            let mut period = interval(FLUSH_PERIOD);
+            loop {
+                period.tick().await;
+                let hits = hits.clone();
+                let pool = pool.clone();
+                let _ = spawn_blocking(move || hits.flush_with(&pool)).await;
+            }
+        });
+    }
+
+    pub fn flush_with(&self, pool: &DbPool) {
+        let flushed = pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|mut conn| self.flush(&mut conn));
+        if let Err(e) = flushed {
+            error!("Shortcut hits flush failed: {:?}", e);
+        }
+    }
+}
+
+impl Pending {
+    fn drop_oldest(&mut self, max: usize) {
+        while self.hits.len() > max {
+            self.hits.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+impl ShortcutStats {
+    /// Stats of each of `shortcuts` in the same order, unused ones included
+    pub fn of(shortcuts: &[Shortcut], conn: &mut DbConn) -> Result<Vec<ShortcutStats>, AppError> {
+        let names: Vec<&str> = shortcuts.iter().map(|s| s.shortcut.as_str()).collect();
+        let mut slugs: Vec<&str> = shortcuts.iter().map(|s| s.team_slug.as_str()).collect();
+        slugs.sort_unstable();
+        slugs.dedup();
+
+        let mut counted: HashMap<(String, String), ShortcutStats> = db::stats(&names, &slugs, conn)
+            .map_err(AppError::from)?
+            .into_iter()
+            .map(|stats| ((stats.shortcut.clone(), stats.team_slug.clone()), stats))
+            .collect();
+
+        Ok(shortcuts
+            .iter()
+            .map(|s| {
+                counted
+                    .remove(&(s.shortcut.clone(), s.team_slug.clone()))
+                    .unwrap_or(ShortcutStats {
+                        shortcut: s.shortcut.clone(),
+                        team_slug: s.team_slug.clone(),
+                        hits: 0,
+                        last_used_at: None,
+                    })
+            })
+            .collect())
+    }
+
+    /// Most used first, keeps the current order between equally used ones
+    pub fn sort_by_popularity(shortcuts: Vec<Shortcut>, conn: &mut DbConn) -> Result<Vec<Shortcut>, AppError> {
+        let stats = ShortcutStats::of(&shortcuts, conn)?;
+
+        let mut with_stats: Vec<(Shortcut, ShortcutStats)> = shortcuts.into_iter().zip(stats).collect();
+        with_stats.sort_by_key(|(_, stats)| Reverse(stats.hits));
+
+        Ok(with_stats.into_iter().map(|(shortcut, _)| shortcut).collect())
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn insert(hits: &[NewHit], conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(shortcut_hits::table)
+            .values(hits)
+            .execute(conn)
+    }
+
+    pub fn stats(
+        names: &[&str],
+        slugs: &[&str],
+        conn: &mut DbConn,
+    ) -> Result<Vec<ShortcutStats>, diesel::result::Error> {
+        shortcut_hits::table
+            .filter(shortcut_hits::shortcut.eq_any(names))
+            .filter(shortcut_hits::team_slug.eq_any(slugs))
+            .group_by((shortcut_hits::shortcut, shortcut_hits::team_slug))
+            .select((
+                shortcut_hits::shortcut,
+                shortcut_hits::team_slug,
+                count_star(),
+                max(shortcut_hits::created_at),
+            ))
+            .load::<ShortcutStats>(conn)
+    }
+}
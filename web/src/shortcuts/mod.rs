@@ -1,27 +1,126 @@
+mod aliases;
 mod controller;
+mod hits;
+mod suggestions;
+mod versions;
 
+pub use aliases::*;
 pub use controller::*;
+pub use hits::*;
+pub use versions::*;
 
-use diesel::{prelude::*, Insertable};
-use serde::Serialize;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{deserialize, prelude::*, serialize, Insertable};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rocket::http::RawStr;
+use serde::{Deserialize, Serialize};
+use std::{io::Write, str::FromStr};
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
 
 use crate::errors::AppError;
-use crate::schema::{shortcuts, users_teams};
+use crate::schema::{shortcut_aliases, shortcuts, teams, users_teams};
 use crate::teams::Team;
 use crate::users::User;
 use crate::DbConn;
 use crate::schema::shortcuts::dsl::*;
 
-pub type AllColumns = (shortcuts::shortcut, shortcuts::team_slug, shortcuts::url);
+pub type AllColumns = (
+    shortcuts::shortcut,
+    shortcuts::team_slug,
+    shortcuts::url,
+    shortcuts::redirect,
+    shortcuts::created_by,
+    shortcuts::created_at,
+    shortcuts::updated_by,
+    shortcuts::updated_at,
+    shortcuts::description,
+    shortcuts::tags,
+);
 
-pub const SHORTCUT_COLUMNS: AllColumns =
-    (shortcuts::shortcut, shortcuts::team_slug, shortcuts::url);
+pub const SHORTCUT_COLUMNS: AllColumns = (
+    shortcuts::shortcut,
+    shortcuts::team_slug,
+    shortcuts::url,
+    shortcuts::redirect,
+    shortcuts::created_by,
+    shortcuts::created_at,
+    shortcuts::updated_by,
+    shortcuts::updated_at,
+    shortcuts::description,
+    shortcuts::tags,
+);
 
-#[derive(Queryable, Serialize, PartialEq, Eq, Debug)]
+#[derive(Queryable, Serialize, ToSchema, PartialEq, Eq, Debug)]
 pub struct Shortcut {
     pub shortcut: String,
     pub team_slug: String,
     pub url: String,
+    /// `None` to follow the instance `default_redirect` setting
+    pub redirect: Option<RedirectMode>,
+    /// Unknown for shortcuts created before authorship was tracked
+    pub created_by: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub description: Option<String>,
+    /// Lowercase, sorted and without duplicates
+    pub tags: Vec<String>,
+}
+
+/// Listing filters, e.g. `/?created_by=me@mail.com&tag=oncall`
+#[derive(FromForm, Debug, Default)]
+pub struct ShortcutFilter {
+    #[field(name = "created_by")]
+    pub author: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(FromFormField, ToSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[schema(rename_all = "lowercase")]
+pub enum ShortcutOrder {
+    /// Then team rank
+    #[default]
+    Name,
+    /// Most used first
+    Popularity,
+}
+
+/// How `get_shortcut` sends the user to the shortcut url
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    FromSqlRow,
+    EnumString,
+    AsExpression,
+    Display,
+    ToSchema,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum RedirectMode {
+    /// 302
+    Found,
+    /// 307
+    Temporary,
+    /// 301, cached by browsers
+    Moved,
+    /// 308, cached by browsers
+    Permanent,
+    /// "You are being redirected to" page, e.g. for external domains
+    Interstitial,
+}
+
+/// Shortcuts only lead to `http` and `https` URLs, never to `javascript:` or `data:` ones
+pub fn is_web_url(target: &str) -> bool {
+    ::url::Url::parse(target)
+        .map(|target| matches!(target.scheme(), "http" | "https") && target.has_host())
+        .unwrap_or(false)
 }
 
 impl Shortcut {
@@ -35,23 +134,86 @@ impl Shortcut {
         db::of_team(team, conn).map_err(AppError::from)
     }
 
-    pub fn sorted(user: &User, conn: &mut DbConn) -> Result<Vec<Shortcut>, AppError> {
-        db::sorted(user, conn).map_err(AppError::from)
+    /// `None` when it does not exist or `user` can't read its team
+    pub fn find(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        user: &User,
+        conn: &mut DbConn,
+    ) -> Result<Option<Shortcut>, AppError> {
+        match Team::find(team_slug_name, user, conn) {
+            Ok(Some(_)) => db::find(shortcut_name, team_slug_name, conn).map_err(AppError::from),
+            Ok(None) | Err(AppError::Unauthorized) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn first(
-        name: &str,
+    pub fn sorted(
+        user: &User,
+        order: ShortcutOrder,
+        filter: &ShortcutFilter,
+        conn: &mut DbConn,
+    ) -> Result<Vec<Shortcut>, AppError> {
+        let found = db::sorted(user, filter, conn).map_err(AppError::from)?;
+
+        match order {
+            ShortcutOrder::Name => Ok(found),
+            ShortcutOrder::Popularity => ShortcutStats::sort_by_popularity(found, conn),
+        }
+    }
+
+    /// Find the shortcut whose name or one of its aliases is the longest prefix of `name`,
+    /// along with that prefix, see [`Shortcut::target`] for the rest of the path
+    pub fn first<'a>(
+        name: &'a str,
         conn: &mut DbConn,
         user: &User,
-    ) -> Result<Option<Shortcut>, AppError> {
-        db::first(name, user, conn).map_err(AppError::from)
+    ) -> Result<Option<(Shortcut, &'a str)>, AppError> {
+        let prefixes = name_prefixes(name);
+
+        Ok(db::first(&prefixes, user, conn)?.map(|(found, i)| (found, prefixes[i])))
     }
 
+    /// Url to redirect to, the path segments in `rest` (what follows the shortcut name or alias) fill
+    /// the `{1}`, `{2}`... placeholders, or all together the `%s` one, or are appended to the url path if it has none
+    pub fn target(&self, rest: &str) -> String {
+        let args: Vec<String> = rest
+            .split('/')
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| RawStr::new(arg).percent_encode().to_string())
+            .collect();
+
+        if !has_placeholders(&self.url) {
+            if args.is_empty() {
+                return self.url.clone();
+            }
+            // Before the query and fragment, `http://x/search?q=a` and `foo` give `http://x/search/foo?q=a`
+            return match ::url::Url::parse(&self.url) {
+                Ok(mut target) if !target.cannot_be_a_base() => {
+                    let path = format!("{}/{}", target.path().trim_end_matches('/'), args.join("/"));
+                    target.set_path(&path);
+                    target.to_string()
+                }
+                _ => format!("{}/{}", self.url.trim_end_matches('/'), args.join("/")),
+            };
+        }
+
+        let mut target = self.url.replace("%s", &args.join("/"));
+        for (i, arg) in args.iter().enumerate() {
+            target = target.replace(&format!("{{{}}}", i + 1), arg);
+        }
+        PLACEHOLDER_REGEX.replace_all(&target, "").to_string()
+    }
+
+    /// `aliases` replace the current ones, `None` keeps them
     pub fn upsert(
-        new_shortcut: NewShortcut,
+        mut new_shortcut: NewShortcut,
+        aliases: Option<Vec<String>>,
         user: &User,
         conn: &mut DbConn,
     ) -> Result<Shortcut, AppError> {
+        new_shortcut.tags = normalize_tags(new_shortcut.tags);
+
         let team = Team::find(&new_shortcut.team_slug, user, conn)
             .map_err(AppError::from)?;
 
@@ -62,8 +224,28 @@ impl Shortcut {
         };
 
         user.can_write_team_shortcuts(&team, conn)?;
-        
-        db::upsert(new_shortcut, conn).map_err(AppError::from)
+
+        conn.transaction(|conn| {
+            let previous = db::find(&new_shortcut.shortcut, &new_shortcut.team_slug, conn)?;
+            if previous.is_none() && ShortcutAlias::exists(&new_shortcut.shortcut, &new_shortcut.team_slug, conn)? {
+                return Err(AppError::Conflict);
+            }
+
+            let saved = match previous {
+                // Unchanged, keep the last author
+                Some(previous) if previous.is_saved_as(&new_shortcut) => previous,
+                previous => {
+                    let saved = db::upsert(new_shortcut, user, conn)?;
+                    ShortcutVersion::record(previous.as_ref(), Some(&saved), user, conn)?;
+                    saved
+                }
+            };
+
+            if let Some(aliases) = aliases {
+                ShortcutAlias::replace(&saved, aliases, conn)?;
+            }
+            Ok(saved)
+        })
     }
 
     pub fn delete(
@@ -83,8 +265,47 @@ impl Shortcut {
 
         user.can_write_team_shortcuts(&team, conn)?;
 
-        db::delete(shortcut_name, team_slug_name, conn).map_err(AppError::from)
+        conn.transaction(|conn| {
+            let previous = db::find(shortcut_name, team_slug_name, conn)?;
+            db::delete(shortcut_name, team_slug_name, conn)?;
+            ShortcutVersion::record(previous.as_ref(), None, user, conn)
+        })
     }
+
+    fn is_saved_as(&self, new_shortcut: &NewShortcut) -> bool {
+        self.url == new_shortcut.url
+            && self.redirect == new_shortcut.redirect
+            && self.description == new_shortcut.description
+            && self.tags == new_shortcut.tags
+    }
+}
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{[0-9]+\}").unwrap();
+}
+
+/// Trimmed, lowercase, sorted and deduplicated so `?tag=` matches whatever the case used when saving
+fn normalize_tags(raw: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = raw
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort_unstable();
+    normalized.dedup();
+    normalized
+}
+
+fn has_placeholders(target: &str) -> bool {
+    target.contains("%s") || PLACEHOLDER_REGEX.is_match(target)
+}
+
+/// `a/b/c` => `["a/b/c", "a/b", "a"]`
+fn name_prefixes(name: &str) -> Vec<&str> {
+    let mut prefixes = vec![name];
+    prefixes.extend(name.match_indices('/').rev().map(|(i, _)| &name[..i]));
+    prefixes.retain(|prefix| !prefix.is_empty());
+    prefixes
 }
 
 mod db {
@@ -97,8 +318,28 @@ mod db {
             .load::<Shortcut>(conn)
     }
 
-    pub fn sorted(user: &User, conn: &mut DbConn) -> Result<Vec<Shortcut>, diesel::result::Error> {
-        shortcuts
+    pub fn find(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        conn: &mut DbConn,
+    ) -> Result<Option<Shortcut>, diesel::result::Error> {
+        shortcuts::table
+            .find((shortcut_name, team_slug_name))
+            .select(SHORTCUT_COLUMNS)
+            .first::<Shortcut>(conn)
+            .optional()
+    }
+
+    pub fn sorted(
+        user: &User,
+        filter: &ShortcutFilter,
+        conn: &mut DbConn,
+    ) -> Result<Vec<Shortcut>, diesel::result::Error> {
+        if user.is_anonymous() {
+            return public_sorted(filter, conn);
+        }
+
+        let mut query = shortcuts
             .inner_join(
                 users_teams::table.on(team_slug
                     .eq(users_teams::team_slug)
@@ -107,37 +348,152 @@ mod db {
             )
             .select(SHORTCUT_COLUMNS)
             .order_by((shortcut.asc(), users_teams::rank.asc()))
-            .get_results(conn)
+            .into_boxed();
+
+        if let Some(author) = &filter.author {
+            query = query.filter(created_by.eq(author));
+        }
+        if let Some(tag) = &filter.tag {
+            query = query.filter(tags.contains(vec![tag.trim().to_lowercase()]));
+        }
+
+        query.get_results(conn)
     }
 
+    /// Of the accepted teams which aren't private, the global team first
+    fn public_sorted(filter: &ShortcutFilter, conn: &mut DbConn) -> Result<Vec<Shortcut>, diesel::result::Error> {
+        let mut query = shortcuts
+            .inner_join(teams::table.on(team_slug.eq(teams::slug)))
+            .filter(teams::is_private.eq(false))
+            .filter(teams::is_accepted)
+            .select(SHORTCUT_COLUMNS)
+            .order_by((shortcut.asc(), team_slug.asc()))
+            .into_boxed();
+
+        if let Some(author) = &filter.author {
+            query = query.filter(created_by.eq(author));
+        }
+        if let Some(tag) = &filter.tag {
+            query = query.filter(tags.contains(vec![tag.trim().to_lowercase()]));
+        }
+
+        query.get_results(conn)
+    }
+
+    /// `names` ordered by preference, the longest match wins then the team rank
     pub fn first(
-        name: &str,
+        names: &[&str],
         user: &User,
         conn: &mut DbConn,
-    ) -> Result<Option<Shortcut>, diesel::result::Error> {
-        shortcuts
+    ) -> Result<Option<(Shortcut, usize)>, diesel::result::Error> {
+        let mut found = if user.is_anonymous() {
+            public_matches(names, conn)?
+        } else {
+            member_matches(names, user, conn)?
+        };
+        found.sort_by_key(|(_, rank, _)| *rank);
+
+        Ok(names
+            .iter()
+            .enumerate()
+            .find_map(|(i, name)| found.iter().position(|(matched, _, _)| matched == name).map(|p| (p, i)))
+            .map(|(p, i)| (found.swap_remove(p).2, i)))
+    }
+
+    /// By name or alias in the teams of `user`, with their rank
+    fn member_matches(
+        names: &[&str],
+        user: &User,
+        conn: &mut DbConn,
+    ) -> Result<Vec<(String, i16, Shortcut)>, diesel::result::Error> {
+        let by_name = shortcuts
             .inner_join(
                 users_teams::table.on(team_slug
                     .eq(users_teams::team_slug)
                     .and(users_teams::user_mail.eq(&user.mail))
                     .and(users_teams::is_accepted)),
             )
-            .filter(shortcut.eq(name))
-            .select(SHORTCUT_COLUMNS)
-            .order_by(users_teams::rank.asc())
-            .first::<Shortcut>(conn)
-            .optional()
+            .filter(shortcut.eq_any(names))
+            .select((shortcut, users_teams::rank, SHORTCUT_COLUMNS))
+            .load::<(String, i16, Shortcut)>(conn)?;
+
+        let by_alias = shortcut_aliases::table
+            .inner_join(
+                shortcuts.on(shortcut
+                    .eq(shortcut_aliases::shortcut)
+                    .and(team_slug.eq(shortcut_aliases::team_slug))),
+            )
+            .inner_join(
+                users_teams::table.on(team_slug
+                    .eq(users_teams::team_slug)
+                    .and(users_teams::user_mail.eq(&user.mail))
+                    .and(users_teams::is_accepted)),
+            )
+            .filter(shortcut_aliases::alias.eq_any(names))
+            .select((shortcut_aliases::alias, users_teams::rank, SHORTCUT_COLUMNS))
+            .load::<(String, i16, Shortcut)>(conn)?;
+
+        Ok(by_name.into_iter().chain(by_alias).collect())
+    }
+
+    /// By name or alias in the public teams, ranked as one: the global team first, then by slug
+    fn public_matches(names: &[&str], conn: &mut DbConn) -> Result<Vec<(String, i16, Shortcut)>, diesel::result::Error> {
+        let by_name = shortcuts
+            .inner_join(teams::table.on(team_slug.eq(teams::slug)))
+            .filter(teams::is_private.eq(false))
+            .filter(teams::is_accepted)
+            .filter(shortcut.eq_any(names))
+            .select((shortcut, SHORTCUT_COLUMNS))
+            .load::<(String, Shortcut)>(conn)?;
+
+        let by_alias = shortcut_aliases::table
+            .inner_join(
+                shortcuts.on(shortcut
+                    .eq(shortcut_aliases::shortcut)
+                    .and(team_slug.eq(shortcut_aliases::team_slug))),
+            )
+            .inner_join(teams::table.on(team_slug.eq(teams::slug)))
+            .filter(teams::is_private.eq(false))
+            .filter(teams::is_accepted)
+            .filter(shortcut_aliases::alias.eq_any(names))
+            .select((shortcut_aliases::alias, SHORTCUT_COLUMNS))
+            .load::<(String, Shortcut)>(conn)?;
+
+        let mut found: Vec<(String, Shortcut)> = by_name.into_iter().chain(by_alias).collect();
+        found.sort_by(|(_, a), (_, b)| a.team_slug.cmp(&b.team_slug));
+
+        Ok(found.into_iter().map(|(matched, found)| (matched, 0, found)).collect())
     }
 
     pub fn upsert(
         new_shortcut: NewShortcut,
+        user: &User,
         conn: &mut DbConn,
     ) -> Result<Shortcut, diesel::result::Error> {
+        let now = Utc::now().naive_utc();
+
         diesel::insert_into(shortcuts::table)
-            .values(new_shortcut.clone())
+            .values((
+                new_shortcut.clone(),
+                created_by.eq(&user.mail),
+                created_at.eq(now),
+                updated_by.eq(&user.mail),
+                updated_at.eq(now),
+            ))
             .on_conflict((shortcuts::shortcut, shortcuts::team_slug))
             .do_update()
-            .set(UpdatableShortcut { url: new_shortcut.url.clone(), team_slug: new_shortcut.team_slug.clone() })
+            .set((
+                UpdatableShortcut {
+                    url: new_shortcut.url.clone(),
+                    team_slug: new_shortcut.team_slug.clone(),
+                    redirect: new_shortcut.redirect,
+                    description: new_shortcut.description.clone(),
+                    tags: new_shortcut.tags.clone(),
+                },
+                updated_by.eq(&user.mail),
+                updated_at.eq(now),
+            ))
+            .returning(SHORTCUT_COLUMNS)
             .get_result(conn)
     }
 
@@ -159,11 +515,36 @@ pub struct NewShortcut {
     pub shortcut: String,
     pub url: String,
     pub team_slug: String,
+    pub redirect: Option<RedirectMode>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
 }
 
 #[derive(AsChangeset)]
-#[diesel(table_name = shortcuts)]
+#[diesel(table_name = shortcuts, treat_none_as_null = true)]
 pub struct UpdatableShortcut {
     pub url: String,
     pub team_slug: String,
+    pub redirect: Option<RedirectMode>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg> for RedirectMode {
+    fn from_sql(bytes: <diesel::pg::Pg as diesel::backend::Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let s: String =
+            deserialize::FromSql::<diesel::sql_types::Text, diesel::pg::Pg>::from_sql(bytes)?;
+        let r = RedirectMode::from_str(&s)?;
+        Ok(r)
+    }
+}
+
+impl serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg> for RedirectMode
+where
+    String: serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
 }
\ No newline at end of file
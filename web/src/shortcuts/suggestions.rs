@@ -0,0 +1,79 @@
+use std::cmp::min;
+
+use crate::errors::AppError;
+use crate::shortcuts::{Shortcut, ShortcutAlias, ShortcutFilter, ShortcutOrder};
+use crate::users::User;
+use crate::DbConn;
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// How a known name looks like the requested one, the first variants are the best
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Likeness {
+    Prefix,
+    Substring,
+    Typo,
+}
+
+impl Shortcut {
+    /// Names (or aliases) of the shortcuts visible to `user` that look like the unknown `name`, best first
+    pub fn suggestions(name: &str, user: &User, conn: &mut DbConn) -> Result<Vec<String>, AppError> {
+        let visible = Shortcut::sorted(user, ShortcutOrder::Name, &ShortcutFilter::default(), conn)?;
+        let aliases = ShortcutAlias::of(&visible, conn)?;
+
+        let known = visible
+            .iter()
+            .map(|s| s.shortcut.as_str())
+            .chain(aliases.iter().map(|a| a.alias.as_str()));
+
+        Ok(suggest(name, known))
+    }
+}
+
+fn suggest<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let wanted = name.to_lowercase();
+
+    let mut ranked: Vec<(Likeness, usize, &str)> = known
+        .filter_map(|candidate| {
+            let lowercase = candidate.to_lowercase();
+            let distance = edit_distance(&wanted, &lowercase);
+
+            let likeness = if lowercase.starts_with(&wanted) {
+                Likeness::Prefix
+            } else if lowercase.contains(&wanted) || wanted.contains(&lowercase) {
+                Likeness::Substring
+            } else if distance <= wanted.chars().count() / 3 + 1 {
+                Likeness::Typo
+            } else {
+                return None;
+            };
+            Some((likeness, distance, candidate))
+        })
+        .collect();
+
+    ranked.sort();
+    ranked.dedup_by(|a, b| a.2 == b.2);
+
+    ranked
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = min(substitution, min(previous[j + 1], current[j]) + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,273 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{deserialize, dsl::max, prelude::*, serialize};
+use serde::{Deserialize, Serialize};
+use std::{io::Write, str::FromStr};
+use strum_macros::{Display, EnumString};
+
+use crate::errors::AppError;
+use crate::schema::shortcut_versions;
+use crate::shortcuts::{NewShortcut, RedirectMode, Shortcut};
+use crate::teams::Team;
+use crate::users::User;
+use crate::DbConn;
+
+pub type VersionColumns = (
+    shortcut_versions::shortcut,
+    shortcut_versions::team_slug,
+    shortcut_versions::version,
+    shortcut_versions::action,
+    shortcut_versions::old_url,
+    shortcut_versions::new_url,
+    shortcut_versions::redirect,
+    shortcut_versions::description,
+    shortcut_versions::tags,
+    shortcut_versions::user_mail,
+    shortcut_versions::created_at,
+);
+
+pub const VERSION_COLUMNS: VersionColumns = (
+    shortcut_versions::shortcut,
+    shortcut_versions::team_slug,
+    shortcut_versions::version,
+    shortcut_versions::action,
+    shortcut_versions::old_url,
+    shortcut_versions::new_url,
+    shortcut_versions::redirect,
+    shortcut_versions::description,
+    shortcut_versions::tags,
+    shortcut_versions::user_mail,
+    shortcut_versions::created_at,
+);
+
+/// One change of a shortcut, `new_url` is `None` once deleted.
+/// `redirect`, `description` and `tags` are the ones of the shortcut after the change
+#[derive(Queryable, Serialize, PartialEq, Eq, Debug)]
+pub struct ShortcutVersion {
+    pub shortcut: String,
+    pub team_slug: String,
+    pub version: i32,
+    pub action: VersionAction,
+    pub old_url: Option<String>,
+    pub new_url: Option<String>,
+    pub redirect: Option<RedirectMode>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub user_mail: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    FromSqlRow,
+    EnumString,
+    AsExpression,
+    Display,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum VersionAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = shortcut_versions)]
+struct NewVersion<'a> {
+    shortcut: &'a str,
+    team_slug: &'a str,
+    version: i32,
+    action: VersionAction,
+    old_url: Option<&'a str>,
+    new_url: Option<&'a str>,
+    redirect: Option<RedirectMode>,
+    description: Option<&'a str>,
+    tags: &'a [String],
+    user_mail: &'a str,
+    created_at: NaiveDateTime,
+}
+
+impl ShortcutVersion {
+    /// Newest first
+    pub fn of(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        user: &User,
+        conn: &mut DbConn,
+    ) -> Result<Vec<ShortcutVersion>, AppError> {
+        let team = Team::find(team_slug_name, user, conn)?.ok_or(AppError::NotFound)?;
+
+        user.can_write_team_shortcuts(&team, conn)?;
+
+        db::of(shortcut_name, team_slug_name, conn).map_err(AppError::from)
+    }
+
+    /// Bring the shortcut back as it was after `version`, which is itself recorded as a new version
+    pub fn revert(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        version: i32,
+        user: &User,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        let team = Team::find(team_slug_name, user, conn)?.ok_or(AppError::NotFound)?;
+
+        user.can_write_team_shortcuts(&team, conn)?;
+
+        let reverted = db::find(shortcut_name, team_slug_name, version, conn)?
+            .ok_or(AppError::NotFound)?;
+
+        match reverted.new_url {
+            Some(new_url) => Shortcut::upsert(
+                NewShortcut {
+                    shortcut: reverted.shortcut,
+                    url: new_url,
+                    team_slug: reverted.team_slug,
+                    redirect: reverted.redirect,
+                    description: reverted.description,
+                    tags: reverted.tags,
+                },
+                None,
+                user,
+                conn,
+            )
+            .map(|_| ()),
+            None => Shortcut::delete(shortcut_name, team_slug_name, user, conn),
+        }
+    }
+
+    /// Append the change from `previous` to `current`, nothing if the shortcut did not change
+    pub(super) fn record(
+        previous: Option<&Shortcut>,
+        current: Option<&Shortcut>,
+        user: &User,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        let (changed, action) = match (previous, current) {
+            (None, Some(current)) => (current, VersionAction::Create),
+            (Some(previous), Some(current))
+                if previous.url != current.url
+                    || previous.redirect != current.redirect
+                    || previous.description != current.description
+                    || previous.tags != current.tags =>
+            {
+                (current, VersionAction::Update)
+            }
+            (Some(previous), None) => (previous, VersionAction::Delete),
+            _ => return Ok(()),
+        };
+
+        let last = db::last_version(&changed.shortcut, &changed.team_slug, conn)?;
+
+        db::insert(
+            NewVersion {
+                shortcut: &changed.shortcut,
+                team_slug: &changed.team_slug,
+                version: last.unwrap_or(0) + 1,
+                action,
+                old_url: previous.map(|s| s.url.as_str()),
+                new_url: current.map(|s| s.url.as_str()),
+                redirect: current.and_then(|s| s.redirect),
+                description: current.and_then(|s| s.description.as_deref()),
+                tags: current.map(|s| s.tags.as_slice()).unwrap_or_default(),
+                user_mail: &user.mail,
+                created_at: Utc::now().naive_utc(),
+            },
+            conn,
+        )
+        .map_err(AppError::from)
+    }
+
+    /// The history of a deleted team is kept but no longer listed, not to be given to a new team of the same slug
+    pub fn archive_team(team_slug_name: &str, conn: &mut DbConn) -> Result<(), AppError> {
+        db::archive(team_slug_name, Utc::now().naive_utc(), conn).map_err(AppError::from)
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn of(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        conn: &mut DbConn,
+    ) -> Result<Vec<ShortcutVersion>, diesel::result::Error> {
+        shortcut_versions::table
+            .filter(shortcut_versions::shortcut.eq(shortcut_name))
+            .filter(shortcut_versions::team_slug.eq(team_slug_name))
+            .filter(shortcut_versions::archived_at.is_null())
+            .select(VERSION_COLUMNS)
+            .order(shortcut_versions::version.desc())
+            .load::<ShortcutVersion>(conn)
+    }
+
+    pub fn find(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        version: i32,
+        conn: &mut DbConn,
+    ) -> Result<Option<ShortcutVersion>, diesel::result::Error> {
+        shortcut_versions::table
+            .filter(shortcut_versions::shortcut.eq(shortcut_name))
+            .filter(shortcut_versions::team_slug.eq(team_slug_name))
+            .filter(shortcut_versions::version.eq(version))
+            .filter(shortcut_versions::archived_at.is_null())
+            .select(VERSION_COLUMNS)
+            .first::<ShortcutVersion>(conn)
+            .optional()
+    }
+
+    pub fn last_version(
+        shortcut_name: &str,
+        team_slug_name: &str,
+        conn: &mut DbConn,
+    ) -> Result<Option<i32>, diesel::result::Error> {
+        shortcut_versions::table
+            .filter(shortcut_versions::shortcut.eq(shortcut_name))
+            .filter(shortcut_versions::team_slug.eq(team_slug_name))
+            .select(max(shortcut_versions::version))
+            .first(conn)
+    }
+
+    pub fn archive(team_slug_name: &str, now: NaiveDateTime, conn: &mut DbConn) -> Result<(), diesel::result::Error> {
+        diesel::update(
+            shortcut_versions::table
+                .filter(shortcut_versions::team_slug.eq(team_slug_name))
+                .filter(shortcut_versions::archived_at.is_null()),
+        )
+        .set(shortcut_versions::archived_at.eq(now))
+        .execute(conn)
+        .map(|_| ())
+    }
+
+    pub fn insert(new_version: NewVersion, conn: &mut DbConn) -> Result<(), diesel::result::Error> {
+        diesel::insert_into(shortcut_versions::table)
+            .values(new_version)
+            .execute(conn)
+            .map(|_| ())
+    }
+}
+
+impl deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg> for VersionAction {
+    fn from_sql(bytes: <diesel::pg::Pg as diesel::backend::Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let s: String =
+            deserialize::FromSql::<diesel::sql_types::Text, diesel::pg::Pg>::from_sql(bytes)?;
+        let r = VersionAction::from_str(&s)?;
+        Ok(r)
+    }
+}
+
+impl serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg> for VersionAction
+where
+    String: serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
@@ -1,16 +1,17 @@
 use rocket::{http::Status, serde::json::Json, State};
 use rocket_dyn_templates::Template;
 use serde_json::{json, Value};
-use std::{cmp::Ordering, str::FromStr};
+use std::str::FromStr;
 
 use crate::{
     errors::{
         AppError,
     },
-    shortcuts::Shortcut,
+    guards::TeamsEnabled,
+    shortcuts::{Shortcut, ShortcutAlias},
     teams::{
          Team, TeamCapability,
-        TeamForOptUser,  TeamWithUserLinks, PatchableTeam, NewTeam
+        TeamWithUserLinks, PatchableTeam, NewTeam
     },
     users::{ User, },
     views::IndexContext,
@@ -18,22 +19,10 @@ use crate::{
 };
 
 #[get("/go/teams")]
-pub fn list_teams(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Template)> {
+pub fn list_teams(_teams: TeamsEnabled, user: User, pool: &State<DbPool>) -> Result<Template, (Status, Template)> {
     let mut conn = pool.get().map_err(AppError::from)?;
 
-    let mut teams =  Team::all_with_user_link(&user.mail, &mut conn)?;
-
-    teams.sort_by(
-        |TeamForOptUser { team: a, .. }, TeamForOptUser { team: b, .. }| {
-            if a.slug.is_empty() {
-                Ordering::Less
-            } else if b.slug.is_empty() {
-                Ordering::Greater
-            } else {
-                a.title.cmp(&b.title)
-            }
-        },
-    );
+    let teams =  Team::all_with_user_link(&user.mail, &mut conn)?;
 
     Ok(Template::render(
         "teams",
@@ -48,6 +37,7 @@ pub fn list_teams(user: User, pool: &State<DbPool>) -> Result<Template, (Status,
 #[get("/go/teams/<slug>")]
 pub fn show_team(
     slug: String,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Template, (Status, Template)> {
@@ -66,6 +56,7 @@ pub fn show_team(
     };
 
     let shortcuts = Shortcut::of_team(&team_with_user_links.team, &user, &mut conn)?;
+    let aliases = ShortcutAlias::of(&shortcuts, &mut conn)?;
 
 
     Ok(Template::render(
@@ -77,7 +68,10 @@ pub fn show_team(
                 shortcuts,
                 team: Some(team_with_user_links),
                 teams: Team::all_with_shortcut_write(&user, &mut conn)?,
-                user
+                user,
+                stats: vec![],
+                aliases,
+                suggestions: vec![],
             }).to_string()
         }),
     ))
@@ -86,6 +80,7 @@ pub fn show_team(
 #[delete("/go/teams/<slug>")]
 pub fn delete_team(
     slug: String,
+    _teams: TeamsEnabled,
     user: User,
 
     pool: &State<DbPool>,
@@ -100,6 +95,7 @@ pub fn delete_team(
 #[post("/go/teams", data = "<new_team>")]
 pub fn create_team(
     new_team: Json<NewTeam>,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Template)> {
@@ -113,6 +109,7 @@ pub fn create_team(
 pub fn patch_team(
     team: String,
     patchable_team: Json<PatchableTeam>,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Template)> {
@@ -127,6 +124,7 @@ pub fn patch_team(
 pub fn kick_user(
     slug: String,
     mail: String,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Template)> {
@@ -142,6 +140,7 @@ pub fn put_user_link_capability(
     team_slug: String,
     mail: String,
     capability: String,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
@@ -159,6 +158,7 @@ pub fn delete_user_link_capability(
     team_slug: String,
     mail: String,
     capability: String,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
@@ -176,6 +176,7 @@ pub fn put_user_team_acceptation(
     team_slug: String,
     mail: String,
     acceptation: bool,
+    _teams: TeamsEnabled,
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
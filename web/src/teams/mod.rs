@@ -4,12 +4,13 @@ pub use controller::*;
 
 use diesel::{deserialize, dsl::{count, max}, prelude::*, serialize, Identifiable};
 use serde::{Deserialize, Serialize};
-use std::{io::Write, str::FromStr};
+use std::{cmp::Ordering, io::Write, str::FromStr};
 use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
 
 use crate::{
     errors::AppError, schema::{        teams::{self, dsl}
-    , users_teams}, users::{ User, UserTeam}, DbConn
+    , users_teams}, settings::teams_enabled, shortcuts::ShortcutVersion, users::{ TokenScope, User, UserTeam}, DbConn
 };
 
 pub type AllColumns = (
@@ -26,7 +27,7 @@ pub const TEAM_COLUMNS: AllColumns = (
     teams::is_accepted,
 );
 
-#[derive(Insertable, Queryable, Serialize, Identifiable, Debug, PartialEq, Eq)]
+#[derive(Insertable, Queryable, Serialize, ToSchema, Identifiable, Debug, PartialEq, Eq)]
 #[diesel(table_name = teams, primary_key(slug))]
 pub struct Team {
     pub slug: String,
@@ -70,7 +71,10 @@ impl Team {
 
     pub fn delete(slug: &str, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
         user.can_write_team(slug, conn)?;
-        db::delete(slug, conn).map_err(AppError::from).map(|_| ())
+        conn.transaction(|conn| {
+            ShortcutVersion::archive_team(slug, conn)?;
+            db::delete(slug, conn).map_err(AppError::from).map(|_| ())
+        })
     }
 
     pub fn update(patchable_team: PatchableTeam, slug: &str, user: &User, conn: &mut DbConn) -> Result<Team, AppError> {
@@ -83,7 +87,11 @@ impl Team {
         db::update(patchable_team, slug, conn).map_err(AppError::from)
     }
 
+    /// Only the global team is found once teams are turned off, so their shortcuts can't be read or written by slug
     pub fn find(slug: &str, user: &User, conn: &mut DbConn) -> Result<Option<Team>, AppError> {
+        if !slug.is_empty() {
+            teams_enabled(conn)?;
+        }
         let team = db::find_by_slug(slug, conn).map_err(AppError::from)?;
         
         let team = if let Some(team) = team {
@@ -101,8 +109,23 @@ impl Team {
         db::find_all_with_shortcut_write(&user.mail, conn).map_err(AppError::from)
     }
 
+    /// The global team first, then by title
     pub fn all_with_user_link(mail: &str, conn: &mut DbConn) -> Result<Vec<TeamForOptUser>, AppError> {
-        db::find_all_with_user_link(mail, conn).map_err(AppError::from)
+        let mut teams = db::find_all_with_user_link(mail, conn).map_err(AppError::from)?;
+
+        teams.sort_by(
+            |TeamForOptUser { team: a, .. }, TeamForOptUser { team: b, .. }| {
+                if a.slug.is_empty() {
+                    Ordering::Less
+                } else if b.slug.is_empty() {
+                    Ordering::Greater
+                } else {
+                    a.title.cmp(&b.title)
+                }
+            },
+        );
+
+        Ok(teams)
     }
 
     pub fn with_all_user_links(slug: &str, user: &User, conn: &mut DbConn) -> Result<Option<TeamWithUserLinks>, AppError> {
@@ -138,7 +161,9 @@ impl Team {
         conn: &mut DbConn,
     ) -> Result<usize, AppError> {
         let is_self_kick = user.mail == mail;
-        if !is_self_kick {
+        if is_self_kick {
+            user.should_have_scope(TokenScope::TeamsAdmin)?;
+        } else {
             user.can_write_team(slug, conn)?;
         }
 
@@ -154,7 +179,7 @@ impl Team {
     ) -> Result<(), AppError> {
         user.can_write_team(team_slug, conn)?;
 
-        let user_link: UserTeam = db::find_user_team_link(mail, team_slug, conn).map_err(AppError::from)?;
+        let user_link: UserTeam = db::find_user_team_link(mail, team_slug, conn)?.ok_or(AppError::NotFound)?;
 
         let mut capabilities = user_link.capabilities;
         if !capabilities.contains(&capability) {
@@ -179,7 +204,7 @@ impl Team {
     ) -> Result<(), AppError> {
         user.can_write_team(team_slug, conn)?;
 
-        let user_link: UserTeam = db::find_user_team_link(mail, team_slug, conn).map_err(AppError::from)?;
+        let user_link: UserTeam = db::find_user_team_link(mail, team_slug, conn)?.ok_or(AppError::NotFound)?;
 
         let mut capabilities = user_link.capabilities;
         if capabilities.contains(&capability) {
@@ -285,10 +310,11 @@ mod db {
             .execute(conn)
     }
 
-    pub fn find_user_team_link(mail: &str, team_slug: &str, conn: &mut r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>) -> Result<UserTeam, diesel::result::Error> {
+    pub fn find_user_team_link(mail: &str, team_slug: &str, conn: &mut r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>) -> Result<Option<UserTeam>, diesel::result::Error> {
         users_teams::table
             .find((mail, team_slug))
             .first(conn)
+            .optional()
     }
 
     pub fn update_user_capabilities(mail: &str, team_slug: &str, capabilities: Vec<TeamCapability>, conn: &mut r2d2::PooledConnection<diesel::r2d2::ConnectionManager<PgConnection>>) -> Result<usize, diesel::result::Error> {
@@ -304,14 +330,14 @@ mod db {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct NewTeam {
     pub slug: String,
     pub title: String,
     pub is_private: bool,
 }
 
-#[derive(Deserialize, AsChangeset)]
+#[derive(Deserialize, ToSchema, AsChangeset)]
 #[diesel(table_name = teams)]
 pub struct PatchableTeam {
     pub title: Option<String>,
@@ -319,7 +345,7 @@ pub struct PatchableTeam {
     pub is_accepted: Option<bool>,
 }
 
-#[derive(Queryable, Serialize)]
+#[derive(Queryable, Serialize, ToSchema)]
 pub struct TeamForOptUser {
     #[serde(flatten)]
     pub team: Team,
@@ -333,7 +359,7 @@ pub struct TeamForUser {
     pub user_link: UserTeam,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
 pub struct TeamWithUserLinks {
     #[serde(flatten)]
     pub team: Team,
@@ -352,6 +378,7 @@ pub struct TeamWithUserLinks {
     EnumString,
     AsExpression,
     Display,
+    ToSchema,
 )]
 #[diesel(sql_type = diesel::sql_types::Text)]
 pub enum TeamCapability {
@@ -0,0 +1,57 @@
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+
+use crate::errors::AppError;
+use crate::guards::bearer_token;
+use crate::settings::features;
+use crate::users::{TokenScope, User};
+use crate::DbPool;
+
+/// Who browses and resolves shortcuts: the logged in user, or the anonymous one when reading isn't private.
+/// Requests with a personal token are never anonymous, a wrong one is refused
+pub struct Reader(pub User);
+
+impl User {
+    /// Member of no team, it reads the shortcuts of the public teams. Its `Read` scope keeps it from writing
+    pub fn anonymous() -> User {
+        User {
+            mail: String::new(),
+            capabilities: vec![],
+            scopes: Some(vec![TokenScope::Read]),
+        }
+    }
+
+    /// No user has an empty mail
+    pub fn is_anonymous(&self) -> bool {
+        self.mail.is_empty()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Reader {
+    type Error = serde_json::Value;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let error = match req.guard::<User>().await {
+            Outcome::Success(user) => return Outcome::Success(Reader(user)),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+            Outcome::Error(error) => error,
+        };
+        if bearer_token(req.headers()).is_some() {
+            return Outcome::Error(error);
+        }
+
+        let pool: Outcome<&State<DbPool>, Self::Error> = req
+            .guard::<&State<DbPool>>()
+            .await
+            .map_error(|_| AppError::Guard.into());
+        let pool = try_outcome!(pool);
+
+        match pool.get().map_err(AppError::from).and_then(|mut conn| features(&mut conn)) {
+            Ok(features) if !features.login.read_private => Outcome::Success(Reader(User::anonymous())),
+            Ok(_) => Outcome::Error(error),
+            Err(e) => Outcome::Error(e.into()),
+        }
+    }
+}
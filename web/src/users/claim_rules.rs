@@ -0,0 +1,193 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::errors::AppError;
+use crate::teams::TeamCapability;
+use crate::users::{Capability, User, UserTeam};
+use crate::DbConn;
+
+/// Grants users logging in with an identity provider according to a claim of their ID token,
+/// like the members of its `go-admins` group being users admins
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+pub struct ClaimRule {
+    /// Only for the users of this provider, any when missing
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Like `groups` or `hd`
+    pub claim: String,
+    /// Equal to the claim, or one of its values when it is a list
+    pub value: String,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub teams: Vec<ClaimTeam>,
+}
+
+/// A team joined, by slug, with what can be done in it
+#[derive(Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+pub struct ClaimTeam {
+    pub team: String,
+    #[serde(default)]
+    pub capabilities: Vec<TeamCapability>,
+}
+
+impl ClaimRule {
+    /// Rules of other providers neither grant nor take back anything
+    pub fn applies_to(&self, provider: &str) -> bool {
+        self.provider.as_ref().is_none_or(|only| only == provider)
+    }
+
+    pub fn matches(&self, provider: &str, claims: &HashMap<String, Value>) -> bool {
+        if !self.applies_to(provider) {
+            return false;
+        }
+
+        match claims.get(&self.claim) {
+            Some(Value::Array(values)) => values.iter().any(|value| self.is(value)),
+            Some(value) => self.is(value),
+            None => false,
+        }
+    }
+
+    fn is(&self, value: &Value) -> bool {
+        match value {
+            Value::String(value) => *value == self.value,
+            Value::Bool(value) => self.value.parse() == Ok(*value),
+            Value::Number(value) => self.value.parse().is_ok_and(|number: serde_json::Number| number == *value),
+            _ => false,
+        }
+    }
+}
+
+impl User {
+    /// Run on every login with a provider, so that changes in it are followed: what the matching
+    /// rules name is granted, what only the others of this provider name is taken back.
+    /// Capabilities and teams no rule of this provider names are left as they are
+    pub fn apply_claim_rules(
+        mail: &str,
+        provider: &str,
+        claims: &HashMap<String, Value>,
+        rules: &[ClaimRule],
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        let (matching, others): (Vec<&ClaimRule>, Vec<&ClaimRule>) =
+            rules
+                .iter()
+                .filter(|rule| rule.applies_to(provider))
+                .partition(|rule| rule.matches(provider, claims));
+
+        conn.transaction::<_, AppError, _>(|conn| {
+            let user = super::db::find(mail, conn)?.ok_or(AppError::NotFound)?;
+
+            let mut granted: Vec<Capability> = vec![];
+            for capability in matching.iter().flat_map(|rule| &rule.capabilities) {
+                if !granted.contains(capability) {
+                    granted.push(*capability);
+                }
+            }
+            let mut capabilities: Vec<Capability> = user
+                .capabilities
+                .iter()
+                .filter(|capability| {
+                    granted.contains(capability)
+                        || !others.iter().any(|rule| rule.capabilities.contains(capability))
+                })
+                .copied()
+                .collect();
+            for capability in granted {
+                if !capabilities.contains(&capability) {
+                    capabilities.push(capability);
+                }
+            }
+            if capabilities != user.capabilities {
+                super::db::update_capabilities(mail, capabilities, conn)?;
+            }
+
+            let mut teams: Vec<&str> = vec![];
+            for team in matching.iter().chain(&others).flat_map(|rule| &rule.teams) {
+                if !teams.contains(&team.team.as_str()) {
+                    teams.push(&team.team);
+                }
+            }
+            for slug in teams {
+                let granted: Vec<&ClaimTeam> = matching
+                    .iter()
+                    .flat_map(|rule| &rule.teams)
+                    .filter(|team| team.team == slug)
+                    .collect();
+                let membership = db::find_user_team(mail, slug, conn)?;
+
+                if granted.is_empty() {
+                    if membership.is_some() {
+                        super::db::remove_user_team(mail, slug, conn)?;
+                    }
+                    continue;
+                }
+
+                let mut team_capabilities: Vec<TeamCapability> = vec![];
+                for capability in granted.iter().flat_map(|team| &team.capabilities) {
+                    if !team_capabilities.contains(capability) {
+                        team_capabilities.push(*capability);
+                    }
+                }
+
+                match membership {
+                    Some(membership) => {
+                        if membership.capabilities != team_capabilities || !membership.is_accepted {
+                            db::update_user_team(mail, slug, team_capabilities, conn)?;
+                        }
+                    }
+                    None => {
+                        if super::db::find_team(slug, conn)?.is_none() {
+                            warn!("Claim rules name the unknown team {slug}, {mail} can't join it");
+                            continue;
+                        }
+                        super::db::add_user_team(
+                            UserTeam {
+                                user_mail: mail.to_string(),
+                                team_slug: slug.to_string(),
+                                capabilities: team_capabilities,
+                                is_accepted: true,
+                                rank: 0,
+                            },
+                            conn,
+                        )?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+mod db {
+    use super::*;
+    use crate::schema::users_teams;
+
+    pub fn find_user_team(
+        mail: &str,
+        slug: &str,
+        conn: &mut DbConn,
+    ) -> Result<Option<UserTeam>, diesel::result::Error> {
+        users_teams::table.find((mail, slug)).first(conn).optional()
+    }
+
+    /// Accepted, granted by the identity provider
+    pub fn update_user_team(
+        mail: &str,
+        slug: &str,
+        capabilities: Vec<TeamCapability>,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::update(users_teams::table.find((mail, slug)))
+            .set((
+                users_teams::capabilities.eq(capabilities),
+                users_teams::is_accepted.eq(true),
+            ))
+            .execute(conn)
+    }
+}
@@ -3,7 +3,6 @@ use std::str::FromStr;
 
 use diesel::prelude::*;
 use lazy_static::lazy_static;
-use log::warn;
 use regex::Regex;
 use rocket::request::{FromRequest, Outcome};
 use rocket::Request;
@@ -11,23 +10,24 @@ use rocket::{http::Status, serde::json::Json, State,    outcome::try_outcome,
 };
 use rocket_dyn_templates::Template;
 use serde::Deserialize;
+use utoipa::ToSchema;
 use serde_json::{json, Value};
 
-use crate::guards::SessionId;
-use crate::teams::{Team, TeamCapability};
-use crate::users::{Capability, User, UserTeam, SAFE_USER_COLUMNS};
+use crate::guards::{bearer_token, SessionId};
+use crate::users::{Capability, User, SAFE_USER_COLUMNS};
 use crate::errors::AppError;
-use crate::schema::users::{self, dsl};
-use crate::schema::{teams, users_teams};
-use crate::DbPool;
+use crate::guards::TeamsEnabled;
+use crate::settings::{second_factor_policy, teams_enabled};
+use crate::schema::users;
+use crate::{AppConfig, DbPool};
 use super::*;
 
 lazy_static! {
    pub static ref MAIL_REGEX: Regex =
-        Regex::new(r#"(?:[a-z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-z0-9!#$%&'*+/=?^_`{|}~-]+)*|"(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21\x23-\x5b\x5d-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])*")@(?:(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z0-9](?:[a-z0-9-]*[a-z0-9])?|\[(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?|[a-z0-9-]*[a-z0-9]:(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21-\x5a\x53-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])+)\])"#,).unwrap();
+        Regex::new(r#"(?i)^(?:[a-z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-z0-9!#$%&'*+/=?^_`{|}~-]+)*|"(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21\x23-\x5b\x5d-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])*")@(?:(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z0-9](?:[a-z0-9-]*[a-z0-9])?|\[(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?|[a-z0-9-]*[a-z0-9]:(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21-\x5a\x53-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])+)\])$"#,).unwrap();
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UserTeamLink {
     pub rank: i16,
 }
@@ -49,31 +49,12 @@ pub fn join_team(
     user: User,
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
-    user.should_have_capability(Capability::UsersTeamsWrite)?;
-
     let mut conn = pool.get().map_err(AppError::from)?;
-    let team: Option<Team> = teams::table
-        .find(&slug)
-        .first(&mut conn)
-        .optional()
-        .map_err(AppError::from)?;
-
-    let team = if let Some(team) = team {
-        team
-    } else {
-        return Err((Status::NotFound, json!({"error": "Team not found"})));
-    };
-
-    diesel::insert_into(users_teams::table)
-        .values(UserTeam {
-            user_mail: user.mail,
-            team_slug: slug,
-            capabilities: vec![TeamCapability::ShortcutsWrite],
-            is_accepted: !team.is_private,
-            rank: team_user_link.rank,
-        })
-        .execute(&mut conn)
-        .map_err(AppError::from)?;
+    if !slug.is_empty() {
+        teams_enabled(&mut conn)?;
+    }
+
+    user.join_team(&slug, team_user_link.rank, &mut conn)?;
 
     Ok(Status::Created)
 }
@@ -90,17 +71,12 @@ pub fn leave_team(
 
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Value)> {
-    user.should_have_capability(Capability::UsersTeamsWrite)?;
-
     let mut conn = pool.get().map_err(AppError::from)?;
-    diesel::delete(users_teams::table)
-        .filter(
-            users_teams::user_mail
-                .eq(&user.mail)
-                .and(users_teams::team_slug.eq(&slug)),
-        )
-        .execute(&mut conn)
-        .map_err(AppError::from)?;
+    if !slug.is_empty() {
+        teams_enabled(&mut conn)?;
+    }
+
+    user.leave_team(&slug, &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -108,32 +84,14 @@ pub fn leave_team(
 #[put("/go/user/teams/ranks", data = "<team_ranks>")]
 pub fn put_user_team_ranks(
     team_ranks: Json<HashMap<String, u16>>,
+    _teams: TeamsEnabled,
     user: User,
 
     pool: &State<DbPool>,
 ) -> Result<Status, (Status, Template)> {
-    user.should_have_capability(Capability::UsersTeamsWrite)?;
-
     let mut conn = pool.get().map_err(AppError::from)?;
-    conn.transaction::<_, diesel::result::Error, _>(|conn| {
-        for (slug, rank) in team_ranks.into_inner() {
-            match diesel::update(users_teams::table.find((&user.mail, &slug)))
-                .set(users_teams::rank.eq(rank as i16))
-                .execute(conn)
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    error!(
-                        "Team rank update failed for {} rank {}, rollback transaction: {:?}",
-                        slug, rank, e
-                    );
-                    return Err(diesel::result::Error::RollbackTransaction);
-                }
-            }
-        }
-        Ok(())
-    })
-    .map_err(AppError::from)?;
+
+    user.set_team_ranks(team_ranks.into_inner(), &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -141,11 +99,7 @@ pub fn put_user_team_ranks(
 #[get("/go/users")]
 pub fn list_users(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Value)> {
     let mut conn = pool.get().map_err(AppError::from)?;
-    let users = dsl::users
-        .select(SAFE_USER_COLUMNS)
-        .order_by(dsl::mail)
-        .load::<User>(&mut conn)
-        .map_err(AppError::from)?;
+    let users = User::all(&mut conn)?;
 
     Ok(Template::render(
         "users",
@@ -158,6 +112,66 @@ pub fn list_users(user: User, pool: &State<DbPool>) -> Result<Template, (Status,
     ))
 }
 
+#[get("/go/user/tokens")]
+pub fn list_user_tokens(user: User, pool: &State<DbPool>) -> Result<Template, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let tokens = UserToken::of(&user, &mut conn)?;
+
+    Ok(Template::render(
+        "tokens",
+        json!({
+            "mail":  &user.mail,
+            "context": json!({
+                "tokens": tokens
+            }).to_string()
+        }),
+    ))
+}
+
+#[get("/go/user/sessions")]
+pub fn list_user_sessions(
+    user: User,
+    session_id: Option<SessionId>,
+    sessions: &State<Sessions>,
+    pool: &State<DbPool>,
+) -> Result<Template, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+    let active_sessions = sessions.of(&user, session_id.as_ref(), &mut conn)?;
+
+    Ok(Template::render(
+        "sessions",
+        json!({
+            "mail":  &user.mail,
+            "context": json!({
+                "sessions": active_sessions
+            }).to_string()
+        }),
+    ))
+}
+
+#[get("/go/user/password")]
+pub fn user_password(
+    user: User,
+    config: &State<AppConfig>,
+    pool: &State<DbPool>,
+) -> Result<Template, (Status, Value)> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    Ok(Template::render(
+        "password",
+        json!({
+            "mail":  &user.mail,
+            "context": json!({
+                "simple_salt": config.simple_login_salt1,
+                "has_pwd": User::has_pwd(&user.mail, &mut conn)?,
+                "pwd_is_temporary": User::has_temporary_pwd(&user.mail, &mut conn)?,
+                "has_totp": User::has_totp(&user.mail, &mut conn)?,
+                "totp_required": second_factor_policy(&mut conn)?.required,
+            }).to_string()
+        }),
+    ))
+}
+
 #[put("/go/users/<mail>/capabilities/<capability>")]
 pub fn put_user_capability(
     mail: String,
@@ -169,24 +183,7 @@ pub fn put_user_capability(
 
     let mut conn = pool.get().map_err(AppError::from)?;
 
-    user.should_have_capability(Capability::UsersAdmin)?;
-
-    let user: User = dsl::users
-        .select(SAFE_USER_COLUMNS)
-        .find(&mail)
-        .first(&mut conn)
-        .map_err(AppError::from)?;
-
-    let mut capabilities = user.capabilities;
-    if !capabilities.contains(&capability) {
-        capabilities.push(capability);
-        diesel::update(dsl::users.find(&mail))
-            .set(dsl::capabilities.eq(capabilities))
-            .execute(&mut conn)
-            .map_err(AppError::from)?;
-    } else {
-        warn!("User {mail} already has capability {capability}");
-    }
+    User::add_capability(&mail, capability, &user, &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -203,26 +200,7 @@ pub fn delete_user_capability(
 
     let mut conn = pool.get().map_err(AppError::from)?;
 
-    user.should_have_capability(Capability::UsersAdmin)?;
-
-    let user: User = dsl::users
-        .select(SAFE_USER_COLUMNS)
-        .find(&mail)
-        .first(&mut conn)
-        .map_err(AppError::from)?;
-
-    let mut capabilities = user.capabilities;
-    if capabilities.contains(&capability) {
-        capabilities.retain(|&c| c != capability);
-        diesel::update(dsl::users.find(&mail))
-            .set(dsl::capabilities.eq(capabilities))
-            .execute(&mut conn)
-            .map_err(AppError::from)?;
-    } else {
-        warn!(
-            "User {mail} already does not have capability {capability}"
-        );
-    }
+    User::remove_capability(&mail, capability, &user, &mut conn)?;
 
     Ok(Status::Ok)
 }
@@ -239,6 +217,26 @@ impl<'r> FromRequest<'r> for User {
             .map_error(|_| AppError::Guard.into());
         let pool = try_outcome!(pool);
 
+        if let Some(secret) = bearer_token(req.headers()) {
+            return match get_token_user(secret, pool) {
+                Ok(user) => Outcome::Success(user),
+                Err(err) => Outcome::Error(err.into()),
+            };
+        }
+
+        let conf: Outcome<&State<AppConfig>, Self::Error> = req
+            .guard::<&State<AppConfig>>()
+            .await
+            .map_error(|_| AppError::Guard.into());
+        let conf = try_outcome!(conf);
+
+        if let Some(mail) = conf.proxy_auth.as_ref().and_then(|proxy_auth| proxy_auth.mail(req)) {
+            return match get_proxy_user(&mail, pool) {
+                Ok(user) => Outcome::Success(user),
+                Err(err) => Outcome::Error(err.into()),
+            };
+        }
+
         let sessions: Outcome<&State<Sessions>, Self::Error> = req
             .guard::<&State<Sessions>>()
             .await
@@ -254,6 +252,21 @@ impl<'r> FromRequest<'r> for User {
     }
 }
 
+fn get_token_user(secret: &str, pool: &State<DbPool>) -> Result<User, AppError> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    UserToken::authenticate(secret, &mut conn)?.ok_or_else(|| {
+        error!("Unknown personal token.");
+        AppError::Unauthorized
+    })
+}
+
+fn get_proxy_user(mail: &str, pool: &State<DbPool>) -> Result<User, AppError> {
+    let mut conn = pool.get().map_err(AppError::from)?;
+
+    User::from_proxy(mail, &mut conn)
+}
+
 fn get_user(
     session_id: &SessionId,
     sessions: &State<Sessions>,
@@ -261,7 +274,7 @@ fn get_user(
 ) -> Result<User, AppError> {
     let mut conn = pool.get().map_err(AppError::from)?;
 
-    match sessions.is_logged_in(&session_id.0) {
+    match sessions.is_logged_in(&session_id.0, &mut conn)? {
         None => {
             error!("Wrong session_id.");
             Err(AppError::Unauthorized)
@@ -1,9 +1,25 @@
+mod anonymous;
+mod claim_rules;
 mod controller;
+mod passwords;
+mod proxy_auth;
+mod pwd_resets;
+mod registrations;
 mod sessions;
+mod tokens;
+mod totp;
 
+pub use anonymous::*;
+pub use claim_rules::*;
 pub use controller::*;
+pub use passwords::*;
+pub use proxy_auth::*;
+pub use pwd_resets::*;
+pub use registrations::*;
 use diesel::{dsl::{count}, prelude::*};
 pub use sessions::*;
+pub use tokens::*;
+pub use totp::*;
 
 use crate::teams::{ Team, TeamCapability};
 use crate::errors::AppError;
@@ -11,20 +27,37 @@ use crate::schema::users;
 use crate::{schema::*, DbConn};
 use diesel::{deserialize, serialize, Associations, Identifiable, Insertable, PgArrayExpressionMethods};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::Write;
 use std::str::FromStr;
 use std::vec;
 use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
 
 pub type SafeColumns = (users::mail, users::capabilities);
 
 pub const SAFE_USER_COLUMNS: SafeColumns = (users::mail, users::capabilities);
 
-#[derive(Queryable, Identifiable, Serialize, Debug, PartialEq)]
+#[derive(Identifiable, Serialize, ToSchema, Debug, PartialEq)]
 #[diesel(table_name = users, primary_key(mail))]
 pub struct User {
     pub mail: String,
     pub capabilities: Vec<Capability>,
+    /// Of the personal token used to authenticate, `None` for sessions and unscoped tokens
+    #[serde(skip)]
+    pub scopes: Option<Vec<TokenScope>>,
+}
+
+impl Queryable<(diesel::sql_types::Text, diesel::sql_types::Array<diesel::sql_types::Text>), diesel::pg::Pg> for User {
+    type Row = (String, Vec<Capability>);
+
+    fn build((mail, capabilities): Self::Row) -> deserialize::Result<Self> {
+        Ok(User {
+            mail,
+            capabilities,
+            scopes: None,
+        })
+    }
 }
 
 #[derive(Insertable, Queryable, Identifiable, Debug)]
@@ -35,7 +68,7 @@ pub struct UserWithPwd {
     pub capabilities: Vec<Capability>,
 }
 
-#[derive(Identifiable, Queryable, Associations, Insertable, PartialEq, Debug, Serialize, Eq)]
+#[derive(Identifiable, Queryable, Associations, Insertable, PartialEq, Debug, Serialize, ToSchema, Eq)]
 #[diesel(table_name = users_teams, primary_key(user_mail, team_slug), belongs_to(Team, foreign_key = team_slug),belongs_to(User, foreign_key = user_mail))]
 pub struct UserTeam {
     pub user_mail: String,
@@ -57,6 +90,7 @@ pub struct UserTeam {
     EnumString,
     AsExpression,
     Display,
+    ToSchema,
 )]
 #[diesel(sql_type = diesel::sql_types::Text)]
 pub enum Capability {
@@ -125,6 +159,8 @@ impl User {
         conn: &mut DbConn,
         capability: TeamCapability,
     ) -> Result<(), AppError> {
+        self.should_have_scope(TokenScope::of_team_capability(capability))?;
+
         if users_teams::table
             .find((&self.mail, &team_slug))
             .filter(users_teams::capabilities.contains(vec![capability]))
@@ -168,10 +204,90 @@ impl User {
     }
 
 
+    /// Every user, by mail
+    pub fn all(conn: &mut DbConn) -> Result<Vec<User>, AppError> {
+        db::all(conn).map_err(AppError::from)
+    }
+
+    /// Ask to join the team, accepted right away unless the team is private
+    pub fn join_team(&self, team_slug: &str, rank: i16, conn: &mut DbConn) -> Result<UserTeam, AppError> {
+        self.should_have_capability(Capability::UsersTeamsWrite)?;
+
+        let team = db::find_team(team_slug, conn)?.ok_or(AppError::NotFound)?;
+
+        db::add_user_team(
+            UserTeam {
+                user_mail: self.mail.clone(),
+                team_slug: team.slug,
+                capabilities: vec![TeamCapability::ShortcutsWrite],
+                is_accepted: !team.is_private,
+                rank,
+            },
+            conn,
+        )
+        .map_err(AppError::from)
+    }
+
+    pub fn leave_team(&self, team_slug: &str, conn: &mut DbConn) -> Result<(), AppError> {
+        self.should_have_capability(Capability::UsersTeamsWrite)?;
+
+        db::remove_user_team(&self.mail, team_slug, conn)
+            .map_err(AppError::from)
+            .map(|_| ())
+    }
+
+    /// All or nothing, `team_ranks` are by team slug
+    pub fn set_team_ranks(&self, team_ranks: HashMap<String, u16>, conn: &mut DbConn) -> Result<(), AppError> {
+        self.should_have_capability(Capability::UsersTeamsWrite)?;
+
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            for (slug, rank) in team_ranks {
+                if let Err(e) = db::update_user_team_rank(&self.mail, &slug, rank as i16, conn) {
+                    error!(
+                        "Team rank update failed for {} rank {}, rollback transaction: {:?}",
+                        slug, rank, e
+                    );
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+            }
+            Ok(())
+        })
+        .map_err(AppError::from)
+    }
+
+    pub fn add_capability(mail: &str, capability: Capability, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
+        user.should_have_capability(Capability::UsersAdmin)?;
+
+        let mut capabilities = db::find(mail, conn)?.ok_or(AppError::NotFound)?.capabilities;
+        if !capabilities.contains(&capability) {
+            capabilities.push(capability);
+            db::update_capabilities(mail, capabilities, conn)?;
+        } else {
+            warn!("User {mail} already has capability {capability}");
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_capability(mail: &str, capability: Capability, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
+        user.should_have_capability(Capability::UsersAdmin)?;
+
+        let mut capabilities = db::find(mail, conn)?.ok_or(AppError::NotFound)?.capabilities;
+        if capabilities.contains(&capability) {
+            capabilities.retain(|&c| c != capability);
+            db::update_capabilities(mail, capabilities, conn)?;
+        } else {
+            warn!("User {mail} already does not have capability {capability}");
+        }
+
+        Ok(())
+    }
+
     pub fn fake_admin() -> Self {
         Self {
             mail: "fake_admin".to_string(),
             capabilities: Capability::all(),
+            scopes: None,
         }
     }
 
@@ -180,6 +296,38 @@ impl User {
 
     pub fn have_capability(&self, capability: Capability) -> bool {
         self.capabilities.contains(&capability)
+            && match TokenScope::of_capability(capability) {
+                Some(scope) => self.have_scope(scope),
+                None => self.scopes.is_none(),
+            }
+    }
+
+    /// Always for sessions and unscoped tokens
+    pub fn have_scope(&self, scope: TokenScope) -> bool {
+        match &self.scopes {
+            None => true,
+            Some(scopes) => scope == TokenScope::Read || scopes.contains(&scope),
+        }
+    }
+
+    pub fn should_have_scope(&self, scope: TokenScope) -> Result<(), AppError> {
+        if self.have_scope(scope) {
+            Ok(())
+        } else {
+            error!("User {} token miss scope {}", self.mail, scope);
+            Err(AppError::Unauthorized)
+        }
+    }
+
+    /// For what no scope covers, e.g. managing the tokens themselves
+    pub fn should_be_unscoped(&self) -> Result<(), AppError> {
+        match &self.scopes {
+            None => Ok(()),
+            Some(scopes) => {
+                error!("User {} token is restricted to {:?}", self.mail, scopes);
+                Err(AppError::Unauthorized)
+            }
+        }
     }
 
     pub fn should_have_capability(&self, capability: Capability) -> Result<(), AppError> {
@@ -197,7 +345,7 @@ impl User {
     ) -> Result<(), AppError> {
         if capabilities
             .iter()
-            .any(|capability| self.capabilities.contains(capability))
+            .any(|capability| self.have_capability(*capability))
         {
             Ok(())
         } else {
@@ -215,7 +363,7 @@ impl User {
     ) -> Result<(), AppError> {
         if capabilities
             .iter()
-            .all(|capability| self.capabilities.contains(capability))
+            .all(|capability| self.have_capability(*capability))
         {
             Ok(())
         } else {
@@ -228,6 +376,60 @@ impl User {
     }
 }
 
+mod db {
+    use super::*;
+
+    pub fn all(conn: &mut DbConn) -> Result<Vec<User>, diesel::result::Error> {
+        users::table
+            .select(SAFE_USER_COLUMNS)
+            .order_by(users::mail)
+            .load::<User>(conn)
+    }
+
+    pub fn find(mail: &str, conn: &mut DbConn) -> Result<Option<User>, diesel::result::Error> {
+        users::table
+            .select(SAFE_USER_COLUMNS)
+            .find(mail)
+            .first(conn)
+            .optional()
+    }
+
+    pub fn update_capabilities(
+        mail: &str,
+        capabilities: Vec<Capability>,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::update(users::table.find(mail))
+            .set(users::capabilities.eq(capabilities))
+            .execute(conn)
+    }
+
+    pub fn find_team(slug: &str, conn: &mut DbConn) -> Result<Option<Team>, diesel::result::Error> {
+        teams::table.find(slug).first(conn).optional()
+    }
+
+    pub fn add_user_team(user_team: UserTeam, conn: &mut DbConn) -> Result<UserTeam, diesel::result::Error> {
+        diesel::insert_into(users_teams::table)
+            .values(user_team)
+            .get_result(conn)
+    }
+
+    pub fn remove_user_team(mail: &str, slug: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(users_teams::table.find((mail, slug))).execute(conn)
+    }
+
+    pub fn update_user_team_rank(
+        mail: &str,
+        slug: &str,
+        rank: i16,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::update(users_teams::table.find((mail, slug)))
+            .set(users_teams::rank.eq(rank))
+            .execute(conn)
+    }
+}
+
 impl Capability {
     pub fn all() -> Vec<Capability> {
         vec![
@@ -0,0 +1,206 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use subtle::ConstantTimeEq;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::schema::users;
+use crate::users::{Capability, User};
+use crate::DbConn;
+
+/// Argon2id cost of the stored passwords, the defaults are the OWASP recommended ones
+#[derive(Clone, Copy, Debug)]
+pub struct PasswordHashing {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Passwords are sent hashed by the browser with the simple login salt
+#[derive(Deserialize, ToSchema)]
+pub struct PwdChange {
+    /// Required when the user already has a password
+    pub current_pwd: Option<String>,
+    pub new_pwd: String,
+}
+
+/// Shown once to the admin, to give to the user
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct TemporaryPwd {
+    pub pwd: String,
+}
+
+enum Verified {
+    No,
+    Yes,
+    /// Matching, but hashed with another algorithm or cost
+    Outdated,
+}
+
+impl PasswordHashing {
+    fn argon2(&self) -> Result<Argon2<'static>, AppError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    /// PHC string holding a random salt and the cost used
+    pub fn hash(&self, pwd: &str) -> Result<String, AppError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2()?.hash_password(pwd.as_bytes(), &salt)?;
+
+        Ok(hash.to_string())
+    }
+
+    /// `stored` is either a PHC string or the legacy `sha256(pwd + legacy_salt)`
+    fn verify(&self, pwd: &str, stored: &str, legacy_salt: &str) -> Result<Verified, AppError> {
+        let Ok(hash) = PasswordHash::new(stored) else {
+            let legacy = digest(format!("{pwd}{legacy_salt}"));
+            if bool::from(legacy.as_bytes().ct_eq(stored.as_bytes())) {
+                return Ok(Verified::Outdated);
+            }
+            return Ok(Verified::No);
+        };
+
+        if Argon2::default().verify_password(pwd.as_bytes(), &hash).is_err() {
+            return Ok(Verified::No);
+        }
+
+        let up_to_date = hash.algorithm == Algorithm::Argon2id.ident()
+            && Params::try_from(&hash).is_ok_and(|params| {
+                params.m_cost() == self.memory_kib
+                    && params.t_cost() == self.iterations
+                    && params.p_cost() == self.parallelism
+            });
+        if up_to_date {
+            Ok(Verified::Yes)
+        } else {
+            Ok(Verified::Outdated)
+        }
+    }
+
+    /// Whether `pwd` is the one of `mail`, its hash is upgraded when outdated
+    pub fn check(&self, mail: &str, pwd: &str, legacy_salt: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        let Some(stored) = db::pwd(mail, conn)? else {
+            // Same work as a wrong password, not to tell unknown users apart
+            self.hash(pwd)?;
+            return Ok(false);
+        };
+
+        match self.verify(pwd, &stored, legacy_salt)? {
+            Verified::No => Ok(false),
+            Verified::Yes => Ok(true),
+            Verified::Outdated => {
+                db::upgrade_pwd(mail, &self.hash(pwd)?, conn)?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl User {
+    pub fn change_pwd(
+        &self,
+        change: PwdChange,
+        hashing: &PasswordHashing,
+        legacy_salt: &str,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        self.should_be_unscoped()?;
+
+        if change.new_pwd.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+        if let Some(stored) = db::pwd(&self.mail, conn)? {
+            let current = change.current_pwd.unwrap_or_default();
+            if let Verified::No = hashing.verify(&current, &stored, legacy_salt)? {
+                return Err(AppError::Unauthorized);
+            }
+        }
+
+        db::set_pwd(&self.mail, &hashing.hash(&change.new_pwd)?, false, conn)?;
+        Ok(())
+    }
+
+    /// Random password for `mail`, who should change it once logged in with it
+    pub fn set_temporary_pwd(
+        mail: &str,
+        user: &User,
+        hashing: &PasswordHashing,
+        login_salt: &str,
+        conn: &mut DbConn,
+    ) -> Result<TemporaryPwd, AppError> {
+        user.should_have_capability(Capability::UsersAdmin)?;
+
+        let pwd = Uuid::new_v4().simple().to_string()[..16].to_string();
+        // What the login page will send once the user types it
+        let sent = digest(format!("{pwd}{login_salt}"));
+
+        match db::set_pwd(mail, &hashing.hash(&sent)?, true, conn)? {
+            0 => Err(AppError::NotFound),
+            _ => Ok(TemporaryPwd { pwd }),
+        }
+    }
+
+    /// Users only logging in with an identity provider have none
+    pub fn has_pwd(mail: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        Ok(db::pwd(mail, conn)?.is_some())
+    }
+
+    pub fn has_temporary_pwd(mail: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        db::is_temporary(mail, conn).map_err(AppError::from)
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn pwd(mail: &str, conn: &mut DbConn) -> Result<Option<String>, diesel::result::Error> {
+        users::table
+            .find(mail)
+            .select(users::pwd)
+            .first::<Option<String>>(conn)
+            .optional()
+            .map(Option::flatten)
+    }
+
+    /// Same password hashed again, still temporary if it was
+    pub fn upgrade_pwd(mail: &str, pwd: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::update(users::table.find(mail))
+            .set(users::pwd.eq(pwd))
+            .execute(conn)
+    }
+
+    pub fn set_pwd(
+        mail: &str,
+        pwd: &str,
+        is_temporary: bool,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::update(users::table.find(mail))
+            .set((users::pwd.eq(pwd), users::pwd_is_temporary.eq(is_temporary)))
+            .execute(conn)
+    }
+
+    pub fn is_temporary(mail: &str, conn: &mut DbConn) -> Result<bool, diesel::result::Error> {
+        users::table
+            .find(mail)
+            .select(users::pwd_is_temporary)
+            .first(conn)
+    }
+}
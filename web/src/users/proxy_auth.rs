@@ -0,0 +1,96 @@
+use diesel::prelude::*;
+use ipnet::IpNet;
+use rocket::Request;
+use std::net::IpAddr;
+
+use crate::errors::AppError;
+use crate::schema::users;
+use crate::settings::default_capabilities;
+use crate::users::{User, UserWithPwd, MAIL_REGEX};
+use crate::DbConn;
+
+/// Users authenticated by a reverse proxy in front of the app, like oauth2-proxy or Pomerium,
+/// which tells their mail in `header`
+#[derive(Clone, Debug)]
+pub struct ProxyAuth {
+    /// Like `X-Forwarded-Email`
+    pub header: String,
+    /// Addresses of the proxies, the header is ignored on requests coming from elsewhere
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl ProxyAuth {
+    /// Parse the proxies, like `10.0.0.1,172.16.0.0/12`
+    pub fn new(header: &str, trusted_proxies: &str) -> Result<Self, String> {
+        let trusted_proxies = trusted_proxies
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("{proxy} is neither an IP address nor a network"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            header: header.to_string(),
+            trusted_proxies,
+        })
+    }
+
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|proxy| proxy.contains(&addr))
+    }
+
+    /// The mail told by the proxy, `None` when the request didn't come through it.
+    /// The peer address is checked, not the forwarded ones anybody can send
+    pub fn mail(&self, req: &Request) -> Option<String> {
+        let mail = req.headers().get_one(&self.header)?;
+
+        match req.remote() {
+            Some(remote) if self.is_trusted(remote.ip()) => Some(mail.trim().to_lowercase()),
+            remote => {
+                warn!("Ignore {} from the untrusted {:?}", self.header, remote);
+                None
+            }
+        }
+    }
+}
+
+impl User {
+    /// Who the proxy vouches for, registered with the default capabilities on their first request
+    pub fn from_proxy(mail: &str, conn: &mut DbConn) -> Result<User, AppError> {
+        if !MAIL_REGEX.is_match(mail) {
+            error!("The proxy sent the invalid mail {mail}");
+            return Err(AppError::Unauthorized);
+        }
+
+        if let Some(user) = super::db::find(mail, conn)? {
+            return Ok(user);
+        }
+
+        let capabilities = default_capabilities(conn)?;
+        db::insert(mail, capabilities, conn)?;
+
+        super::db::find(mail, conn)?.ok_or(AppError::Db)
+    }
+}
+
+mod db {
+    use super::*;
+    use crate::users::Capability;
+
+    /// Without password, as the proxy logs them in. Concurrent first requests register them once
+    pub fn insert(mail: &str, capabilities: Vec<Capability>, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(users::table)
+            .values(UserWithPwd {
+                mail: mail.to_string(),
+                pwd: None,
+                capabilities,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)
+    }
+}
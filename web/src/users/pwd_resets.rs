@@ -0,0 +1,137 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use serde::Deserialize;
+use sha256::digest;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::schema::pwd_resets;
+use crate::services::mailer::{Mail, Mailer};
+use crate::users::{PasswordHashing, User};
+use crate::DbConn;
+
+/// How long a mailed link can be used
+pub const PWD_RESET_VALIDITY: TimeDelta = TimeDelta::hours(1);
+
+/// Links mailed to an address during `PWD_RESET_VALIDITY`, not to flood its mailbox
+const MAX_PWD_RESETS: i64 = 3;
+
+#[derive(Deserialize, ToSchema)]
+pub struct ForgottenPwd {
+    pub mail: String,
+}
+
+/// `pwd` is sent hashed by the browser with the simple login salt
+#[derive(Deserialize, ToSchema)]
+pub struct PwdReset {
+    pub token: String,
+    pub pwd: String,
+}
+
+/// Tokens of the mailed links are simple UUIDs, 32 lowercase hex characters
+pub fn is_pwd_reset_token(token: &str) -> bool {
+    token.len() == 32 && token.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl User {
+    /// Mail a single use reset link to `mail`, whether it is a user or not is never told
+    pub fn send_pwd_reset(
+        mail: &str,
+        public_url: &str,
+        mailer: &Mailer,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        if !db::user_exists(mail, conn)? {
+            return Ok(());
+        }
+
+        let now = Utc::now().naive_utc();
+        if db::count_since(mail, now - PWD_RESET_VALIDITY, conn)? >= MAX_PWD_RESETS {
+            warn!("Too many password resets for {mail}");
+            return Ok(());
+        }
+
+        let token = Uuid::new_v4().simple().to_string();
+        db::insert(&digest(token.as_str()), mail, now, conn)?;
+
+        mailer.send(&Mail {
+            to: mail.to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "To choose a new password, open this link within the hour:\n\n\
+                {public_url}/go/password/reset?token={token}\n\n\
+                If you didn't ask for it, just ignore this mail."
+            ),
+        })?;
+        Ok(())
+    }
+
+    /// Set the password of the user the link was mailed to, returns their mail
+    pub fn reset_pwd(reset: PwdReset, hashing: &PasswordHashing, conn: &mut DbConn) -> Result<String, AppError> {
+        if reset.pwd.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+
+        let now = Utc::now().naive_utc();
+        let Some(mail) = db::use_token(&digest(reset.token.as_str()), now, now - PWD_RESET_VALIDITY, conn)? else {
+            return Err(AppError::BadRequest);
+        };
+
+        db::set_pwd(&mail, &hashing.hash(&reset.pwd)?, conn)?;
+        Ok(mail)
+    }
+}
+
+mod db {
+    use super::*;
+    use crate::schema::users;
+
+    pub fn user_exists(mail: &str, conn: &mut DbConn) -> Result<bool, diesel::result::Error> {
+        diesel::select(diesel::dsl::exists(users::table.find(mail))).get_result(conn)
+    }
+
+    pub fn count_since(mail: &str, since: NaiveDateTime, conn: &mut DbConn) -> Result<i64, diesel::result::Error> {
+        pwd_resets::table
+            .filter(pwd_resets::user_mail.eq(mail))
+            .filter(pwd_resets::created_at.gt(since))
+            .count()
+            .get_result(conn)
+    }
+
+    pub fn insert(hash: &str, mail: &str, now: NaiveDateTime, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(pwd_resets::table)
+            .values((
+                pwd_resets::hash.eq(hash),
+                pwd_resets::user_mail.eq(mail),
+                pwd_resets::created_at.eq(now),
+            ))
+            .execute(conn)
+    }
+
+    /// No longer temporary, the user chose it
+    pub fn set_pwd(mail: &str, pwd: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::update(users::table.find(mail))
+            .set((users::pwd.eq(pwd), users::pwd_is_temporary.eq(false)))
+            .execute(conn)
+    }
+
+    /// Marks the token as used in the same query, so it can't be used twice
+    pub fn use_token(
+        hash: &str,
+        now: NaiveDateTime,
+        created_after: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<Option<String>, diesel::result::Error> {
+        diesel::update(
+            pwd_resets::table
+                .find(hash)
+                .filter(pwd_resets::used_at.is_null())
+                .filter(pwd_resets::created_at.gt(created_after)),
+        )
+        .set(pwd_resets::used_at.eq(now))
+        .returning(pwd_resets::user_mail)
+        .get_result(conn)
+        .optional()
+    }
+}
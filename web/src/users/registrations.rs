@@ -0,0 +1,160 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use sha256::digest;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::schema::{pending_registrations, users};
+use crate::services::mailer::{Mail, Mailer};
+use crate::settings::{default_capabilities, Registration};
+use crate::users::{PasswordHashing, User, UserWithPwd, SAFE_USER_COLUMNS};
+use crate::DbConn;
+
+/// How long a mailed confirmation link can be used
+pub const REGISTRATION_VALIDITY: TimeDelta = TimeDelta::hours(24);
+
+/// Links mailed to an address during `REGISTRATION_VALIDITY`, not to flood its mailbox
+const MAX_REGISTRATIONS: i64 = 3;
+
+impl User {
+    /// Mail a link to confirm `mail` is the one of whoever registers, the user is only created once it is opened
+    pub fn register(
+        mail: &str,
+        pwd: &str,
+        registration: &Registration,
+        hashing: &PasswordHashing,
+        public_url: &str,
+        mailer: &Mailer,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        if !registration.enabled {
+            return Err(AppError::Disable);
+        }
+        if !registration.allows(mail) {
+            return Err(AppError::Unauthorized);
+        }
+        if pwd.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+        if db::user_exists(mail, conn)? {
+            return Err(AppError::Conflict);
+        }
+
+        let now = Utc::now().naive_utc();
+        if db::count_since(mail, now - REGISTRATION_VALIDITY, conn)? >= MAX_REGISTRATIONS {
+            warn!("Too many registrations of {mail}");
+            return Err(AppError::TooManyRequests);
+        }
+
+        let token = Uuid::new_v4().simple().to_string();
+        db::insert(&digest(token.as_str()), mail, &hashing.hash(pwd)?, now, conn)?;
+
+        mailer.send(&Mail {
+            to: mail.to_string(),
+            subject: "Confirm your registration".to_string(),
+            body: format!(
+                "To finish your registration, open this link within a day:\n\n\
+                {public_url}/go/register/confirm?token={token}\n\n\
+                If you didn't register, just ignore this mail."
+            ),
+        })?;
+        Ok(())
+    }
+
+    /// New simple login user with the default capabilities, from the token of a mailed link
+    pub fn confirm_registration(
+        token: &str,
+        registration: &Registration,
+        conn: &mut DbConn,
+    ) -> Result<User, AppError> {
+        let now = Utc::now().naive_utc();
+        conn.transaction(|conn| {
+            let Some((mail, pwd)) = db::take(&digest(token), now - REGISTRATION_VALIDITY, conn)? else {
+                return Err(AppError::BadRequest);
+            };
+            // Settings may have changed since the link was mailed
+            if !registration.enabled {
+                return Err(AppError::Disable);
+            }
+            if !registration.allows(&mail) {
+                return Err(AppError::Unauthorized);
+            }
+            if db::user_exists(&mail, conn)? {
+                return Err(AppError::Conflict);
+            }
+
+            let capabilities = default_capabilities(conn)?;
+            db::insert_user(
+                UserWithPwd {
+                    mail,
+                    pwd: Some(pwd),
+                    capabilities,
+                },
+                conn,
+            )
+            .map_err(AppError::from)
+        })
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn user_exists(mail: &str, conn: &mut DbConn) -> Result<bool, diesel::result::Error> {
+        diesel::select(diesel::dsl::exists(users::table.find(mail))).get_result(conn)
+    }
+
+    pub fn count_since(mail: &str, since: NaiveDateTime, conn: &mut DbConn) -> Result<i64, diesel::result::Error> {
+        pending_registrations::table
+            .filter(pending_registrations::mail.eq(mail))
+            .filter(pending_registrations::created_at.gt(since))
+            .count()
+            .get_result(conn)
+    }
+
+    pub fn insert(
+        hash: &str,
+        mail: &str,
+        pwd: &str,
+        now: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(pending_registrations::table)
+            .values((
+                pending_registrations::hash.eq(hash),
+                pending_registrations::mail.eq(mail),
+                pending_registrations::pwd.eq(pwd),
+                pending_registrations::created_at.eq(now),
+            ))
+            .execute(conn)
+    }
+
+    /// The mail and password registered with the token, the other links mailed to it can't be used anymore
+    pub fn take(
+        hash: &str,
+        created_after: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<Option<(String, String)>, diesel::result::Error> {
+        let registered: Option<(String, String)> = diesel::delete(
+            pending_registrations::table
+                .find(hash)
+                .filter(pending_registrations::created_at.gt(created_after)),
+        )
+        .returning((pending_registrations::mail, pending_registrations::pwd))
+        .get_result(conn)
+        .optional()?;
+
+        if let Some((mail, _)) = &registered {
+            diesel::delete(pending_registrations::table.filter(pending_registrations::mail.eq(mail)))
+                .execute(conn)?;
+        }
+        Ok(registered)
+    }
+
+    pub fn insert_user(user: UserWithPwd, conn: &mut DbConn) -> Result<User, diesel::result::Error> {
+        diesel::insert_into(users::table)
+            .values(user)
+            .returning(SAFE_USER_COLUMNS)
+            .get_result(conn)
+    }
+}
@@ -1,38 +1,471 @@
-use std::{collections::HashMap, sync::Mutex};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use rocket::tokio::{
+    task::spawn_blocking,
+    time::{interval_at, Instant},
+};
+use serde::Serialize;
+use sha256::digest;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use utoipa::ToSchema;
 
+use crate::errors::AppError;
+use crate::guards::SessionId;
+use crate::schema::sessions;
+use crate::users::{Capability, User};
+use crate::{DbConn, DbPool};
 
-#[derive(Default)]
+const PURGE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// How long a session lives without being seen, and at most since it started
+#[derive(Clone, Copy, Debug)]
+pub struct SessionExpiry {
+    pub idle: TimeDelta,
+    pub absolute: TimeDelta,
+}
+
+impl Default for SessionExpiry {
+    fn default() -> Self {
+        Self {
+            idle: TimeDelta::days(7),
+            absolute: TimeDelta::days(30),
+        }
+    }
+}
+
+impl SessionExpiry {
+    fn is_expired(&self, created_at: NaiveDateTime, last_seen_at: NaiveDateTime, now: NaiveDateTime) -> bool {
+        last_seen_at <= now - self.idle || created_at <= now - self.absolute
+    }
+}
+
+/// Who started a session, as told by its login request
+#[derive(Clone, Default, Debug)]
+pub struct SessionClient {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Queryable, Serialize, ToSchema, Clone, PartialEq, Eq, Debug)]
+pub struct ActiveSession {
+    /// Hash of the session id, enough to revoke it but not to use it
+    pub id: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// The session of the request
+    pub current: bool,
+}
+
+/// Where the sessions live, the value is the user mail or the OIDC nonce while logging in
+pub trait SessionStore: Send + Sync {
+    /// Starts the session, restarting it if it already exists
+    fn put(&self, session_id: &str, value: &str, client: &SessionClient, conn: &mut DbConn) -> Result<(), AppError>;
+
+    /// Value of the session if it is not expired, marking it as seen
+    fn get(&self, session_id: &str, conn: &mut DbConn) -> Result<Option<String>, AppError>;
+
+    fn delete(&self, session_id: &str, conn: &mut DbConn) -> Result<(), AppError>;
+
+    /// Sessions not expired with this value, last seen first
+    fn of(&self, value: &str, conn: &mut DbConn) -> Result<Vec<ActiveSession>, AppError>;
+
+    /// Delete the session with this [`ActiveSession::id`] if it has this value
+    fn revoke(&self, value: &str, id: &str, conn: &mut DbConn) -> Result<usize, AppError>;
+
+    /// Delete every session with this value, but the `except` one
+    fn revoke_all(&self, value: &str, except: Option<&str>, conn: &mut DbConn) -> Result<usize, AppError>;
+
+    /// Number of expired sessions removed
+    fn purge_expired(&self, conn: &mut DbConn) -> Result<usize, AppError>;
+}
+
+#[derive(Clone)]
 pub struct Sessions {
-    sessions: Mutex<HashMap<String, String>>,
+    store: Arc<dyn SessionStore>,
 }
 
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions::in_memory(SessionExpiry::default())
+    }
+}
 
 impl Sessions {
-    pub fn put(&self, session_id: &str, mail: &str) {
-        let mut sessions = match self.sessions.lock() {
-            Ok(s) => s,
-            Err(s) => s.into_inner(),
-        };
-        sessions.insert(session_id.to_string(), mail.to_string());
+    /// Lost on restart and not shared between instances, fit for tests
+    pub fn in_memory(expiry: SessionExpiry) -> Self {
+        Self {
+            store: Arc::new(MemorySessions {
+                expiry,
+                sessions: Mutex::default(),
+            }),
+        }
     }
 
-    pub fn is_logged_in(&self, session_id: &str) -> Option<String> {
-        let sessions = match self.sessions.lock() {
-            Ok(s) => s,
-            Err(s) => s.into_inner(),
-        };
+    pub fn postgres(expiry: SessionExpiry) -> Self {
+        Self {
+            store: Arc::new(PgSessions { expiry }),
+        }
+    }
+
+    pub fn put(&self, session_id: &str, value: &str, client: &SessionClient, conn: &mut DbConn) -> Result<(), AppError> {
+        self.store.put(session_id, value, client, conn)
+    }
+
+    pub fn is_logged_in(&self, session_id: &str, conn: &mut DbConn) -> Result<Option<String>, AppError> {
+        self.store.get(session_id, conn)
+    }
+
+    pub fn delete(&self, session_id: &str, conn: &mut DbConn) -> Result<(), AppError> {
+        self.store.delete(session_id, conn)
+    }
+
+    pub fn purge_expired(&self, conn: &mut DbConn) -> Result<usize, AppError> {
+        self.store.purge_expired(conn)
+    }
+
+    pub fn of(&self, user: &User, current: Option<&SessionId>, conn: &mut DbConn) -> Result<Vec<ActiveSession>, AppError> {
+        user.should_be_unscoped()?;
 
-        sessions.get(session_id).cloned()
+        let current = current.map(|session_id| digest(session_id.0.as_str()));
+        let mut sessions = self.store.of(&user.mail, conn)?;
+        for session in sessions.iter_mut() {
+            session.current = current.as_ref() == Some(&session.id);
+        }
+        Ok(sessions)
+    }
+
+    pub fn revoke(&self, id: &str, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
+        user.should_be_unscoped()?;
+
+        match self.store.revoke(&user.mail, id, conn)? {
+            0 => Err(AppError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// Log `mail` out everywhere
+    pub fn revoke_all_of(&self, mail: &str, user: &User, conn: &mut DbConn) -> Result<usize, AppError> {
+        user.should_have_capability(Capability::UsersAdmin)?;
+
+        self.end_all_of(mail, None, conn)
+    }
+
+    /// When `mail` changes of password, the session doing it can be kept
+    pub fn end_all_of(&self, mail: &str, except: Option<&SessionId>, conn: &mut DbConn) -> Result<usize, AppError> {
+        self.store
+            .revoke_all(mail, except.map(|session_id| session_id.0.as_str()), conn)
+    }
+
+    /// Remove expired sessions every [`PURGE_PERIOD`] in the background
+    pub fn purge_periodically(&self, pool: DbPool) {
+        let sessions = self.clone();
+        rocket::tokio::spawn(async move {
+            let mut period = interval_at(Instant::now() + PURGE_PERIOD, PURGE_PERIOD);
+            loop {
+                period.tick().await;
+                let sessions = sessions.clone();
+                let pool = pool.clone();
+                let _ = spawn_blocking(move || sessions.purge_with(&pool)).await;
+            }
+        });
+    }
+
+    fn purge_with(&self, pool: &DbPool) {
+        let purged = pool
+            .get()
+            .map_err(AppError::from)
+            .and_then(|mut conn| self.purge_expired(&mut conn));
+        match purged {
+            Ok(0) => {}
+            Ok(count) => info!("{count} expired sessions purged."),
+            Err(e) => error!("Sessions purge failed: {:?}", e),
+        }
     }
 }
 
+/// Sessions started now, as `session_id: mail` YAML
 impl From<&str> for Sessions {
     fn from(sessions: &str) -> Self {
         if sessions.is_empty() {
             return Sessions::default();
         }
+        let now = Utc::now().naive_utc();
+        let sessions: HashMap<String, String> = serde_yaml::from_str(sessions).unwrap();
+
         Self {
-            sessions: serde_yaml::from_str(sessions).unwrap(),
+            store: Arc::new(MemorySessions {
+                expiry: SessionExpiry::default(),
+                sessions: Mutex::new(
+                    sessions
+                        .into_iter()
+                        .map(|(session_id, value)| {
+                            (
+                                session_id,
+                                Session {
+                                    value,
+                                    created_at: now,
+                                    last_seen_at: now,
+                                    client: SessionClient::default(),
+                                },
+                            )
+                        })
+                        .collect(),
+                ),
+            }),
+        }
+    }
+}
+
+struct Session {
+    value: String,
+    created_at: NaiveDateTime,
+    last_seen_at: NaiveDateTime,
+    client: SessionClient,
+}
+
+struct MemorySessions {
+    expiry: SessionExpiry,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl MemorySessions {
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        match self.sessions.lock() {
+            Ok(s) => s,
+            Err(s) => s.into_inner(),
+        }
+    }
+}
+
+impl SessionStore for MemorySessions {
+    fn put(&self, session_id: &str, value: &str, client: &SessionClient, _conn: &mut DbConn) -> Result<(), AppError> {
+        let now = Utc::now().naive_utc();
+        self.sessions().insert(
+            session_id.to_string(),
+            Session {
+                value: value.to_string(),
+                created_at: now,
+                last_seen_at: now,
+                client: client.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    fn get(&self, session_id: &str, _conn: &mut DbConn) -> Result<Option<String>, AppError> {
+        let now = Utc::now().naive_utc();
+        let mut sessions = self.sessions();
+
+        match sessions.get_mut(session_id) {
+            Some(session) if !self.expiry.is_expired(session.created_at, session.last_seen_at, now) => {
+                session.last_seen_at = now;
+                Ok(Some(session.value.clone()))
+            }
+            _ => Ok(None),
         }
     }
-}
\ No newline at end of file
+
+    fn delete(&self, session_id: &str, _conn: &mut DbConn) -> Result<(), AppError> {
+        self.sessions().remove(session_id);
+        Ok(())
+    }
+
+    fn of(&self, value: &str, _conn: &mut DbConn) -> Result<Vec<ActiveSession>, AppError> {
+        let now = Utc::now().naive_utc();
+
+        let mut sessions: Vec<ActiveSession> = self
+            .sessions()
+            .iter()
+            .filter(|(_, session)| session.value == value)
+            .filter(|(_, session)| !self.expiry.is_expired(session.created_at, session.last_seen_at, now))
+            .map(|(session_id, session)| ActiveSession {
+                id: digest(session_id.as_str()),
+                created_at: session.created_at,
+                last_seen_at: session.last_seen_at,
+                user_agent: session.client.user_agent.clone(),
+                ip: session.client.ip.clone(),
+                current: false,
+            })
+            .collect();
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+        Ok(sessions)
+    }
+
+    fn revoke(&self, value: &str, id: &str, _conn: &mut DbConn) -> Result<usize, AppError> {
+        let mut sessions = self.sessions();
+
+        let before = sessions.len();
+        sessions.retain(|session_id, session| session.value != value || digest(session_id.as_str()) != id);
+        Ok(before - sessions.len())
+    }
+
+    fn revoke_all(&self, value: &str, except: Option<&str>, _conn: &mut DbConn) -> Result<usize, AppError> {
+        let mut sessions = self.sessions();
+
+        let before = sessions.len();
+        sessions.retain(|session_id, session| session.value != value || Some(session_id.as_str()) == except);
+        Ok(before - sessions.len())
+    }
+
+    fn purge_expired(&self, _conn: &mut DbConn) -> Result<usize, AppError> {
+        let now = Utc::now().naive_utc();
+        let mut sessions = self.sessions();
+
+        let before = sessions.len();
+        sessions.retain(|_, session| !self.expiry.is_expired(session.created_at, session.last_seen_at, now));
+        Ok(before - sessions.len())
+    }
+}
+
+/// Shared by every instance and kept across restarts, only the hash of the session ids is stored
+struct PgSessions {
+    expiry: SessionExpiry,
+}
+
+impl SessionStore for PgSessions {
+    fn put(&self, session_id: &str, value: &str, client: &SessionClient, conn: &mut DbConn) -> Result<(), AppError> {
+        db::upsert(&digest(session_id), value, client, Utc::now().naive_utc(), conn)?;
+        Ok(())
+    }
+
+    fn get(&self, session_id: &str, conn: &mut DbConn) -> Result<Option<String>, AppError> {
+        let now = Utc::now().naive_utc();
+        db::touch(
+            &digest(session_id),
+            now,
+            now - self.expiry.idle,
+            now - self.expiry.absolute,
+            conn,
+        )
+        .map_err(AppError::from)
+    }
+
+    fn delete(&self, session_id: &str, conn: &mut DbConn) -> Result<(), AppError> {
+        db::delete(&digest(session_id), conn)?;
+        Ok(())
+    }
+
+    fn of(&self, value: &str, conn: &mut DbConn) -> Result<Vec<ActiveSession>, AppError> {
+        let now = Utc::now().naive_utc();
+        db::of(value, now - self.expiry.idle, now - self.expiry.absolute, conn).map_err(AppError::from)
+    }
+
+    fn revoke(&self, value: &str, id: &str, conn: &mut DbConn) -> Result<usize, AppError> {
+        db::revoke(value, id, conn).map_err(AppError::from)
+    }
+
+    fn revoke_all(&self, value: &str, except: Option<&str>, conn: &mut DbConn) -> Result<usize, AppError> {
+        db::revoke_all(value, &digest(except.unwrap_or_default()), conn).map_err(AppError::from)
+    }
+
+    fn purge_expired(&self, conn: &mut DbConn) -> Result<usize, AppError> {
+        let now = Utc::now().naive_utc();
+        db::delete_expired(now - self.expiry.idle, now - self.expiry.absolute, conn).map_err(AppError::from)
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn upsert(
+        id: &str,
+        value: &str,
+        client: &SessionClient,
+        now: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        let values = (
+            sessions::value.eq(value),
+            sessions::created_at.eq(now),
+            sessions::last_seen_at.eq(now),
+            sessions::user_agent.eq(&client.user_agent),
+            sessions::ip.eq(&client.ip),
+        );
+        diesel::insert_into(sessions::table)
+            .values((sessions::id.eq(id), values))
+            .on_conflict(sessions::id)
+            .do_update()
+            .set(values)
+            .execute(conn)
+    }
+
+    /// Sessions seen before `idle_limit` or created before `absolute_limit` are expired
+    pub fn touch(
+        id: &str,
+        now: NaiveDateTime,
+        idle_limit: NaiveDateTime,
+        absolute_limit: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<Option<String>, diesel::result::Error> {
+        diesel::update(sessions::table.find(id))
+            .filter(sessions::last_seen_at.gt(idle_limit))
+            .filter(sessions::created_at.gt(absolute_limit))
+            .set(sessions::last_seen_at.eq(now))
+            .returning(sessions::value)
+            .get_result(conn)
+            .optional()
+    }
+
+    pub fn delete(id: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(sessions::table.find(id)).execute(conn)
+    }
+
+    pub fn of(
+        value: &str,
+        idle_limit: NaiveDateTime,
+        absolute_limit: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<Vec<ActiveSession>, diesel::result::Error> {
+        sessions::table
+            .filter(sessions::value.eq(value))
+            .filter(sessions::last_seen_at.gt(idle_limit))
+            .filter(sessions::created_at.gt(absolute_limit))
+            .select((
+                sessions::id,
+                sessions::created_at,
+                sessions::last_seen_at,
+                sessions::user_agent,
+                sessions::ip,
+                false.into_sql::<diesel::sql_types::Bool>(),
+            ))
+            .order(sessions::last_seen_at.desc())
+            .load::<ActiveSession>(conn)
+    }
+
+    pub fn revoke(value: &str, id: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(sessions::table.find(id))
+            .filter(sessions::value.eq(value))
+            .execute(conn)
+    }
+
+    pub fn revoke_all(value: &str, except: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(
+            sessions::table
+                .filter(sessions::value.eq(value))
+                .filter(sessions::id.ne(except)),
+        )
+        .execute(conn)
+    }
+
+    pub fn delete_expired(
+        idle_limit: NaiveDateTime,
+        absolute_limit: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::delete(
+            sessions::table.filter(
+                sessions::last_seen_at
+                    .le(idle_limit)
+                    .or(sessions::created_at.le(absolute_limit)),
+            ),
+        )
+        .execute(conn)
+    }
+}
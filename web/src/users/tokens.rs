@@ -0,0 +1,261 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{deserialize, prelude::*, serialize};
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use std::{io::Write, str::FromStr};
+use strum_macros::{Display, EnumString};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::schema::{user_tokens, users};
+use crate::teams::TeamCapability;
+use crate::users::{Capability, User, SAFE_USER_COLUMNS};
+use crate::DbConn;
+
+/// Prefix of the secrets, so they are easy to spot in scripts and logs
+const TOKEN_PREFIX: &str = "go_";
+
+pub type TokenColumns = (
+    user_tokens::id,
+    user_tokens::user_mail,
+    user_tokens::name,
+    user_tokens::scopes,
+    user_tokens::created_at,
+    user_tokens::last_used_at,
+);
+
+pub const TOKEN_COLUMNS: TokenColumns = (
+    user_tokens::id,
+    user_tokens::user_mail,
+    user_tokens::name,
+    user_tokens::scopes,
+    user_tokens::created_at,
+    user_tokens::last_used_at,
+);
+
+/// Personal access token, sent as `Authorization: Bearer <secret>`, only its hash is stored
+#[derive(Queryable, Serialize, ToSchema, PartialEq, Eq, Debug)]
+pub struct UserToken {
+    pub id: i32,
+    pub user_mail: String,
+    pub name: String,
+    /// Empty to act with all the rights of the user
+    pub scopes: Vec<TokenScope>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// Returned once on creation, the secret can't be found again
+#[derive(Serialize, ToSchema, Debug)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    pub token: UserToken,
+    pub secret: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewToken {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<TokenScope>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_tokens)]
+struct NewUserToken<'a> {
+    user_mail: &'a str,
+    name: &'a str,
+    hash: String,
+    scopes: &'a [TokenScope],
+    created_at: NaiveDateTime,
+}
+
+/// What a scoped token can do, every scope can read what its user can read
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    FromSqlRow,
+    EnumString,
+    AsExpression,
+    Display,
+    ToSchema,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum TokenScope {
+    Read,
+    ShortcutsWrite,
+    /// Teams and their members
+    TeamsAdmin,
+}
+
+impl TokenScope {
+    /// `None` when only sessions and unscoped tokens can use `capability`
+    pub fn of_capability(capability: Capability) -> Option<TokenScope> {
+        match capability {
+            Capability::UsersTeamsRead => Some(TokenScope::Read),
+            Capability::TeamsWrite
+            | Capability::TeamsCreateWithValidation
+            | Capability::UsersTeamsWrite => Some(TokenScope::TeamsAdmin),
+            Capability::Features | Capability::UsersAdmin => None,
+        }
+    }
+
+    pub fn of_team_capability(capability: TeamCapability) -> TokenScope {
+        match capability {
+            TeamCapability::ShortcutsWrite => TokenScope::ShortcutsWrite,
+            TeamCapability::TeamsWrite => TokenScope::TeamsAdmin,
+        }
+    }
+}
+
+impl UserToken {
+    /// Newest first
+    pub fn of(user: &User, conn: &mut DbConn) -> Result<Vec<UserToken>, AppError> {
+        user.should_be_unscoped()?;
+
+        db::of(&user.mail, conn).map_err(AppError::from)
+    }
+
+    pub fn create(new_token: NewToken, user: &User, conn: &mut DbConn) -> Result<CreatedToken, AppError> {
+        user.should_be_unscoped()?;
+
+        let name = new_token.name.trim();
+        if name.is_empty() {
+            return Err(AppError::BadRequest);
+        }
+        if db::find_by_name(&user.mail, name, conn)?.is_some() {
+            return Err(AppError::Conflict);
+        }
+
+        let mut scopes = new_token.scopes;
+        scopes.sort();
+        scopes.dedup();
+
+        let secret = format!(
+            "{TOKEN_PREFIX}{}{}",
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let token = db::insert(
+            NewUserToken {
+                user_mail: &user.mail,
+                name,
+                hash: digest(secret.as_str()),
+                scopes: &scopes,
+                created_at: Utc::now().naive_utc(),
+            },
+            conn,
+        )?;
+
+        Ok(CreatedToken { token, secret })
+    }
+
+    pub fn revoke(id: i32, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
+        user.should_be_unscoped()?;
+
+        match db::delete(id, &user.mail, conn)? {
+            0 => Err(AppError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// The user owning `secret`, restricted to the token scopes
+    pub fn authenticate(secret: &str, conn: &mut DbConn) -> Result<Option<User>, AppError> {
+        if !secret.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+
+        let token = match db::use_hash(&digest(secret), conn)? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        let mut user = db::user(&token.user_mail, conn)?;
+        if !token.scopes.is_empty() {
+            user.scopes = Some(token.scopes);
+        }
+        Ok(Some(user))
+    }
+}
+
+mod db {
+    use super::*;
+
+    pub fn of(mail: &str, conn: &mut DbConn) -> Result<Vec<UserToken>, diesel::result::Error> {
+        user_tokens::table
+            .filter(user_tokens::user_mail.eq(mail))
+            .select(TOKEN_COLUMNS)
+            .order(user_tokens::id.desc())
+            .load::<UserToken>(conn)
+    }
+
+    pub fn find_by_name(
+        mail: &str,
+        name: &str,
+        conn: &mut DbConn,
+    ) -> Result<Option<UserToken>, diesel::result::Error> {
+        user_tokens::table
+            .filter(user_tokens::user_mail.eq(mail))
+            .filter(user_tokens::name.eq(name))
+            .select(TOKEN_COLUMNS)
+            .first::<UserToken>(conn)
+            .optional()
+    }
+
+    pub fn insert(new_token: NewUserToken, conn: &mut DbConn) -> Result<UserToken, diesel::result::Error> {
+        diesel::insert_into(user_tokens::table)
+            .values(new_token)
+            .returning(TOKEN_COLUMNS)
+            .get_result(conn)
+    }
+
+    pub fn delete(id: i32, mail: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(user_tokens::table.find(id))
+            .filter(user_tokens::user_mail.eq(mail))
+            .execute(conn)
+    }
+
+    /// Also records the use
+    pub fn use_hash(hash: &str, conn: &mut DbConn) -> Result<Option<UserToken>, diesel::result::Error> {
+        diesel::update(user_tokens::table)
+            .filter(user_tokens::hash.eq(hash))
+            .set(user_tokens::last_used_at.eq(Utc::now().naive_utc()))
+            .returning(TOKEN_COLUMNS)
+            .get_result(conn)
+            .optional()
+    }
+
+    pub fn user(mail: &str, conn: &mut DbConn) -> Result<User, diesel::result::Error> {
+        users::table
+            .find(mail)
+            .select(SAFE_USER_COLUMNS)
+            .first::<User>(conn)
+    }
+}
+
+impl deserialize::FromSql<diesel::sql_types::Text, diesel::pg::Pg> for TokenScope {
+    fn from_sql(bytes: <diesel::pg::Pg as diesel::backend::Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let s: String =
+            deserialize::FromSql::<diesel::sql_types::Text, diesel::pg::Pg>::from_sql(bytes)?;
+        let r = TokenScope::from_str(&s)?;
+        Ok(r)
+    }
+}
+
+impl serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg> for TokenScope
+where
+    String: serialize::ToSql<diesel::sql_types::Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.to_string().as_bytes())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
@@ -0,0 +1,414 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+use sha256::digest;
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::schema::{recovery_codes, second_factor_failures, second_factor_logins, totps};
+use crate::settings::SecondFactorPolicy;
+use crate::users::{Capability, User};
+use crate::DbConn;
+
+/// Shown by authenticators next to the mail of the user
+pub const TOTP_ISSUER: &str = "go";
+
+/// How long the code of a password login can be posted
+pub const SECOND_FACTOR_VALIDITY: TimeDelta = TimeDelta::minutes(5);
+
+/// Codes posted for a login before it has to start over, not to let them be guessed
+const MAX_SECOND_FACTOR_ATTEMPTS: i16 = 5;
+
+/// Wrong codes a user can post over `SECOND_FACTOR_FAILURES_WINDOW`, whatever the login they were posted for
+const MAX_SECOND_FACTOR_FAILURES: i64 = 10;
+
+const SECOND_FACTOR_FAILURES_WINDOW: TimeDelta = TimeDelta::minutes(15);
+
+const RECOVERY_CODES: usize = 10;
+
+/// Seconds a code is shown by authenticators
+const TOTP_STEP: u64 = 30;
+
+/// What to add to an authenticator, which then shows the codes confirming the enrollment
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct TotpEnrollment {
+    /// Base32, for authenticators without a camera
+    pub secret: String,
+    /// `otpauth://totp/...`
+    pub provisioning_uri: String,
+    /// SVG image of the provisioning URI
+    pub qr_code: String,
+}
+
+/// Shown by the authenticator, or one of the recovery codes
+#[derive(Deserialize, ToSchema)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// Each one can replace a code once, when the authenticator is lost
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Posted after the password, with the token the login returned
+#[derive(Deserialize, ToSchema)]
+pub struct SecondFactorCode {
+    pub token: String,
+    pub code: String,
+}
+
+impl User {
+    /// Whether logging in with a password takes a code too
+    pub fn has_totp(mail: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        Ok(db::find(mail, conn)?.is_some_and(|totp| totp.enabled))
+    }
+
+    /// New secret for `mail`, replacing one which wasn't confirmed
+    pub fn enroll_totp(mail: &str, conn: &mut DbConn) -> Result<TotpEnrollment, AppError> {
+        if User::has_totp(mail, conn)? {
+            return Err(AppError::Conflict);
+        }
+
+        let secret = Secret::generate_secret().to_encoded().to_string();
+        let provisioning_uri = totp(&secret, mail)?.get_url();
+        let qr_code = QrCode::new(provisioning_uri.as_bytes())
+            .map_err(|e| {
+                error!("Can't encode the provisioning URI of {mail}: {e}");
+                AppError::ServiceError
+            })?
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build();
+
+        db::upsert(mail, &secret, conn)?;
+        Ok(TotpEnrollment {
+            secret,
+            provisioning_uri,
+            qr_code,
+        })
+    }
+
+    /// Enable the enrolled authenticator once it shows a valid code, the recovery codes are only given once
+    pub fn confirm_totp(mail: &str, code: &str, conn: &mut DbConn) -> Result<RecoveryCodes, AppError> {
+        let Some(enrolled) = db::find(mail, conn)? else {
+            return Err(AppError::NotFound);
+        };
+        if enrolled.enabled {
+            return Err(AppError::Conflict);
+        }
+        let Some(step) = matching_step(&totp(&enrolled.secret, mail)?, code) else {
+            return Err(AppError::BadRequest);
+        };
+
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| {
+                let code = Uuid::new_v4().simple().to_string();
+                format!("{}-{}", &code[..5], &code[5..10])
+            })
+            .collect();
+        let hashes: Vec<String> = recovery_codes.iter().map(|code| recovery_hash(code)).collect();
+
+        conn.transaction(|conn| {
+            db::enable(mail, step, conn)?;
+            db::replace_recovery_codes(mail, &hashes, conn)
+        })?;
+
+        Ok(RecoveryCodes { recovery_codes })
+    }
+
+    /// Stop asking codes to the user, with one of them. Refused when 2FA is required
+    pub fn disable_totp(
+        &self,
+        code: &str,
+        policy: &SecondFactorPolicy,
+        conn: &mut DbConn,
+    ) -> Result<(), AppError> {
+        self.should_be_unscoped()?;
+
+        if policy.required {
+            return Err(AppError::Conflict);
+        }
+        if !User::has_totp(&self.mail, conn)? {
+            return Err(AppError::NotFound);
+        }
+        if !User::check_second_factor(&self.mail, code, conn)? {
+            return Err(AppError::Unauthorized);
+        }
+
+        db::delete(&self.mail, conn)?;
+        Ok(())
+    }
+
+    /// For users who lost their authenticator and recovery codes, they enroll a new one on their next login
+    /// when 2FA is required
+    pub fn reset_totp(mail: &str, user: &User, conn: &mut DbConn) -> Result<(), AppError> {
+        user.should_have_capability(Capability::UsersAdmin)?;
+
+        match db::delete(mail, conn)? {
+            0 => Err(AppError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// A code of the authenticator, never accepted twice, or a recovery code which is then used up
+    pub fn check_second_factor(mail: &str, code: &str, conn: &mut DbConn) -> Result<bool, AppError> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(db::use_recovery_code(mail, &recovery_hash(&code), conn)? > 0);
+        }
+
+        let Some(enabled) = db::find(mail, conn)?.filter(|totp| totp.enabled) else {
+            return Ok(false);
+        };
+        let Some(step) = matching_step(&totp(&enabled.secret, mail)?, &code) else {
+            return Ok(false);
+        };
+        // Only newer steps, so a code seen over a shoulder can't be used again
+        Ok(db::use_step(mail, step, conn)? > 0)
+    }
+
+    /// Once the password of `mail` is checked, returns the token to post along the code
+    pub fn start_second_factor(mail: &str, conn: &mut DbConn) -> Result<String, AppError> {
+        let now = Utc::now().naive_utc();
+        db::delete_logins_before(mail, now - SECOND_FACTOR_VALIDITY, conn)?;
+
+        let token = Uuid::new_v4().simple().to_string();
+        db::insert_login(&digest(token.as_str()), mail, now, conn)?;
+        Ok(token)
+    }
+
+    /// Returns the mail the login was started for once the code is right, along the recovery codes
+    /// when it confirmed the enrollment of the authenticator.
+    /// Too many wrong codes for the user lock all of its logins for a while, `TooManyRequests`
+    pub fn finish_second_factor(
+        second_factor: &SecondFactorCode,
+        conn: &mut DbConn,
+    ) -> Result<(String, Option<RecoveryCodes>), AppError> {
+        let hash = digest(second_factor.token.as_str());
+        let now = Utc::now().naive_utc();
+        let Some(mail) = db::attempt_login(&hash, now - SECOND_FACTOR_VALIDITY, conn)? else {
+            return Err(AppError::BadRequest);
+        };
+        if db::count_failures_since(&mail, now - SECOND_FACTOR_FAILURES_WINDOW, conn)? >= MAX_SECOND_FACTOR_FAILURES {
+            warn!("Too many wrong codes for {mail}");
+            return Err(AppError::TooManyRequests);
+        }
+
+        let recovery_codes = if User::has_totp(&mail, conn)? {
+            if !User::check_second_factor(&mail, &second_factor.code, conn)? {
+                db::insert_failure(&mail, now, conn)?;
+                return Err(AppError::Unauthorized);
+            }
+            None
+        } else {
+            match User::confirm_totp(&mail, &second_factor.code, conn) {
+                Ok(recovery_codes) => Some(recovery_codes),
+                Err(AppError::BadRequest) => {
+                    db::insert_failure(&mail, now, conn)?;
+                    return Err(AppError::Unauthorized);
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        db::delete_login(&hash, conn)?;
+        db::delete_failures(&mail, conn)?;
+        Ok((mail, recovery_codes))
+    }
+}
+
+fn totp(secret: &str, mail: &str) -> Result<TOTP, AppError> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().map_err(|e| {
+        error!("Invalid TOTP secret of {mail}: {e:?}");
+        AppError::Db
+    })?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        TOTP_STEP,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        mail.to_string(),
+    )
+    .map_err(|e| {
+        error!("Can't make the TOTP of {mail}: {e:?}");
+        AppError::Db
+    })
+}
+
+/// The time step of `code`, the clock of the authenticator being at most a step off. `None` when wrong
+fn matching_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let now = Utc::now().timestamp() as u64 / TOTP_STEP;
+
+    [now - 1, now, now + 1]
+        .into_iter()
+        .find(|step| bool::from(totp.generate(step * TOTP_STEP).as_bytes().ct_eq(code.as_bytes())))
+        .map(|step| step as i64)
+}
+
+/// Recovery codes are typed with or without their dash
+fn recovery_hash(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    digest(code)
+}
+
+mod db {
+    use super::*;
+
+    #[derive(Queryable)]
+    pub struct Totp {
+        pub secret: String,
+        pub enabled: bool,
+    }
+
+    pub fn find(mail: &str, conn: &mut DbConn) -> Result<Option<Totp>, diesel::result::Error> {
+        totps::table
+            .find(mail)
+            .select((totps::secret, totps::enabled))
+            .first(conn)
+            .optional()
+    }
+
+    /// Not enabled until confirmed
+    pub fn upsert(mail: &str, secret: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(totps::table)
+            .values((totps::user_mail.eq(mail), totps::secret.eq(secret)))
+            .on_conflict(totps::user_mail)
+            .do_update()
+            .set((totps::secret.eq(secret), totps::enabled.eq(false), totps::last_step.eq(0)))
+            .execute(conn)
+    }
+
+    pub fn enable(mail: &str, step: i64, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::update(totps::table.find(mail))
+            .set((totps::enabled.eq(true), totps::last_step.eq(step)))
+            .execute(conn)
+    }
+
+    /// Along the recovery codes
+    pub fn delete(mail: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        conn.transaction(|conn| {
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_mail.eq(mail))).execute(conn)?;
+            diesel::delete(totps::table.find(mail)).execute(conn)
+        })
+    }
+
+    /// Checked and set in the same query, so concurrent logins can't use a step twice
+    pub fn use_step(mail: &str, step: i64, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::update(
+            totps::table
+                .find(mail)
+                .filter(totps::enabled)
+                .filter(totps::last_step.lt(step)),
+        )
+        .set(totps::last_step.eq(step))
+        .execute(conn)
+    }
+
+    pub fn replace_recovery_codes(
+        mail: &str,
+        hashes: &[String],
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_mail.eq(mail))).execute(conn)?;
+        diesel::insert_into(recovery_codes::table)
+            .values(
+                hashes
+                    .iter()
+                    .map(|hash| (recovery_codes::hash.eq(hash), recovery_codes::user_mail.eq(mail)))
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)
+    }
+
+    pub fn use_recovery_code(mail: &str, hash: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(
+            recovery_codes::table
+                .find(hash)
+                .filter(recovery_codes::user_mail.eq(mail)),
+        )
+        .execute(conn)
+    }
+
+    pub fn delete_logins_before(
+        mail: &str,
+        before: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<usize, diesel::result::Error> {
+        diesel::delete(
+            second_factor_logins::table
+                .filter(second_factor_logins::user_mail.eq(mail))
+                .filter(second_factor_logins::created_at.le(before)),
+        )
+        .execute(conn)
+    }
+
+    pub fn insert_login(hash: &str, mail: &str, now: NaiveDateTime, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(second_factor_logins::table)
+            .values((
+                second_factor_logins::hash.eq(hash),
+                second_factor_logins::user_mail.eq(mail),
+                second_factor_logins::created_at.eq(now),
+            ))
+            .execute(conn)
+    }
+
+    /// Counts the attempt in the same query, `None` once expired or attempted too many times
+    pub fn attempt_login(
+        hash: &str,
+        created_after: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<Option<String>, diesel::result::Error> {
+        diesel::update(
+            second_factor_logins::table
+                .find(hash)
+                .filter(second_factor_logins::created_at.gt(created_after))
+                .filter(second_factor_logins::attempts.lt(MAX_SECOND_FACTOR_ATTEMPTS)),
+        )
+        .set(second_factor_logins::attempts.eq(second_factor_logins::attempts + 1))
+        .returning(second_factor_logins::user_mail)
+        .get_result(conn)
+        .optional()
+    }
+
+    pub fn delete_login(hash: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(second_factor_logins::table.find(hash)).execute(conn)
+    }
+
+    pub fn insert_failure(mail: &str, now: NaiveDateTime, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(second_factor_failures::table)
+            .values((
+                second_factor_failures::user_mail.eq(mail),
+                second_factor_failures::failed_at.eq(now),
+            ))
+            .execute(conn)
+    }
+
+    pub fn count_failures_since(
+        mail: &str,
+        since: NaiveDateTime,
+        conn: &mut DbConn,
+    ) -> Result<i64, diesel::result::Error> {
+        second_factor_failures::table
+            .filter(second_factor_failures::user_mail.eq(mail))
+            .filter(second_factor_failures::failed_at.gt(since))
+            .count()
+            .get_result(conn)
+    }
+
+    pub fn delete_failures(mail: &str, conn: &mut DbConn) -> Result<usize, diesel::result::Error> {
+        diesel::delete(second_factor_failures::table.filter(second_factor_failures::user_mail.eq(mail))).execute(conn)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    shortcuts::Shortcut,teams::{Team, TeamWithUserLinks},users::User};
+    shortcuts::{Shortcut, ShortcutAlias, ShortcutStats},teams::{Team, TeamWithUserLinks},users::User};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -11,4 +11,11 @@ pub struct IndexContext {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub team: Option<TeamWithUserLinks>,
     pub teams: Vec<Team>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<ShortcutStats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<ShortcutAlias>,
+    /// Known names looking like the one not found
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
 }
@@ -0,0 +1,69 @@
+use go_web::api::API_BASE;
+use regex::Regex;
+use rocket::http::Status;
+use std::fs;
+mod utils;
+use serde_json::Value;
+use utils::*;
+
+const CHECKED_IN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+fn served(client: &rocket::local::blocking::Client) -> Value {
+    let response = client.get("/go/api/v1/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<Value>().unwrap()
+}
+
+/// Run with `UPDATE_OPENAPI=1` to write the new document after changing the API
+#[test]
+fn is_the_checked_in_one() {
+    let (client, _conn) = launch_with("");
+    let document = served(&client);
+
+    if std::env::var("UPDATE_OPENAPI").is_ok() {
+        fs::write(CHECKED_IN, serde_json::to_string_pretty(&document).unwrap() + "\n").unwrap();
+    }
+
+    let checked_in: Value = serde_json::from_str(&fs::read_to_string(CHECKED_IN).unwrap()).unwrap();
+    assert!(
+        document == checked_in,
+        "openapi.json is outdated, update it with `UPDATE_OPENAPI=1 cargo test --test api_openapi`"
+    );
+}
+
+#[test]
+fn documents_every_api_route() {
+    let (client, _conn) = launch_with("");
+    let document = served(&client);
+    let param = Regex::new(r"<([a-z_]+)(\.\.)?>").unwrap();
+
+    for route in client.rocket().routes() {
+        let path = route.uri.path().to_string();
+        if !path.starts_with(API_BASE) || path.ends_with("/openapi.json") {
+            continue;
+        }
+
+        let path = param.replace_all(&path, "{$1}");
+        let method = route.method.as_str().to_lowercase();
+        assert!(
+            document["paths"][path.as_ref()][&method].is_object(),
+            "{method} {path} is missing from the OpenAPI document"
+        );
+    }
+}
+
+#[test]
+fn accepts_personal_tokens_as_bearer() {
+    let (client, _conn) = launch_with("");
+    let document = served(&client);
+
+    let scheme = &document["components"]["securitySchemes"]["personal_token"];
+    assert_eq!(scheme["type"], "http");
+    assert_eq!(scheme["scheme"], "bearer");
+    assert!(document["security"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|requirement| requirement.get("personal_token").is_some()));
+}
@@ -0,0 +1,192 @@
+use go_web::guards::SESSION_COOKIE;
+use go_web::teams::TeamCapability;
+use go_web::users::Capability;
+use rocket::http::ContentType;
+use rocket::http::Cookie;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::{json, Value};
+use utils::*;
+
+fn get(client: &Client, path: &str) -> (Status, Value) {
+    let response = client
+        .get(path)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    (response.status(), response.into_json::<Value>().unwrap_or_default())
+}
+
+fn names(page: &Value) -> Vec<&str> {
+    page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["shortcut"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn as_unknown_user_is_unauthorized_with_json_error() {
+    let (client, _conn) = launch_with("");
+
+    let response = client.get("/go/api/v1/shortcuts").dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        response.into_json::<Value>(),
+        Some(json!({"error": "Should you really be there ?"}))
+    );
+}
+
+#[test]
+fn list_shortcuts_paginated_and_searched() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", true, true, &mut conn);
+    shortcut("docs", "http://localhost/docs", "", &mut conn);
+    shortcut("jira", "http://localhost/jira", "", &mut conn);
+    shortcut("wiki", "http://localhost/wiki", "", &mut conn);
+    shortcut("private", "http://localhost/private", "slug1", &mut conn);
+    user("some_mail@mail.com", "pwd", &[("", &[], 0, true)], &[], &mut conn);
+
+    let (status, page) = get(&client, "/go/api/v1/shortcuts?page=2&per_page=2");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(names(&page), vec!["wiki"]);
+    assert_eq!((page["page"].as_u64(), page["total"].as_u64()), (Some(2), Some(3)));
+
+    let (_, page) = get(&client, "/go/api/v1/shortcuts?q=JIR");
+    assert_eq!(names(&page), vec!["jira"]);
+
+    let (status, body) = get(&client, "/go/api/v1/shortcuts?per_page=0");
+    assert_eq!(status, Status::BadRequest);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn get_put_and_delete_a_shortcut() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("slug1", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .put("/go/api/v1/shortcuts/docs/api?team=slug1")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost/docs", "tags": ["Dev"], "aliases": ["api"]}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let saved = response.into_json::<Value>().unwrap();
+    assert_eq!(saved["shortcut"], "docs/api");
+    assert_eq!(saved["created_by"], "some_mail@mail.com");
+    assert_eq!(saved["tags"], json!(["dev"]));
+    assert_eq!(saved["aliases"], json!(["api"]));
+
+    let (status, found) = get(&client, "/go/api/v1/shortcuts/docs/api?team=slug1");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(found, saved);
+
+    let (status, _) = get(&client, "/go/api/v1/shortcuts/docs/api");
+    assert_eq!(status, Status::NotFound);
+
+    let response = client
+        .put("/go/api/v1/shortcuts/other?team=slug1")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "not an url"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .delete("/go/api/v1/shortcuts/docs/api?team=slug1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(get_shortcut_with_team("docs/api", "slug1", &mut conn).is_none());
+}
+
+#[test]
+fn manage_teams_and_memberships() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user("some_mail@mail.com", "pwd", &[], &[Capability::TeamsWrite], &mut conn);
+    user("other_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+    let response = client
+        .post("/go/api/v1/teams")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"slug": "slug1", "title": "team1", "is_private": false}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(
+        response.into_json::<Value>(),
+        Some(json!({"slug": "slug1", "title": "team1", "is_private": false, "is_accepted": true}))
+    );
+
+    let response = client
+        .patch("/go/api/v1/teams/slug1")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"title": "renamed"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(get_team("slug1", &mut conn).unwrap().title, "renamed");
+
+    let response = client
+        .put("/go/api/v1/teams/slug1/users/other_mail@mail.com/capabilities/TeamsWrite")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let (status, team) = get(&client, "/go/api/v1/teams/slug1");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(team["user_links"][0]["user_mail"], "some_mail@mail.com");
+
+    let response = client
+        .delete("/go/api/v1/teams/slug1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(get_team("slug1", &mut conn).is_none());
+}
+
+#[test]
+fn join_team_and_change_user_capabilities() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", true, true, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[],
+        &[Capability::UsersTeamsWrite],
+        &mut conn,
+    );
+
+    let response = client
+        .post("/go/api/v1/user/teams?team=slug1")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"rank": 3}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    let user_team = response.into_json::<Value>().unwrap();
+    assert_eq!(user_team["is_accepted"], false);
+    assert_eq!(user_team["rank"], 3);
+
+    let response = client
+        .put("/go/api/v1/users/some_mail@mail.com/capabilities/UsersAdmin")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let (status, users) = get(&client, "/go/api/v1/users");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(users["items"][0]["mail"], "some_mail@mail.com");
+    assert_eq!(users["items"][0]["capabilities"], json!(["UsersTeamsWrite"]));
+}
@@ -80,7 +80,7 @@ async fn with_no_redirect_return_search_and_edit_form_filled() {
 
                 assert_eq!(
                     screen
-                        .find(ByExt::role("combobox"))
+                        .find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string())))
                         .await?
                         .prop("value")
                         .await?
@@ -405,7 +405,7 @@ mod create_shortcut {
                         .click()
                         .await?;
 
-                    let input = screen.find(ByExt::role("combobox")).await?;
+                    let input = screen.find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string()))).await?;
                     let select = SelectElement::new(&input).await.unwrap();
                     for opt in select.options().await?.iter() {
                         assert_ne!(opt.text().await?, "team")
@@ -447,7 +447,7 @@ mod create_shortcut {
                         .click()
                         .await?;
 
-                    let input = screen.find(ByExt::role("combobox")).await?;
+                    let input = screen.find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string()))).await?;
                     let select = SelectElement::new(&input).await.unwrap();
                     for opt in select.options().await?.iter() {
                         assert_ne!(opt.text().await?, "team")
@@ -492,7 +492,7 @@ mod create_shortcut {
                         .click()
                         .await?;
 
-                    let input = screen.find(ByExt::role("combobox")).await?;
+                    let input = screen.find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string()))).await?;
                     let select = SelectElement::new(&input).await.unwrap();
                     for opt in select.options().await?.iter() {
                         assert_ne!(opt.text().await?, "team")
@@ -537,7 +537,7 @@ mod create_shortcut {
             .await
             .unwrap();
 
-        let input = screen.find(ByExt::role("combobox")).await.unwrap();
+        let input = screen.find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string()))).await.unwrap();
         let select = SelectElement::new(&input).await.unwrap();
         select.select_by_value(team).await.unwrap();
 
@@ -588,7 +588,7 @@ mod create_shortcut {
 
         assert_eq!(
             screen
-                .find(ByExt::role("combobox"))
+                .find(ByExt::role("combobox").name(TextMatch::Exact("Team".to_string())))
                 .await
                 .unwrap()
                 .prop("value")
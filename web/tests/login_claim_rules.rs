@@ -0,0 +1,251 @@
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::schema::users_teams;
+use go_web::teams::TeamCapability;
+use go_web::users::{Capability, UserTeam};
+use rocket::http::{Cookie, Status};
+use rocket::local::blocking::Client;
+use serde_json::{json, Value};
+mod utils;
+use utils::*;
+
+/// Through the mock issuer, with `claims` in the ID token
+fn login_with(mail: &str, claims: Value, client: &Client, issuer: &MockIssuer) -> Status {
+    login_through("keycloak", mail, claims, client, issuer)
+}
+
+fn login_through(provider: &str, mail: &str, claims: Value, client: &Client, issuer: &MockIssuer) -> Status {
+    let response = client.get(format!("/go/login/{provider}")).dispatch();
+    let session_id = response.cookies().get(SESSION_COOKIE).unwrap().value().to_string();
+    let auth_url = response.headers().get_one("Location").unwrap().to_string();
+
+    let state = issuer.authorize_with_claims("code", mail, claims, &auth_url);
+
+    client
+        .get(format!("/go/login/redirect/{provider}?state={state}&code=code"))
+        .cookie(Cookie::new(SESSION_COOKIE, session_id))
+        .dispatch()
+        .status()
+}
+
+fn capabilities(mail: &str, conn: &mut PgConnection) -> Vec<Capability> {
+    get_user(mail, conn).unwrap().capabilities
+}
+
+fn teams(mail: &str, conn: &mut PgConnection) -> Vec<UserTeam> {
+    users_teams::table
+        .filter(users_teams::user_mail.eq(mail))
+        .order_by(users_teams::team_slug)
+        .load(conn)
+        .unwrap()
+}
+
+fn admins_rule() -> Value {
+    json!([{ "claim": "groups", "value": "go-admins", "capabilities": ["UsersAdmin", "Features"] }])
+}
+
+#[test]
+fn grant_capabilities_to_new_users_on_top_of_the_default_ones() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+    default_capabilities(&[Capability::TeamsCreateWithValidation], &mut conn);
+    claim_rules(admins_rule(), &mut conn);
+
+    let claims = json!({ "groups": ["developers", "go-admins"] });
+    assert_eq!(
+        login_with("some_mail@mail.com", claims, &client, &issuer),
+        Status::SeeOther
+    );
+
+    assert_eq!(
+        capabilities("some_mail@mail.com", &mut conn),
+        vec![
+            Capability::TeamsCreateWithValidation,
+            Capability::UsersAdmin,
+            Capability::Features
+        ]
+    );
+}
+
+#[test]
+fn take_back_capabilities_once_the_claim_is_gone() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[],
+        &[Capability::UsersAdmin, Capability::TeamsWrite],
+        &mut conn,
+    );
+    claim_rules(admins_rule(), &mut conn);
+
+    login_with("some_mail@mail.com", json!({ "groups": ["developers"] }), &client, &issuer);
+
+    // Only what rules name is theirs to take back
+    assert_eq!(capabilities("some_mail@mail.com", &mut conn), vec![Capability::TeamsWrite]);
+}
+
+#[test]
+fn match_single_valued_claims_and_providers() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+    claim_rules(
+        json!([
+            { "claim": "hd", "value": "mail.com", "capabilities": ["TeamsCreateWithValidation"] },
+            { "provider": "google", "claim": "hd", "value": "mail.com", "capabilities": ["UsersAdmin"] },
+            { "claim": "email_verified", "value": "true", "capabilities": ["UsersTeamsRead"] },
+        ]),
+        &mut conn,
+    );
+
+    login_with(
+        "some_mail@mail.com",
+        json!({ "hd": "mail.com", "email_verified": true }),
+        &client,
+        &issuer,
+    );
+
+    assert_eq!(
+        capabilities("some_mail@mail.com", &mut conn),
+        vec![Capability::TeamsCreateWithValidation, Capability::UsersTeamsRead]
+    );
+}
+
+#[test]
+fn join_teams_with_their_capabilities() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+    team("ops", "Ops", true, true, &mut conn);
+    team("devs", "Devs", false, true, &mut conn);
+    claim_rules(
+        json!([
+            { "claim": "groups", "value": "ops", "teams": [{ "team": "ops", "capabilities": ["ShortcutsWrite"] }] },
+            { "claim": "groups", "value": "ops-leads", "teams": [{ "team": "ops", "capabilities": ["TeamsWrite"] }] },
+            { "claim": "groups", "value": "devs", "teams": [{ "team": "devs" }, { "team": "unknown" }] },
+        ]),
+        &mut conn,
+    );
+
+    let claims = json!({ "groups": ["ops", "ops-leads", "devs"] });
+    assert_eq!(
+        login_with("some_mail@mail.com", claims, &client, &issuer),
+        Status::SeeOther
+    );
+
+    assert_eq!(
+        teams("some_mail@mail.com", &mut conn),
+        vec![
+            UserTeam {
+                user_mail: "some_mail@mail.com".to_string(),
+                team_slug: "devs".to_string(),
+                capabilities: vec![],
+                is_accepted: true,
+                rank: 0,
+            },
+            UserTeam {
+                user_mail: "some_mail@mail.com".to_string(),
+                team_slug: "ops".to_string(),
+                capabilities: vec![TeamCapability::ShortcutsWrite, TeamCapability::TeamsWrite],
+                is_accepted: true,
+                rank: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn follow_group_changes_on_every_login() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+    team("ops", "Ops", true, true, &mut conn);
+    team("devs", "Devs", false, true, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("ops", &[TeamCapability::ShortcutsWrite], 3, false), ("devs", &[], 1, true)],
+        &[],
+        &mut conn,
+    );
+    claim_rules(
+        json!([
+            { "claim": "groups", "value": "ops", "teams": [{ "team": "ops", "capabilities": ["TeamsWrite"] }] },
+            { "claim": "groups", "value": "devs", "teams": [{ "team": "devs" }] },
+        ]),
+        &mut conn,
+    );
+
+    login_with("some_mail@mail.com", json!({ "groups": ["ops"] }), &client, &issuer);
+
+    assert_eq!(
+        teams("some_mail@mail.com", &mut conn),
+        vec![UserTeam {
+            user_mail: "some_mail@mail.com".to_string(),
+            team_slug: "ops".to_string(),
+            capabilities: vec![TeamCapability::TeamsWrite],
+            is_accepted: true,
+            rank: 3,
+        }]
+    );
+
+    client.post("/go/logout").dispatch();
+    login_with("some_mail@mail.com", json!({ "groups": [] }), &client, &issuer);
+
+    assert_eq!(teams("some_mail@mail.com", &mut conn), vec![]);
+}
+
+#[test]
+fn of_other_providers_take_nothing_back() {
+    let issuer = MockIssuer::start();
+    let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak", "google"])).launch();
+    team("ops", "Ops", true, true, &mut conn);
+    claim_rules(
+        json!([{
+            "provider": "keycloak",
+            "claim": "groups",
+            "value": "go-admins",
+            "capabilities": ["UsersAdmin"],
+            "teams": [{ "team": "ops" }],
+        }]),
+        &mut conn,
+    );
+
+    let claims = json!({ "groups": ["go-admins"] });
+    login_through("keycloak", "some_mail@mail.com", claims, &client, &issuer);
+    assert_eq!(capabilities("some_mail@mail.com", &mut conn), vec![Capability::UsersAdmin]);
+
+    client.post("/go/logout").dispatch();
+    login_through("google", "some_mail@mail.com", json!({}), &client, &issuer);
+
+    assert_eq!(capabilities("some_mail@mail.com", &mut conn), vec![Capability::UsersAdmin]);
+    assert_eq!(teams("some_mail@mail.com", &mut conn).len(), 1);
+}
+
+#[test]
+fn are_put_by_features_admins() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+    let put = |client: &Client| {
+        client
+            .put("/go/settings/claim_rules")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(admins_rule().to_string())
+            .dispatch()
+            .status()
+    };
+    assert_eq!(put(&client), Status::Unauthorized);
+
+    diesel::update(go_web::schema::users::table)
+        .set(go_web::schema::users::capabilities.eq(vec![Capability::Features.to_string()]))
+        .execute(&mut conn)
+        .unwrap();
+    assert_eq!(put(&client), Status::Ok);
+
+    let response = client
+        .put("/go/settings/claim_rules")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!([{ "claim": "groups", "value": "go-admins", "capabilities": ["Unknown"] }]).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
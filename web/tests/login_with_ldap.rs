@@ -0,0 +1,140 @@
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::login::LoginSuccessfull;
+use go_web::schema::users_teams;
+use go_web::services::ldap::{LdapConfig, LdapService};
+use go_web::teams::TeamCapability;
+use go_web::users::{Capability, User};
+use rocket::http::{Cookie, Status};
+use rocket::local::blocking::Client;
+use serde_json::json;
+use sha256::digest;
+mod utils;
+use utils::*;
+
+fn login(client: &Client, mail: &str, pwd: &str) -> Status {
+    client
+        .post("/go/login")
+        .body(json!({ "mail": mail, "pwd": pwd }).to_string())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn bound_users_are_registered_with_default_capabilities() {
+    let ldap = MockLdap::start();
+    ldap.add_user("some_user", "some_user@mail.com", "ldap_pwd", &[]);
+    let (client, mut conn) = TestServer::default().ldap(ldap.service()).launch();
+    default_capabilities(&[Capability::TeamsCreateWithValidation], &mut conn);
+
+    let response = client
+        .post("/go/login")
+        .body(json!({ "mail": "some_user@mail.com", "pwd": "ldap_pwd" }).to_string())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let login = response.into_json::<LoginSuccessfull>().unwrap();
+    assert!(!login.pwd_is_temporary);
+    assert_eq!(
+        get_user("some_user@mail.com", &mut conn),
+        Some(User {
+            mail: "some_user@mail.com".to_string(),
+            capabilities: vec![Capability::TeamsCreateWithValidation],
+            scopes: None
+        })
+    );
+    let response = client
+        .get("/go/api/v1/user")
+        .cookie(Cookie::new(SESSION_COOKIE, login.token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn wrong_or_empty_passwords_are_refused() {
+    let ldap = MockLdap::start();
+    ldap.add_user("some_user", "some_user@mail.com", "ldap_pwd", &[]);
+    let (client, mut conn) = TestServer::default().ldap(ldap.service()).launch();
+
+    assert_eq!(login(&client, "some_user@mail.com", "wrong_pwd"), Status::Unauthorized);
+    assert_eq!(login(&client, "some_user@mail.com", ""), Status::Unauthorized);
+    assert_eq!(login(&client, "unknown@mail.com", "ldap_pwd"), Status::Unauthorized);
+    assert_eq!(get_user("some_user@mail.com", &mut conn), None);
+}
+
+#[test]
+fn the_entry_must_be_the_one_of_the_bound_user() {
+    let ldap = MockLdap::start();
+    ldap.add_user("some_user", "some_user@mail.com", "ldap_pwd", &[]);
+    ldap.add_user("another_user", "some_user@other.com", "other_pwd", &[]);
+    let (client, mut conn) = TestServer::default().ldap(ldap.service()).launch();
+
+    // Binds as uid=some_user but the mail is the one of another entry
+    assert_eq!(login(&client, "some_user@other.com", "ldap_pwd"), Status::Unauthorized);
+    assert_eq!(get_user("some_user@other.com", &mut conn), None);
+}
+
+#[test]
+fn users_outside_of_the_directory_keep_their_passwords() {
+    let ldap = MockLdap::start();
+    let (client, mut conn) = TestServer::default().ldap(ldap.service()).launch();
+    // What the browser would have sent without LDAP, hashed again as stored
+    let stored = digest(format!("{}salt2", digest("local_pwdsalt1")));
+    user("local@mail.com", &stored, &[], &[], &mut conn);
+
+    assert_eq!(login(&client, "local@mail.com", "wrong_pwd"), Status::Unauthorized);
+    assert_eq!(login(&client, "local@mail.com", "local_pwd"), Status::Ok);
+}
+
+#[test]
+fn an_unreachable_directory_falls_back_to_passwords() {
+    let unreachable = LdapService::new(LdapConfig {
+        url: "ldap://127.0.0.1:1".to_string(),
+        ..MockLdap::start().config()
+    });
+    let (client, mut conn) = TestServer::default().ldap(unreachable).launch();
+    let stored = digest(format!("{}salt2", digest("local_pwdsalt1")));
+    user("local@mail.com", &stored, &[], &[], &mut conn);
+
+    assert_eq!(login(&client, "local@mail.com", "local_pwd"), Status::Ok);
+}
+
+#[test]
+fn groups_map_to_teams_with_claim_rules() {
+    let ldap = MockLdap::start();
+    ldap.add_user("some_user", "some_user@mail.com", "ldap_pwd", &["ops"]);
+    let (client, mut conn) = TestServer::default().ldap(ldap.service()).launch();
+    team("ops", "Ops", true, true, &mut conn);
+    claim_rules(
+        json!([{
+            "provider": "ldap",
+            "claim": "memberOf",
+            "value": "cn=ops,ou=groups,dc=go,dc=test",
+            "capabilities": ["UsersTeamsRead"],
+            "teams": [{ "team": "ops", "capabilities": ["ShortcutsWrite"] }],
+        }]),
+        &mut conn,
+    );
+
+    assert_eq!(login(&client, "some_user@mail.com", "ldap_pwd"), Status::Ok);
+
+    assert_eq!(
+        get_user("some_user@mail.com", &mut conn).unwrap().capabilities,
+        vec![Capability::UsersTeamsRead]
+    );
+    let teams: Vec<(String, Vec<TeamCapability>, bool)> = users_teams::table
+        .select((users_teams::team_slug, users_teams::capabilities, users_teams::is_accepted))
+        .filter(users_teams::user_mail.eq("some_user@mail.com"))
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(teams, vec![("ops".to_string(), vec![TeamCapability::ShortcutsWrite], true)]);
+}
+
+#[test]
+fn login_page_sends_passwords_as_typed() {
+    let (client, _conn) = TestServer::default().ldap(MockLdap::start().service()).launch();
+
+    let page = client.get("/go/login").dispatch().into_string().unwrap();
+
+    assert!(page.contains(r#""ldap":true"#));
+}
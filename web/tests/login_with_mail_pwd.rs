@@ -1,10 +1,14 @@
+use diesel::prelude::*;
 use diesel::PgConnection;
 use go_web::login::LoginSuccessfull;
+use go_web::schema::users;
+use go_web::users::PasswordHashing;
 use rocket::async_test;
 use rocket::futures::FutureExt;
 use rocket::tokio::sync::Mutex;
 mod utils;
 use rocket::http::Status;
+use rocket::local::blocking::Client;
 use serde_json::json;
 use thirtyfour::prelude::*;
 use thirtyfour_testing_library_ext::{Screen, By as ByExt};
@@ -256,4 +260,77 @@ mod controller {
 
         assert_eq!(response.status(), Status::BadRequest);
     }
+
+    fn stored_pwd(mail: &str, conn: &mut PgConnection) -> String {
+        users::table
+            .find(mail)
+            .select(users::pwd)
+            .first::<Option<String>>(conn)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn login(client: &Client, pwd: &str) -> Status {
+        client
+            .post("/go/login")
+            .body(json!({ "mail": "some_mail@mail.go", "pwd": pwd }).to_string())
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn upgrade_legacy_pwd_hash_to_argon2id() {
+        let (client, mut conn) = launch_with("");
+        user(
+            "some_mail@mail.go",
+            "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+            &[],
+            &[],
+            &mut conn,
+        );
+
+        assert_eq!(login(&client, "some_pwd"), Status::Ok);
+
+        let upgraded = stored_pwd("some_mail@mail.go", &mut conn);
+        assert!(upgraded.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert_eq!(login(&client, "some_pwd"), Status::Ok);
+        assert_eq!(login(&client, "wrong_pwd"), Status::Unauthorized);
+        assert_eq!(stored_pwd("some_mail@mail.go", &mut conn), upgraded);
+    }
+
+    #[test]
+    fn keep_legacy_pwd_hash_on_wrong_pwd() {
+        let (client, mut conn) = launch_with("");
+        user(
+            "some_mail@mail.go",
+            "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+            &[],
+            &[],
+            &mut conn,
+        );
+
+        assert_eq!(login(&client, "wrong_pwd"), Status::Unauthorized);
+
+        assert_eq!(
+            stored_pwd("some_mail@mail.go", &mut conn),
+            "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20"
+        );
+    }
+
+    #[test]
+    fn rehash_pwd_hashed_with_another_cost() {
+        let (client, mut conn) = launch_with("");
+        let other_cost = PasswordHashing {
+            memory_kib: 2048,
+            iterations: 2,
+            parallelism: 1,
+        }
+        .hash("some_pwd")
+        .unwrap();
+        user("some_mail@mail.go", &other_cost, &[], &[], &mut conn);
+
+        assert_eq!(login(&client, "some_pwd"), Status::Ok);
+
+        assert!(stored_pwd("some_mail@mail.go", &mut conn).starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    }
 }
@@ -3,15 +3,18 @@ use rocket::async_test;
 use rocket::futures::FutureExt;
 use rocket::http::Cookie;
 use rocket::http::Status;
+use rocket::local::blocking::{Client, LocalResponse};
 
 mod utils;
+use serde_json::json;
 use go_web::guards::SESSION_COOKIE;
+use go_web::users::{Capability, User};
 use rocket::tokio::sync::Mutex;
 use thirtyfour::WebDriver;
 use thirtyfour_testing_library_ext::{Screen, By as ByExt, TextMatch};
 use utils::*;
 
-mod with_google{
+mod with_provider {
     use super::*;
     #[test]
     fn with_connected_user_is_not_allowed() {
@@ -27,34 +30,44 @@ mod with_google{
         assert_eq!(response.status(), Status::BadRequest);
     }
 
-    #[cfg(feature = "mock")]
     #[test]
-    fn as_user_gen_nonce_then_redirect_to_google() {
+    fn with_unknown_provider_is_not_found() {
+        let (client, _conn) = launch_with("");
 
-        let mut oidc_service = OidcService::faux();
+        let response = client.get("/go/login/unknown").dispatch();
 
+        assert_eq!(response.status(), Status::NotFound);
+    }
 
-        faux::when!(oidc_service.redirect())
-        .then_return(Ok(("http://auth_url".to_string(), "nonce".to_string())));
-
+    #[test]
+    fn as_user_gen_nonce_then_redirect_to_the_provider() {
+        let issuer = MockIssuer::start();
+        let (client, _conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
 
-        let (client, _conn) = launch_with_sessions_and_mock("", oidc_service);
-        
+        let response = client.get("/go/login/keycloak").dispatch();
 
-        let response = client.get("/go/login/google").dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let url = response.headers().get_one("Location").unwrap();
+        assert!(url.starts_with(&format!("{}/authorize?", issuer.url)));
+        assert!(url.contains("redirect_uri=http%3A%2F%2Fgo.test%2Fgo%2Flogin%2Fredirect%2Fkeycloak"));
+        assert!(response.cookies().get(SESSION_COOKIE).is_some());
+    }
 
-        assert_eq!(response.status(), Status::PermanentRedirect);
+    #[test]
+    fn login_page_lists_the_providers() {
+        let issuer = MockIssuer::start();
+        let (client, _conn) =
+            TestServer::default().oidc(issuer.service(&["keycloak", "other"])).launch();
 
-        let mut location = response.headers().get("Location");
-        let url = location.next().unwrap();
-        assert_eq!(url, "http://auth_url");
-        assert_eq!(location.next(), None);
+        let page = client.get("/go/login").dispatch().into_string().unwrap();
 
-        assert!(response.cookies().get(SESSION_COOKIE).is_some());
+        assert!(page.contains(
+            r#""providers":[{"id":"keycloak","name":"Mock keycloak"},{"id":"other","name":"Mock other"}]"#
+        ));
     }
 }
 
-mod after_google_redirect {
+mod after_provider_redirect {
     use super::*;
     #[test]
     fn with_wrong_nonce_is_not_allowed() {
@@ -69,94 +82,277 @@ mod after_google_redirect {
         assert_eq!(response.status(), Status::Unauthorized);
     }
 
-    #[cfg(feature = "mock")]
-    #[test]
-    fn as_known_user_create_session() {
+    /// Through the mock issuer, returns the last response and the session it started
+    fn login_as(mail: &str, client: &Client, issuer: &MockIssuer) -> (Status, Option<String>, String) {
+        let (session_id, auth_url) = start_login(client);
+        let state = issuer.authorize("code", mail, &auth_url);
 
-        let mut oidc_service = OidcService::faux();
+        let response = callback(client, &session_id, &format!("state={state}&code=code"));
+        let location = response.headers().get_one("Location").map(str::to_string);
+        let session_id = response
+            .cookies()
+            .get(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or(session_id);
 
-        faux::when!(oidc_service.retrieve_token("code", "nonce"))
-            .then_return(Ok(TokenRes {mail: "some_mail@mail.com".to_string()}));
+        (response.status(), location, session_id)
+    }
+
+    /// The session holding the login and the URL of the provider
+    fn start_login(client: &Client) -> (String, String) {
+        let response = client.get("/go/login/keycloak").dispatch();
+        let session_id = response.cookies().get(SESSION_COOKIE).unwrap().value().to_string();
 
+        (session_id, response.headers().get_one("Location").unwrap().to_string())
+    }
+
+    fn callback<'c>(client: &'c Client, session_id: &str, query: &str) -> LocalResponse<'c> {
+        client
+            .get(format!("/go/login/redirect/keycloak?{query}"))
+            .cookie(Cookie::new(SESSION_COOKIE, session_id.to_string()))
+            .dispatch()
+    }
 
-        let (client, mut conn) = launch_with_sessions_and_mock("some_session_id: nonce", oidc_service);
+    fn user_status(client: &Client, session_id: &str) -> Status {
+        client
+            .get("/go/api/v1/user")
+            .cookie(Cookie::new(SESSION_COOKIE, session_id.to_string()))
+            .dispatch()
+            .status()
+    }
 
+    #[test]
+    fn as_known_user_create_session() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
         user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
-        
 
-        let response = client        
-        .get("/go/login/redirect/google?state=4SOFn03KuR72BXkANMKnoQ&code=code&scope=email+openid+https%3A%2F%2Fwww.googleapis.com%2Fauth%2Fuserinfo.email&authuser=1&prompt=consent").cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
-        .dispatch(); 
+        let (status, location, session_id) = login_as("some_mail@mail.com", &client, &issuer);
 
-        assert_eq!(response.status(), Status::PermanentRedirect);
+        assert_eq!(status, Status::SeeOther);
+        assert_eq!(location.as_deref(), Some("/"));
+        assert_eq!(user_status(&client, &session_id), Status::Ok);
+    }
 
-        let mut location = response.headers().get("Location");
-        let url = location.next().unwrap();
-        
-        assert_eq!(url, "/");
-        assert!(     location.next().is_none()); 
+    #[test]
+    fn does_not_log_in_the_session_of_the_login() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+        user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+        let (login_session_id, auth_url) = start_login(&client);
+        let state = issuer.authorize("code", "some_mail@mail.com", &auth_url);
+        let response = callback(&client, &login_session_id, &format!("state={state}&code=code"));
 
+        let session_id = response.cookies().get(SESSION_COOKIE).unwrap().value().to_string();
+        assert_ne!(session_id, login_session_id);
+        assert_eq!(user_status(&client, &session_id), Status::Ok);
+        assert_eq!(user_status(&client, &login_session_id), Status::Unauthorized);
     }
 
-    #[cfg(feature = "mock")]
     #[test]
     fn as_unknown_user_create_user_ande_session() {
-        use go_web::users::User;
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
 
-        let mut oidc_service = OidcService::faux();
-        faux::when!(oidc_service.retrieve_token("code", "nonce"))
-            .then_return(Ok(TokenRes {mail: "some_mail@mail.com".to_string()}));
+        let (status, _, session_id) = login_as("some_mail@mail.com", &client, &issuer);
 
+        assert_eq!(status, Status::SeeOther);
+        assert_eq!(
+            get_user("some_mail@mail.com", &mut conn),
+            Some(User { mail: "some_mail@mail.com".to_string(), capabilities: vec![], scopes: None })
+        );
+        assert_eq!(user_status(&client, &session_id), Status::Ok);
+    }
 
-        let (client, mut conn) = launch_with_sessions_and_mock("some_session_id: nonce", oidc_service);
+    #[test]
+    fn as_unknown_user_with_default_capabilities_create_user_with_default_capabilities() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+        default_capabilities(&[Capability::TeamsCreateWithValidation], &mut conn);
+
+        let (status, _, _) = login_as("some_mail@mail.com", &client, &issuer);
+
+        assert_eq!(status, Status::SeeOther);
+        assert_eq!(
+            get_user("some_mail@mail.com", &mut conn),
+            Some(User {
+                mail: "some_mail@mail.com".to_string(),
+                capabilities: vec![Capability::TeamsCreateWithValidation],
+                scopes: None
+            })
+        );
+    }
 
-        
+    #[test]
+    fn with_a_code_unknown_to_the_provider_fails() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
 
-        let response = client        
-        .get("/go/login/redirect/google?state=4SOFn03KuR72BXkANMKnoQ&code=code&scope=email+openid+https%3A%2F%2Fwww.googleapis.com%2Fauth%2Fuserinfo.email&authuser=1&prompt=consent").cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
-        .dispatch(); 
+        let (session_id, auth_url) = start_login(&client);
+        let state = issuer.authorize("code", "some_mail@mail.com", &auth_url);
 
-        assert_eq!(response.status(), Status::PermanentRedirect);
+        let response = callback(&client, &session_id, &format!("state={state}&code=wrong_code"));
 
-        let mut location = response.headers().get("Location");
-        let url = location.next().unwrap();
-        
-        assert_eq!(url, "/");
-        assert!(     location.next().is_none()); 
+        assert_eq!(response.status(), Status::BadGateway);
+        assert!(response.into_string().unwrap().contains("let us log you in"));
+        assert_eq!(get_user("some_mail@mail.com", &mut conn), None);
+    }
+
+    #[test]
+    fn with_a_forged_state_is_refused() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+
+        let (session_id, auth_url) = start_login(&client);
+        let state = issuer.authorize("code", "some_mail@mail.com", &auth_url);
+
+        let response = callback(&client, &session_id, "state=forged_state&code=code");
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(callback(&client, &session_id, "code=code").status(), Status::Unauthorized);
+        assert_eq!(get_user("some_mail@mail.com", &mut conn), None);
 
-        assert_eq!(get_user("some_mail@mail.com", &mut conn), Some(User {mail: "some_mail@mail.com".to_string(), capabilities: vec![]}));
+        // The login can't be completed afterwards
+        let response = callback(&client, &session_id, &format!("state={state}&code=code"));
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(user_status(&client, &session_id), Status::Unauthorized);
+    }
+
+    #[test]
+    fn on_another_provider_is_refused() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak", "google"])).launch();
+
+        let (session_id, auth_url) = start_login(&client);
+        let state = issuer.authorize("code", "some_mail@mail.com", &auth_url);
 
+        let response = client
+            .get(format!("/go/login/redirect/google?state={state}&code=code"))
+            .cookie(Cookie::new(SESSION_COOKIE, session_id.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(get_user("some_mail@mail.com", &mut conn), None);
+        assert_eq!(user_status(&client, &session_id), Status::Unauthorized);
     }
 
-    #[cfg(feature = "mock")]
     #[test]
-    fn as_unknown_user_with_default_capabilities_create_user_with_default_capabilities() {
-        use go_web::users::{User, Capability};
+    fn without_state_is_refused() {
+        let issuer = MockIssuer::start();
+        let (client, _conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
 
-        let mut oidc_service = OidcService::faux();
-        faux::when!(oidc_service.retrieve_token("code", "nonce"))
-            .then_return(Ok(TokenRes {mail: "some_mail@mail.com".to_string()}));
+        let (session_id, auth_url) = start_login(&client);
+        issuer.authorize("code", "some_mail@mail.com", &auth_url);
 
+        assert_eq!(callback(&client, &session_id, "code=code").status(), Status::BadRequest);
+    }
 
-        let (client, mut conn) = launch_with_sessions_and_mock("some_session_id: nonce", oidc_service);
+    #[test]
+    fn refused_by_the_provider_shows_why() {
+        let issuer = MockIssuer::start();
+        let (client, _conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
 
-        default_capabilities(&[ Capability::TeamsCreateWithValidation],&mut conn);
-        
+        let (session_id, _) = start_login(&client);
+        let response = callback(&client, &session_id, "error=access_denied&state=some_state");
 
-        let response = client        
-        .get("/go/login/redirect/google?state=4SOFn03KuR72BXkANMKnoQ&code=code&scope=email+openid+https%3A%2F%2Fwww.googleapis.com%2Fauth%2Fuserinfo.email&authuser=1&prompt=consent").cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
-        .dispatch(); 
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response.into_string().unwrap().contains("refused the login: access_denied"));
+    }
+
+    #[test]
+    fn with_an_unverified_mail_is_refused() {
+        let issuer = MockIssuer::start();
+        let (client, mut conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+        user("admin@mail.com", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+
+        let (session_id, auth_url) = start_login(&client);
+        let state =
+            issuer.authorize_with_claims("code", "admin@mail.com", json!({ "email_verified": false }), &auth_url);
+
+        let response = callback(&client, &session_id, &format!("state={state}&code=code"));
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response.into_string().unwrap().contains("verify it there"));
+        assert_eq!(user_status(&client, &session_id), Status::Unauthorized);
+    }
 
-        assert_eq!(response.status(), Status::PermanentRedirect);
+    #[test]
+    fn with_an_unverified_mail_is_accepted_from_trusted_providers() {
+        let issuer = MockIssuer::start();
+        let trusted = go_web::services::oidc::OidcProviderConfig {
+            trust_unverified_email: true,
+            ..issuer.config("keycloak")
+        };
+        let service = rocket::execute(go_web::services::oidc::OidcService::discover(&[trusted], "http://go.test"));
+        let (client, _conn) = TestServer::default().oidc(service).launch();
+
+        let (session_id, auth_url) = start_login(&client);
+        let state =
+            issuer.authorize_with_claims("code", "some_mail@mail.com", json!({ "email_verified": false }), &auth_url);
+
+        let response = callback(&client, &session_id, &format!("state={state}&code=code"));
+        assert_eq!(response.status(), Status::SeeOther);
+        let session_id = response.cookies().get(SESSION_COOKIE).unwrap().value().to_string();
+        assert_eq!(user_status(&client, &session_id), Status::Ok);
+    }
 
-        let mut location = response.headers().get("Location");
-        let url = location.next().unwrap();
-        
-        assert_eq!(url, "/");
-        assert!(     location.next().is_none()); 
+    #[test]
+    fn without_id_token_fails() {
+        let issuer = MockIssuer::start();
+        let (client, _conn) = TestServer::default().oidc(issuer.service(&["keycloak"])).launch();
+
+        let (session_id, auth_url) = start_login(&client);
+        let state = issuer.authorize_without_id_token("code", &auth_url);
+
+        let response = callback(&client, &session_id, &format!("state={state}&code=code"));
+        assert_eq!(response.status(), Status::BadGateway);
+    }
+}
 
-        assert_eq!(get_user("some_mail@mail.com", &mut conn), Some(User {mail: "some_mail@mail.com".to_string(), capabilities: vec![ Capability::TeamsCreateWithValidation]}));
+mod without_provider {
+    use super::*;
+    use go_web::services::oidc::{OidcProviderConfig, OidcService};
+
+    #[test]
+    fn login_page_has_no_provider_button() {
+        let (client, _conn) = TestServer::default().oidc(OidcService::new()).launch();
+
+        let page = client.get("/go/login").dispatch().into_string().unwrap();
+
+        assert!(page.contains(r#""providers":[]"#));
+    }
 
+    #[test]
+    fn provider_login_is_disabled() {
+        let (client, _conn) = TestServer::default()
+            .sessions(r#"some_session_id: '{"provider": "google", "nonce": "nonce", "state": "state", "pkce_verifier": "verifier"}'"#)
+            .oidc(OidcService::new())
+            .launch();
+
+        let response = client.get("/go/login/google").dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        assert!(response.into_string().unwrap().contains("Login with an identity provider is disabled"));
+
+        let response = client
+            .get("/go/login/redirect/google?code=code&state=state")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+    }
+
+    #[test]
+    fn unreachable_providers_are_left_out() {
+        let issuer = MockIssuer::start();
+        let unreachable = OidcProviderConfig {
+            issuer_url: "http://127.0.0.1:1".to_string(),
+            ..issuer.config("unreachable")
+        };
+
+        let service = rocket::execute(OidcService::discover(
+            &[unreachable, issuer.config("keycloak")],
+            "http://go.test",
+        ));
+
+        let ids: Vec<_> = service.providers().into_iter().map(|provider| provider.id).collect();
+        assert_eq!(ids, vec!["keycloak"]);
     }
 }
 
@@ -176,11 +372,11 @@ async fn login_page_has_oauth_links() {
                 let screen = Screen::build_with_testing_library(driver.clone()).await?;
                 assert_eq!(
                     screen
-                        .find(ByExt::role("link").name(TextMatch::Exact("Login with google".to_string())))
+                        .find(ByExt::role("link").name(TextMatch::Exact("Login with Google".to_string())))
                         .await?
                         .text()
                         .await?,
-                    "Login with google"
+                    "Login with Google"
                 );
 
                 Ok(())
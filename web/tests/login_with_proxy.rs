@@ -0,0 +1,124 @@
+use go_web::users::{Capability, ProxyAuth, User};
+use rocket::http::{Header, Status};
+use rocket::local::blocking::Client;
+use serde_json::Value;
+mod utils;
+use utils::*;
+
+fn launch() -> (Client, diesel::PgConnection) {
+    TestServer::default().proxy_auth(ProxyAuth::new("X-Forwarded-Email", "10.0.0.1, 172.16.0.0/12").unwrap()).launch()
+}
+
+fn get_user_from(client: &Client, remote: &str, mail: &str) -> (Status, Option<Value>) {
+    let response = client
+        .get("/go/api/v1/user")
+        .remote(remote.parse().unwrap())
+        .header(Header::new("X-Forwarded-Email", mail.to_string()))
+        .dispatch();
+
+    (response.status(), response.into_json())
+}
+
+#[test]
+fn from_a_trusted_proxy_register_unknown_users_with_default_capabilities() {
+    let (client, mut conn) = launch();
+    default_capabilities(&[Capability::TeamsCreateWithValidation], &mut conn);
+
+    let (status, user) = get_user_from(&client, "10.0.0.1:41234", "Some_Mail@mail.com");
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user.unwrap()["mail"], "some_mail@mail.com");
+    assert_eq!(
+        get_user("some_mail@mail.com", &mut conn),
+        Some(User {
+            mail: "some_mail@mail.com".to_string(),
+            capabilities: vec![Capability::TeamsCreateWithValidation],
+            scopes: None
+        })
+    );
+}
+
+#[test]
+fn from_a_trusted_network_authenticate_known_users() {
+    let (client, mut conn) = launch();
+    user("some_mail@mail.com", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+
+    let (status, user) = get_user_from(&client, "172.20.3.4:41234", "some_mail@mail.com");
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user.unwrap()["capabilities"], serde_json::json!(["UsersAdmin"]));
+    assert_eq!(
+        client
+            .get("/")
+            .remote("172.20.3.4:41234".parse().unwrap())
+            .header(Header::new("X-Forwarded-Email", "some_mail@mail.com"))
+            .dispatch()
+            .status(),
+        Status::Ok
+    );
+}
+
+#[test]
+fn from_elsewhere_the_header_is_ignored() {
+    let (client, mut conn) = launch();
+    user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+    let (status, _) = get_user_from(&client, "10.0.0.2:41234", "some_mail@mail.com");
+    assert_eq!(status, Status::Unauthorized);
+
+    let response = client
+        .get("/go/api/v1/user")
+        .header(Header::new("X-Forwarded-Email", "some_mail@mail.com"))
+        .header(Header::new("X-Real-IP", "10.0.0.1"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let (status, _) = get_user_from(&client, "10.0.0.1:41234", "another_mail@mail.com");
+    assert_eq!(status, Status::Ok);
+    let (status, _) = get_user_from(&client, "192.168.0.1:41234", "third_mail@mail.com");
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(get_user("third_mail@mail.com", &mut conn), None);
+}
+
+#[test]
+fn with_an_invalid_mail_is_refused() {
+    let (client, mut conn) = launch();
+
+    let (status, _) = get_user_from(&client, "10.0.0.1:41234", "not a mail");
+
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(get_user("not a mail", &mut conn), None);
+}
+
+#[test]
+fn with_a_mail_among_other_text_is_refused() {
+    let (client, mut conn) = launch();
+
+    let (status, _) = get_user_from(&client, "10.0.0.1:41234", "x y<some_mail@mail.com>");
+
+    assert_eq!(status, Status::Unauthorized);
+    assert_eq!(get_user("x y<some_mail@mail.com>", &mut conn), None);
+}
+
+#[test]
+fn without_the_header_fall_back_to_sessions() {
+    let (client, mut conn) = launch();
+    user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+    let response = client
+        .get("/go/api/v1/user")
+        .remote("10.0.0.1:41234".parse().unwrap())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn trusted_proxies_are_addresses_or_networks() {
+    let proxy_auth = ProxyAuth::new("X-Forwarded-Email", "10.0.0.1,fd00::/8,").unwrap();
+
+    assert!(proxy_auth.is_trusted("10.0.0.1".parse().unwrap()));
+    assert!(!proxy_auth.is_trusted("10.0.0.10".parse().unwrap()));
+    assert!(proxy_auth.is_trusted("fd12::1".parse().unwrap()));
+    assert!(ProxyAuth::new("X-Forwarded-Email", "10.0.0.300").is_err());
+}
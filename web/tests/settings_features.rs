@@ -0,0 +1,204 @@
+use go_web::guards::SESSION_COOKIE;
+use go_web::settings::Features;
+use go_web::teams::TeamCapability;
+use go_web::users::Capability;
+use rocket::http::{ContentType, Cookie, Status};
+use rocket::local::blocking::Client;
+use serde_json::{json, Value};
+use sha256::digest;
+mod utils;
+use utils::*;
+
+fn launch() -> (Client, diesel::PgConnection) {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        &digest("some_pwdsalt2"),
+        &[("", &[], 0, true)],
+        &[Capability::Features, Capability::UsersTeamsWrite],
+        &mut conn,
+    );
+    team("some_team", "Some team", false, true, &mut conn);
+
+    (client, conn)
+}
+
+fn without(patch: Value, conn: &mut diesel::PgConnection) {
+    let mut all = Features::default();
+    all.patch(serde_json::from_value(patch).unwrap());
+    features(all, conn);
+}
+
+#[test]
+fn are_shown_to_admins_of_features() {
+    let (client, _conn) = launch();
+
+    let response = client
+        .get("/go/settings")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let page = response.into_string().unwrap();
+    assert!(page.contains(r#"window.features = {"login":{"google":true,"read_private":true,"simple":true},"teams":true}"#));
+}
+
+#[test]
+fn patch_keeps_the_flags_not_sent() {
+    let (client, _conn) = launch();
+
+    let response = client
+        .patch("/go/settings")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!({ "login": { "google": false } }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_json::<Value>().unwrap(),
+        json!({ "login": { "simple": true, "google": false, "read_private": true }, "teams": true })
+    );
+
+    let response = client
+        .patch("/go/settings")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!({ "teams": false }).to_string())
+        .dispatch();
+    assert_eq!(
+        response.into_json::<Value>().unwrap(),
+        json!({ "login": { "simple": true, "google": false, "read_private": true }, "teams": false })
+    );
+}
+
+#[test]
+fn disabled_simple_login_is_refused() {
+    let (client, mut conn) = launch();
+    registration(true, &[], &mut conn);
+    without(json!({ "login": { "simple": false } }), &mut conn);
+
+    let credentials = json!({ "mail": "some_mail@mail.com", "pwd": "some_pwd" }).to_string();
+    let response = client.post("/go/login").body(&credentials).dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .post("/go/register")
+        .body(json!({ "mail": "other_mail@mail.com", "pwd": "some_pwd" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .post("/go/password/forgot")
+        .body(json!({ "mail": "some_mail@mail.com" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    let page = client.get("/go/login").dispatch().into_string().unwrap();
+    assert!(page.contains(r#""simple_salt":null"#));
+    assert!(page.contains(r#""registration":false"#));
+}
+
+#[test]
+fn disabled_oidc_login_is_refused() {
+    let (client, mut conn) = launch();
+    without(json!({ "login": { "google": false } }), &mut conn);
+
+    assert_eq!(client.get("/go/login/google").dispatch().status(), Status::Conflict);
+
+    let page = client.get("/go/login").dispatch().into_string().unwrap();
+    assert!(page.contains(r#""providers":[]"#));
+}
+
+#[test]
+fn enabled_simple_login_still_works() {
+    let (client, mut conn) = launch();
+    without(json!({ "login": { "google": false }, "teams": false }), &mut conn);
+
+    let response = client
+        .post("/go/login")
+        .body(json!({ "mail": "some_mail@mail.com", "pwd": "some_pwd" }).to_string())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn disabled_teams_are_hidden() {
+    let (client, mut conn) = launch();
+    without(json!({ "teams": false }), &mut conn);
+    let cookie = || Cookie::new(SESSION_COOKIE, "some_session_id");
+
+    for uri in ["/go/teams", "/go/teams/some_team", "/go/api/v1/teams", "/go/api/v1/teams/some_team/shortcuts"] {
+        let response = client.get(uri).cookie(cookie()).dispatch();
+        assert_eq!(response.status(), Status::NotFound, "{uri}");
+    }
+
+    let response = client
+        .post("/go/api/v1/teams")
+        .cookie(cookie())
+        .body(json!({ "slug": "new_team", "title": "New team", "is_private": false }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .post("/go/api/v1/user/teams?team=some_team")
+        .cookie(cookie())
+        .body(json!({ "rank": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn disabled_teams_keep_the_global_team() {
+    let (client, mut conn) = launch();
+    without(json!({ "teams": false }), &mut conn);
+
+    let response = client
+        .delete("/go/user/teams")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/go/api/v1/user/teams")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!({ "rank": 0 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[test]
+fn disabled_teams_refuse_their_shortcuts() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("some_team", "Some team", false, true, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true), ("some_team", &[TeamCapability::ShortcutsWrite], 1, true)],
+        &[],
+        &mut conn,
+    );
+    shortcut("docs", "http://localhost/docs", "some_team", &mut conn);
+    without(json!({ "teams": false }), &mut conn);
+    let cookie = || Cookie::new(SESSION_COOKIE, "some_session_id");
+
+    for uri in ["/go/api/v1/shortcuts/docs?team=some_team", "/go/shortcuts/history/docs?team=some_team"] {
+        let response = client.get(uri).cookie(cookie()).dispatch();
+        assert_eq!(response.status(), Status::NotFound, "{uri}");
+    }
+
+    for uri in ["/go/api/v1/shortcuts/new?team=some_team", "/new?team=some_team"] {
+        let response = client
+            .put(uri)
+            .header(ContentType::JSON)
+            .cookie(cookie())
+            .body(r#"{"url": "http://localhost/new"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound, "{uri}");
+    }
+
+    for uri in ["/go/api/v1/shortcuts/docs?team=some_team", "/docs?team=some_team"] {
+        let response = client.delete(uri).cookie(cookie()).dispatch();
+        assert_eq!(response.status(), Status::NotFound, "{uri}");
+    }
+    assert!(get_shortcut_with_team("docs", "some_team", &mut conn).is_some());
+    assert!(get_shortcut_with_team("new", "some_team", &mut conn).is_none());
+}
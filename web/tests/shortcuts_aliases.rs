@@ -0,0 +1,136 @@
+use go_web::guards::SESSION_COOKIE;
+use go_web::teams::TeamCapability;
+use rocket::http::ContentType;
+use rocket::http::Cookie;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use utils::*;
+
+fn put(client: &Client, path: &str, body: &str) -> Status {
+    client
+        .put(path)
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(body)
+        .dispatch()
+        .status()
+}
+
+fn location(client: &Client, path: &str) -> Option<String> {
+    let response = client
+        .get(path)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    response.headers().get_one("Location").map(|l| l.to_string())
+}
+
+#[test]
+fn redirect_to_canonical_target() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    assert_eq!(
+        put(&client, "/docs", r#"{"url": "http://localhost/docs", "aliases": ["doc", " documentation ", "docs"]}"#),
+        Status::Ok
+    );
+
+    assert_eq!(location(&client, "/doc"), Some("http://localhost/docs".to_string()));
+    assert_eq!(
+        location(&client, "/documentation/install"),
+        Some("http://localhost/docs/install".to_string())
+    );
+    assert!(get_shortcut("doc", &mut conn).is_none());
+}
+
+#[test]
+fn follow_canonical_update() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/docs", r#"{"url": "http://localhost/docs", "aliases": ["doc"]}"#);
+    put(&client, "/docs", r#"{"url": "http://localhost/new_docs"}"#);
+
+    assert_eq!(location(&client, "/doc"), Some("http://localhost/new_docs".to_string()));
+
+    put(&client, "/docs", r#"{"url": "http://localhost/new_docs", "aliases": []}"#);
+
+    assert_eq!(location(&client, "/doc"), None);
+}
+
+#[test]
+fn are_deleted_with_canonical() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/docs", r#"{"url": "http://localhost/docs", "aliases": ["doc"]}"#);
+
+    let response = client
+        .delete("/docs")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/doc")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn conflict_with_names_of_the_same_team() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    shortcut("wiki", "http://localhost/wiki", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[
+            ("", &[TeamCapability::ShortcutsWrite], 0, true),
+            ("slug1", &[TeamCapability::ShortcutsWrite], 1, true),
+        ],
+        &[],
+        &mut conn,
+    );
+
+    assert_eq!(
+        put(&client, "/docs", r#"{"url": "http://localhost/docs", "aliases": ["wiki"]}"#),
+        Status::Conflict
+    );
+    assert!(get_shortcut("docs", &mut conn).is_none());
+
+    assert_eq!(
+        put(&client, "/docs", r#"{"url": "http://localhost/docs", "aliases": ["doc"]}"#),
+        Status::Ok
+    );
+    assert_eq!(
+        put(&client, "/wiki", r#"{"url": "http://localhost/wiki", "aliases": ["doc"]}"#),
+        Status::Conflict
+    );
+    assert_eq!(put(&client, "/doc", r#"{"url": "http://localhost/doc"}"#), Status::Conflict);
+
+    assert_eq!(
+        put(&client, "/doc?team=slug1", r#"{"url": "http://localhost/doc", "aliases": ["wiki"]}"#),
+        Status::Ok
+    );
+}
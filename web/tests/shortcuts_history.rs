@@ -0,0 +1,262 @@
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::schema::{shortcut_versions, users_teams};
+use go_web::teams::TeamCapability;
+use rocket::http::ContentType;
+use rocket::http::Cookie;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::{json, Value};
+use utils::*;
+
+fn put(client: &Client, path: &str, url: &str) {
+    let response = client
+        .put(path)
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(format!(r#"{{"url": "{url}"}}"#))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+fn delete(client: &Client, path: &str) {
+    let response = client
+        .delete(path)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+fn history(client: &Client, path: &str) -> Vec<(i64, String, Value, Value)> {
+    let response = client
+        .get(path)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response
+        .into_json::<Value>()
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| {
+            assert_eq!(v["user_mail"], "some_mail@mail.com");
+            (
+                v["version"].as_i64().unwrap(),
+                v["action"].as_str().unwrap().to_string(),
+                v["old_url"].clone(),
+                v["new_url"].clone(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn as_user_with_team_capability_list_changes() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("slug1", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/myShortCut/hop?team=slug1", "http://localhost/1");
+    put(&client, "/myShortCut/hop?team=slug1", "http://localhost/1");
+    put(&client, "/myShortCut/hop?team=slug1", "http://localhost/2");
+    delete(&client, "/myShortCut/hop?team=slug1");
+
+    assert_eq!(
+        history(&client, "/go/shortcuts/history/myShortCut/hop?team=slug1"),
+        vec![
+            (3, "Delete".to_string(), "http://localhost/2".into(), Value::Null),
+            (2, "Update".to_string(), "http://localhost/1".into(), "http://localhost/2".into()),
+            (1, "Create".to_string(), Value::Null, "http://localhost/1".into()),
+        ]
+    );
+}
+
+#[test]
+fn revert_restore_previous_url() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/myShortCut", "http://localhost/1");
+    put(&client, "/myShortCut", "http://localhost/2");
+
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?version=1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        get_shortcut("myShortCut", &mut conn).unwrap().url,
+        "http://localhost/1"
+    );
+    assert_eq!(
+        history(&client, "/go/shortcuts/history/myShortCut")[0],
+        (3, "Update".to_string(), "http://localhost/2".into(), "http://localhost/1".into())
+    );
+}
+
+#[test]
+fn revert_restore_deleted_shortcut() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/myShortCut", "http://localhost/1");
+    delete(&client, "/myShortCut");
+    assert!(get_shortcut("myShortCut", &mut conn).is_none());
+
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?version=1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        get_shortcut("myShortCut", &mut conn).unwrap().url,
+        "http://localhost/1"
+    );
+    assert_eq!(
+        history(&client, "/go/shortcuts/history/myShortCut")[0],
+        (3, "Create".to_string(), Value::Null, "http://localhost/1".into())
+    );
+}
+
+#[test]
+fn revert_unknown_version_return_404() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    put(&client, "/myShortCut", "http://localhost/1");
+
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?version=2")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn as_user_without_team_capability_is_unauthorized() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("myShortCut", "http://localhost/1", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/go/shortcuts/history/myShortCut")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?version=1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn is_archived_with_the_team() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    let capabilities: &[TeamCapability] = &[TeamCapability::ShortcutsWrite, TeamCapability::TeamsWrite];
+    user("some_mail@mail.com", "pwd", &[("slug1", capabilities, 0, true)], &[], &mut conn);
+    put(&client, "/myShortCut?team=slug1", "http://localhost/1");
+    put(&client, "/myShortCut?team=slug1", "http://localhost/2");
+
+    delete(&client, "/go/teams/slug1");
+
+    let archived: i64 = shortcut_versions::table
+        .filter(shortcut_versions::team_slug.eq("slug1"))
+        .filter(shortcut_versions::archived_at.is_not_null())
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(archived, 2);
+
+    // Not given to a new team of the same slug
+    team("slug1", "team1 again", false, true, &mut conn);
+    diesel::insert_into(users_teams::table)
+        .values((
+            users_teams::user_mail.eq("some_mail@mail.com"),
+            users_teams::team_slug.eq("slug1"),
+            users_teams::capabilities.eq(vec![TeamCapability::ShortcutsWrite]),
+            users_teams::is_accepted.eq(true),
+            users_teams::rank.eq(0),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    put(&client, "/myShortCut?team=slug1", "http://localhost/3");
+
+    assert_eq!(
+        history(&client, "/go/shortcuts/history/myShortCut?team=slug1"),
+        vec![(3, "Create".to_string(), Value::Null, "http://localhost/3".into())]
+    );
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?team=slug1&version=1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn description_and_tags_changes_are_versions() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user("some_mail@mail.com", "pwd", &[("", &[TeamCapability::ShortcutsWrite], 0, true)], &[], &mut conn);
+    let put_with = |body: Value| {
+        let response = client
+            .put("/myShortCut")
+            .header(ContentType::JSON)
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    };
+
+    put_with(json!({ "url": "http://localhost/1", "description": "Docs", "tags": ["doc"] }));
+    put_with(json!({ "url": "http://localhost/1", "description": "Old docs", "tags": ["doc"] }));
+    put_with(json!({ "url": "http://localhost/1", "description": "Old docs", "tags": ["archive"] }));
+    assert_eq!(history(&client, "/go/shortcuts/history/myShortCut").len(), 3);
+
+    let response = client
+        .post("/go/shortcuts/revert/myShortCut?version=1")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let reverted = get_shortcut("myShortCut", &mut conn).unwrap();
+    assert_eq!(reverted.description.as_deref(), Some("Docs"));
+    assert_eq!(reverted.tags, vec!["doc".to_string()]);
+}
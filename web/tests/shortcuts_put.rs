@@ -1,5 +1,5 @@
 use go_web::guards::SESSION_COOKIE;
-use go_web::shortcuts::Shortcut;
+use go_web::shortcuts::{RedirectMode, Shortcut};
 use go_web::teams::TeamCapability;
 use rocket::http;
 use rocket::http::ContentType;
@@ -35,6 +35,33 @@ fn with_invalid_url_return_400() {
     );
 }
 
+#[test]
+fn with_other_scheme_than_http_return_400() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    for url in [
+        "javascript:alert(document.cookie)//http://x",
+        "data:text/html,<script>alert(1)</script>",
+        "ftp://localhost/file",
+    ] {
+        let response = client
+            .put("/myShortCut/hop")
+            .header(ContentType::JSON)
+            .body(json!({ "url": url }).to_string())
+            .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest, "{url}");
+    }
+}
+
 #[test]
 fn as_user_with_team_capability_is_ok() {
     let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
@@ -55,13 +82,21 @@ fn as_user_with_team_capability_is_ok() {
 
     assert_eq!(response.status(), Status::Ok);
 
-    let shortcut = get_shortcut("myShortCut/hop", &mut conn);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert!(shortcut.created_at.is_some());
+    assert_eq!(shortcut.created_at, shortcut.updated_at);
     assert_eq!(
-        shortcut.unwrap(),
+        shortcut,
         Shortcut {
             shortcut: "myShortCut/hop".to_string(),
             url: "http://localhost".to_string(),
-            team_slug: "".to_string()
+            team_slug: "".to_string(),
+            redirect: None,
+            created_by: Some("some_mail@mail.com".to_string()),
+            updated_by: Some("some_mail@mail.com".to_string()),
+            description: None,
+            tags: vec![],
+            ..shortcut
         }
     );
 }
@@ -87,13 +122,21 @@ fn with_specific_team_is_ok() {
 
     assert_eq!(response.status(), Status::Ok);
 
-    let shortcut = get_shortcut("myShortCut/hop", &mut conn);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert!(shortcut.created_at.is_some());
+    assert_eq!(shortcut.created_at, shortcut.updated_at);
     assert_eq!(
-        shortcut.unwrap(),
+        shortcut,
         Shortcut {
             shortcut: "myShortCut/hop".to_string(),
             url: "http://localhost".to_string(),
-            team_slug: "slug1".to_string()
+            team_slug: "slug1".to_string(),
+            redirect: None,
+            created_by: Some("some_mail@mail.com".to_string()),
+            updated_by: Some("some_mail@mail.com".to_string()),
+            description: None,
+            tags: vec![],
+            ..shortcut
         }
     );
 }
@@ -118,13 +161,22 @@ fn that_already_exist_replace_it() {
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert!(shortcut.updated_at.is_some());
     assert_eq!(
-        get_shortcut("myShortCut/hop", &mut conn),
-        Some(Shortcut {
+        shortcut,
+        Shortcut {
             shortcut: "myShortCut/hop".to_string(),
             team_slug: "".to_string(),
-            url: "http://localhost".to_string()
-        })
+            url: "http://localhost".to_string(),
+            redirect: None,
+            created_by: None,
+            created_at: None,
+            updated_by: Some("some_mail@mail.com".to_string()),
+            description: None,
+            tags: vec![],
+            ..shortcut
+        }
     );
 }
 
@@ -232,3 +284,113 @@ fn with_team_not_yet_accepted_is_not_allowed() {
 
     assert_eq!(response.status(), Status::Unauthorized);
 }
+
+#[test]
+fn with_redirect_mode_is_ok() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .put("/myShortCut/hop")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost", "redirect": "Permanent"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert_eq!(
+        shortcut,
+        Shortcut {
+            shortcut: "myShortCut/hop".to_string(),
+            team_slug: "".to_string(),
+            url: "http://localhost".to_string(),
+            redirect: Some(RedirectMode::Permanent),
+            created_by: Some("some_mail@mail.com".to_string()),
+            updated_by: Some("some_mail@mail.com".to_string()),
+            description: None,
+            tags: vec![],
+            ..shortcut
+        }
+    );
+
+    let response = client
+        .put("/myShortCut/hop")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        get_shortcut("myShortCut/hop", &mut conn).unwrap().redirect,
+        None
+    );
+}
+
+#[test]
+fn unchanged_keep_last_author() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("myShortCut/hop", "http://localhost", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .put("/myShortCut/hop")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert_eq!(shortcut.updated_by, None);
+    assert_eq!(shortcut.updated_at, None);
+}
+
+#[test]
+fn with_description_and_tags_is_ok() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .put("/myShortCut/hop")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost", "description": "Where to start", "tags": [" OnCall", "oncall", "Onboarding", ""]}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert_eq!(shortcut.description, Some("Where to start".to_string()));
+    assert_eq!(shortcut.tags, vec!["onboarding".to_string(), "oncall".to_string()]);
+
+    let response = client
+        .put("/myShortCut/hop")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(r#"{"url": "http://localhost"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let shortcut = get_shortcut("myShortCut/hop", &mut conn).unwrap();
+    assert_eq!(shortcut.description, None);
+    assert!(shortcut.tags.is_empty());
+}
@@ -0,0 +1,253 @@
+use diesel::RunQueryDsl;
+use go_web::guards::SESSION_COOKIE;
+use go_web::shortcuts::Hits;
+use go_web::DbPool;
+use go_web::teams::TeamCapability;
+use rocket::http::ContentType;
+use rocket::http::Cookie;
+use rocket::http::Status;
+mod utils;
+use serde_json::Value;
+use utils::*;
+
+fn use_shortcut(client: &rocket::local::blocking::Client, path: &str) {
+    let response = client
+        .get(path)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+}
+
+fn hits(stats: &Value) -> Vec<(String, String, i64)> {
+    stats
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| {
+            (
+                s["shortcut"].as_str().unwrap().to_string(),
+                s["team_slug"].as_str().unwrap().to_string(),
+                s["hits"].as_i64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn as_user_count_uses() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    shortcut("aShortcut", "http://localhost/a", "", &mut conn);
+    shortcut("bShortcut", "http://localhost/b", "", &mut conn);
+    shortcut("bShortcut", "http://localhost/b1", "slug1", &mut conn);
+    shortcut("jira", "http://localhost/jira/{1}", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true), ("slug1", &[], 1, true)],
+        &[],
+        &mut conn,
+    );
+
+    use_shortcut(&client, "/bShortcut");
+    use_shortcut(&client, "/bShortcut");
+    use_shortcut(&client, "/jira/ABC-1");
+
+    let response = client
+        .get("/go/shortcuts/stats")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let stats = response.into_json::<Value>().unwrap();
+    assert_eq!(
+        hits(&stats),
+        vec![
+            ("aShortcut".to_string(), "".to_string(), 0),
+            ("bShortcut".to_string(), "".to_string(), 2),
+            ("bShortcut".to_string(), "slug1".to_string(), 0),
+            ("jira".to_string(), "".to_string(), 1),
+        ]
+    );
+    assert!(stats[0]["last_used_at"].is_null());
+    assert!(stats[1]["last_used_at"].is_string());
+}
+
+#[test]
+fn sorted_by_popularity() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("aShortcut", "http://localhost/a", "", &mut conn);
+    shortcut("bShortcut", "http://localhost/b", "", &mut conn);
+    shortcut("cShortcut", "http://localhost/c", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    use_shortcut(&client, "/cShortcut");
+    use_shortcut(&client, "/cShortcut");
+    use_shortcut(&client, "/bShortcut");
+
+    let response = client
+        .get("/go/shortcuts/stats?sort=popularity")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        hits(&response.into_json::<Value>().unwrap()),
+        vec![
+            ("cShortcut".to_string(), "".to_string(), 2),
+            ("bShortcut".to_string(), "".to_string(), 1),
+            ("aShortcut".to_string(), "".to_string(), 0),
+        ]
+    );
+}
+
+#[test]
+fn without_redirect_is_not_counted() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("aShortcut", "http://localhost/a", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/aShortcut?no_redirect=true")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/go/shortcuts/stats")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(
+        hits(&response.into_json::<Value>().unwrap()),
+        vec![("aShortcut".to_string(), "".to_string(), 0)]
+    );
+}
+
+#[test]
+fn as_unknown_user_is_unauthorized() {
+    let (client, _conn) = launch_with("");
+
+    let response = client.get("/go/shortcuts/stats").dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn filtered_by_tag_and_author() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("aShortcut", "http://localhost/a", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    for (name, body) in [
+        ("bShortcut", r#"{"url": "http://localhost/b", "tags": ["oncall"]}"#),
+        ("cShortcut", r#"{"url": "http://localhost/c", "tags": ["Onboarding"]}"#),
+    ] {
+        let response = client
+            .put(format!("/{name}"))
+            .header(ContentType::JSON)
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let listed = |query: &str| {
+        let response = client
+            .get(format!("/go/shortcuts/stats?{query}"))
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        hits(&response.into_json::<Value>().unwrap())
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(listed("tag=onboarding"), vec!["cShortcut".to_string()]);
+    assert_eq!(listed("tag=OnCall"), vec!["bShortcut".to_string()]);
+    assert_eq!(
+        listed("created_by=some_mail@mail.com"),
+        vec!["bShortcut".to_string(), "cShortcut".to_string()]
+    );
+    assert_eq!(listed("created_by=some_mail@mail.com&tag=oncall"), vec!["bShortcut".to_string()]);
+}
+
+#[test]
+fn are_kept_when_they_can_not_be_written() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("aShortcut", "http://localhost/a", "", &mut conn);
+    user("some_mail@mail.com", "pwd", &[("", &[], 0, true)], &[], &mut conn);
+
+    use_shortcut(&client, "/aShortcut");
+
+    // Writes fail in an aborted transaction
+    let mut broken = client.rocket().state::<DbPool>().unwrap().get().unwrap();
+    diesel::sql_query("BEGIN").execute(&mut broken).unwrap();
+    assert!(diesel::sql_query("SELECT 1 / 0").execute(&mut broken).is_err());
+    assert!(client.rocket().state::<Hits>().unwrap().flush(&mut broken).is_err());
+    diesel::sql_query("ROLLBACK").execute(&mut broken).unwrap();
+
+    let response = client
+        .get("/go/shortcuts/stats")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(
+        hits(&response.into_json::<Value>().unwrap()),
+        vec![("aShortcut".to_string(), "".to_string(), 1)]
+    );
+}
+
+#[test]
+fn waiting_to_be_written_are_bounded() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    for name in ["aShortcut", "bShortcut", "cShortcut"] {
+        shortcut(name, "http://localhost/", "", &mut conn);
+    }
+    user("some_mail@mail.com", "pwd", &[("", &[], 0, true)], &[], &mut conn);
+    let some_user = get_user("some_mail@mail.com", &mut conn).unwrap();
+    let pending = Hits::with_max_pending(2);
+    let mut pooled = client.rocket().state::<DbPool>().unwrap().get().unwrap();
+
+    pending.record(&get_shortcut("aShortcut", &mut conn).unwrap(), &some_user);
+    pending.record(&get_shortcut("bShortcut", &mut conn).unwrap(), &some_user);
+    diesel::sql_query("BEGIN").execute(&mut pooled).unwrap();
+    assert!(diesel::sql_query("SELECT 1 / 0").execute(&mut pooled).is_err());
+    assert!(pending.flush(&mut pooled).is_err());
+    diesel::sql_query("ROLLBACK").execute(&mut pooled).unwrap();
+
+    // The oldest one goes
+    pending.record(&get_shortcut("cShortcut", &mut conn).unwrap(), &some_user);
+    assert_eq!(pending.flush(&mut pooled).unwrap(), 2);
+
+    let response = client
+        .get("/go/shortcuts/stats")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(
+        hits(&response.into_json::<Value>().unwrap()),
+        vec![
+            ("aShortcut".to_string(), "".to_string(), 0),
+            ("bShortcut".to_string(), "".to_string(), 1),
+            ("cShortcut".to_string(), "".to_string(), 1),
+        ]
+    );
+}
@@ -1,5 +1,9 @@
 use go_web::guards::SESSION_COOKIE;
+use go_web::users::Capability;
+use go_web::shortcuts::RedirectMode;
+use go_web::teams::TeamCapability;
 use rocket::http;
+use rocket::http::ContentType;
 use rocket::http::Status;
 mod utils;
 use utils::*;
@@ -23,6 +27,46 @@ fn but_undefined_return_a_404() {
     assert_eq!(response.status(), Status::NotFound);
 }
 
+#[test]
+fn but_undefined_suggest_similar_visible_ones() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", true, true, &mut conn);
+    shortcut("documentation", "http://localhost/doc", "", &mut conn);
+    shortcut("docs", "http://localhost/doc", "", &mut conn);
+    shortcut("jira", "http://localhost/jira", "", &mut conn);
+    shortcut("mydoc", "http://localhost/mydoc", "", &mut conn);
+    shortcut("doc_private", "http://localhost/private", "slug1", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/doc")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains(r#""suggestions":["docs","documentation","mydoc"]"#));
+
+    let response = client
+        .get("/jria")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains(r#""suggestions":["jira"]"#));
+}
+
 #[test]
 fn with_user_redirect_to_target() {
     let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
@@ -40,7 +84,7 @@ fn with_user_redirect_to_target() {
         .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
         .dispatch();
 
-    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(response.status(), Status::TemporaryRedirect);
     let mut location = response.headers().get("Location");
 
     assert_eq!(location.next(), Some("https://thetarget.test.go.com"));
@@ -81,7 +125,7 @@ some_other_session_id: some_other_mail@mail.com",
         .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
         .dispatch();
 
-    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(response.status(), Status::TemporaryRedirect);
     let mut location = response.headers().get("Location");
 
     assert_eq!(location.next(), Some("https://thetarget.test.go.com"));
@@ -92,7 +136,7 @@ some_other_session_id: some_other_mail@mail.com",
         .cookie(http::Cookie::new(SESSION_COOKIE, "some_other_session_id"))
         .dispatch();
 
-    assert_eq!(response.status(), Status::PermanentRedirect);
+    assert_eq!(response.status(), Status::TemporaryRedirect);
     let mut location = response.headers().get("Location");
 
     assert_eq!(location.next(), Some("https://theothertarget.test.go.com"));
@@ -107,3 +151,301 @@ fn as_unknown_user_is_not_allowed() {
 
     assert_eq!(response.status(), Status::Unauthorized);
 }
+
+#[test]
+fn with_path_suffix_fill_placeholders_of_longest_prefix() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("jira", "https://jira.test.go.com", "", &mut conn);
+    shortcut(
+        "jira/browse",
+        "https://jira.test.go.com/browse/{1}?focus={2}",
+        "",
+        &mut conn,
+    );
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/jira/browse/ABC-123/comments")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    let mut location = response.headers().get("Location");
+
+    assert_eq!(
+        location.next(),
+        Some("https://jira.test.go.com/browse/ABC-123?focus=comments")
+    );
+    assert_eq!(location.next(), None);
+}
+
+#[test]
+fn with_path_suffix_fill_whole_suffix_placeholder() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("gh", "https://github.test.go.com/%s/pulls", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/gh/Greedeuh/go_slash")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    let mut location = response.headers().get("Location");
+
+    assert_eq!(
+        location.next(),
+        Some("https://github.test.go.com/Greedeuh/go_slash/pulls")
+    );
+    assert_eq!(location.next(), None);
+}
+
+#[test]
+fn with_path_suffix_and_no_placeholder_append_it() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("wiki", "https://wiki.test.go.com/", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/wiki/some/page")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    let mut location = response.headers().get("Location");
+
+    assert_eq!(location.next(), Some("https://wiki.test.go.com/some/page"));
+    assert_eq!(location.next(), None);
+}
+
+#[test]
+fn with_path_suffix_and_no_placeholder_append_it_before_the_query() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("search", "http://localhost/search?q=a#results", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/search/foo")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    let mut location = response.headers().get("Location");
+
+    assert_eq!(location.next(), Some("http://localhost/search/foo?q=a#results"));
+    assert_eq!(location.next(), None);
+}
+
+#[test]
+fn with_path_suffix_prefer_longest_prefix_over_team_rank() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("slug1", "team1", false, true, &mut conn);
+    shortcut("jira", "https://jira.test.go.com/{1}", "", &mut conn);
+    shortcut("jira", "https://otherjira.test.go.com/{1}", "slug1", &mut conn);
+    shortcut(
+        "jira/board",
+        "https://jira.test.go.com/board/{1}",
+        "slug1",
+        &mut conn,
+    );
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true), ("slug1", &[], 1, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/jira/board/42")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    let mut location = response.headers().get("Location");
+    assert_eq!(location.next(), Some("https://jira.test.go.com/board/42"));
+
+    let response = client
+        .get("/jira/ABC-123")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    let mut location = response.headers().get("Location");
+    assert_eq!(location.next(), Some("https://jira.test.go.com/ABC-123"));
+}
+
+#[test]
+fn with_redirect_mode_use_it() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    for (redirect, status) in [
+        ("Found", Status::Found),
+        ("Temporary", Status::TemporaryRedirect),
+        ("Moved", Status::MovedPermanently),
+        ("Permanent", Status::PermanentRedirect),
+    ] {
+        let response = client
+            .put("/myShortCut/hop")
+            .header(ContentType::JSON)
+            .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(format!(
+                r#"{{"url": "https://thetarget.test.go.com", "redirect": "{redirect}"}}"#
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get("/myShortCut/hop")
+            .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+
+        assert_eq!(response.status(), status);
+        let mut location = response.headers().get("Location");
+        assert_eq!(location.next(), Some("https://thetarget.test.go.com"));
+    }
+}
+
+#[test]
+fn without_redirect_mode_use_default_one() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("myShortCut/hop", "https://thetarget.test.go.com", "", &mut conn);
+    default_redirect(RedirectMode::Found, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/myShortCut/hop")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Found);
+    let mut location = response.headers().get("Location");
+    assert_eq!(location.next(), Some("https://thetarget.test.go.com"));
+}
+
+#[test]
+fn with_interstitial_redirect_mode_show_target() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("myShortCut/hop", "https://thetarget.test.go.com", "", &mut conn);
+    default_redirect(RedirectMode::Interstitial, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/myShortCut/hop")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Location").is_none());
+    assert!(response
+        .into_string()
+        .unwrap()
+        .contains(r#"href="https://thetarget.test.go.com""#));
+}
+
+#[test]
+fn with_interstitial_redirect_mode_refuse_other_schemes() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    shortcut("myShortCut/hop", "javascript:alert(document.cookie)//http://x", "", &mut conn);
+    default_redirect(RedirectMode::Interstitial, &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[],
+        &mut conn,
+    );
+
+    let response = client
+        .get("/myShortCut/hop")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert!(!response.into_string().unwrap_or_default().contains("javascript:"));
+}
+
+#[test]
+fn default_redirect_is_set_by_admins_of_features() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com\nother_session_id: other_mail@mail.com");
+    shortcut("myShortCut/hop", "https://thetarget.test.go.com", "", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[], 0, true)],
+        &[Capability::Features],
+        &mut conn,
+    );
+    user("other_mail@mail.com", "pwd", &[("", &[], 0, true)], &[], &mut conn);
+
+    let response = client
+        .put("/go/settings/default_redirect")
+        .header(ContentType::JSON)
+        .body(r#""Found""#)
+        .cookie(http::Cookie::new(SESSION_COOKIE, "other_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .put("/go/settings/default_redirect")
+        .header(ContentType::JSON)
+        .body(r#""Unknown""#)
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put("/go/settings/default_redirect")
+        .header(ContentType::JSON)
+        .body(r#""Found""#)
+        .cookie(http::Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/myShortCut/hop")
+        .cookie(http::Cookie::new(SESSION_COOKIE, "other_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Found);
+}
@@ -0,0 +1,156 @@
+use go_web::guards::SESSION_COOKIE;
+use go_web::teams::TeamCapability;
+use go_web::users::Capability;
+use rocket::http::{Cookie, Header, Status};
+use rocket::local::blocking::Client;
+use serde_json::{json, Value};
+mod utils;
+use utils::*;
+
+/// A shortcut in the global team, a public team, a private one and a team not accepted yet
+fn launch() -> (Client, diesel::PgConnection) {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    team("public", "Public", false, true, &mut conn);
+    team("private", "Private", true, true, &mut conn);
+    team("pending", "Pending", false, false, &mut conn);
+    shortcut("wiki", "http://localhost/wiki", "", &mut conn);
+    shortcut("docs", "http://localhost/docs", "public", &mut conn);
+    shortcut("wiki", "http://localhost/public_wiki", "public", &mut conn);
+    shortcut("secret", "http://localhost/secret", "private", &mut conn);
+    shortcut("soon", "http://localhost/soon", "pending", &mut conn);
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[
+            ("", &[TeamCapability::ShortcutsWrite], 0, true),
+            ("private", &[], 1, true),
+        ],
+        &[Capability::Features],
+        &mut conn,
+    );
+
+    (client, conn)
+}
+
+fn names(client: &Client, uri: &str) -> Vec<String> {
+    let response = client.get(uri.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    response.into_json::<Value>().unwrap()["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|shortcut| format!("{}@{}", shortcut["shortcut"].as_str().unwrap(), shortcut["team_slug"].as_str().unwrap()))
+        .collect()
+}
+
+#[test]
+fn is_disabled_by_default() {
+    let (client, _conn) = launch();
+
+    assert_eq!(client.get("/wiki").dispatch().status(), Status::Unauthorized);
+    assert_eq!(client.get("/go/api/v1/shortcuts").dispatch().status(), Status::Unauthorized);
+}
+
+#[test]
+fn is_enabled_by_admins_of_features() {
+    let (client, _conn) = launch();
+
+    let response = client
+        .patch("/go/settings")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!({ "login": { "read_private": false } }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/wiki").dispatch();
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+}
+
+#[test]
+fn resolves_shortcuts_of_public_teams_global_first() {
+    let (client, mut conn) = launch();
+    read_private(false, &mut conn);
+
+    let response = client.get("/wiki/page").dispatch();
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    assert_eq!(response.headers().get_one("Location"), Some("http://localhost/wiki/page"));
+
+    let response = client.get("/docs").dispatch();
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+    assert_eq!(response.headers().get_one("Location"), Some("http://localhost/docs"));
+
+    assert_eq!(client.get("/secret").dispatch().status(), Status::NotFound);
+    assert_eq!(client.get("/soon").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn browses_shortcuts_of_public_teams() {
+    let (client, mut conn) = launch();
+    read_private(false, &mut conn);
+
+    assert_eq!(names(&client, "/go/api/v1/shortcuts"), vec!["docs@public", "wiki@", "wiki@public"]);
+    assert_eq!(names(&client, "/go/api/v1/teams/public/shortcuts"), vec!["docs@public", "wiki@public"]);
+    assert_eq!(
+        client.get("/go/api/v1/teams/private/shortcuts").dispatch().status(),
+        Status::Unauthorized
+    );
+    assert_eq!(client.get("/go/api/v1/shortcuts/docs?team=public").dispatch().status(), Status::Ok);
+    assert_eq!(client.get("/go/api/v1/shortcuts/secret?team=private").dispatch().status(), Status::NotFound);
+
+    let page = client.get("/").dispatch();
+    assert_eq!(page.status(), Status::Ok);
+    assert!(!page.into_string().unwrap().contains("secret"));
+}
+
+#[test]
+fn still_requires_a_login_to_write() {
+    let (client, mut conn) = launch();
+    read_private(false, &mut conn);
+
+    let response = client
+        .put("/go/api/v1/shortcuts/new")
+        .body(json!({ "url": "http://localhost/new" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .put("/new")
+        .body(json!({ "url": "http://localhost/new" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/go/user/teams")
+        .body(json!({ "rank": 0 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    assert_eq!(client.get("/go/api/v1/teams").dispatch().status(), Status::Unauthorized);
+}
+
+#[test]
+fn refuses_wrong_tokens() {
+    let (client, mut conn) = launch();
+    read_private(false, &mut conn);
+
+    let response = client
+        .get("/go/api/v1/shortcuts")
+        .header(Header::new("Authorization", "Bearer go_unknown"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn keeps_logged_in_users_reading_their_teams() {
+    let (client, mut conn) = launch();
+    read_private(false, &mut conn);
+
+    let response = client
+        .get("/secret")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::TemporaryRedirect);
+}
@@ -0,0 +1,318 @@
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::login::LoginSuccessfull;
+use go_web::schema::users;
+use go_web::services::mailer::Mailer;
+use go_web::users::{Capability, SessionExpiry, TemporaryPwd};
+use rocket::http::Cookie;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::json;
+use sha256::digest;
+use utils::*;
+
+fn register(client: &Client, mail: &str) -> Status {
+    client
+        .post("/go/register")
+        .body(json!({ "mail": mail, "pwd": "some_pwd" }).to_string())
+        .dispatch()
+        .status()
+}
+
+fn login(client: &Client, mail: &str, pwd: &str) -> Option<LoginSuccessfull> {
+    let response = client
+        .post("/go/login")
+        .body(json!({ "mail": mail, "pwd": pwd }).to_string())
+        .dispatch();
+    if response.status() != Status::Ok {
+        return None;
+    }
+    response.into_json::<LoginSuccessfull>()
+}
+
+fn user_status(client: &Client, session_id: &str) -> Status {
+    client
+        .get("/go/api/v1/user")
+        .cookie(Cookie::new(SESSION_COOKIE, session_id.to_string()))
+        .dispatch()
+        .status()
+}
+
+mod registration {
+    use super::*;
+
+    const CONFIRM_URL: &str = "http://go.test/go/register/confirm";
+
+    fn launch() -> (Client, PgConnection, Outbox) {
+        let outbox = Outbox::default();
+        let (client, conn) = TestServer::default()
+            .db_sessions(SessionExpiry::default())
+            .mailer(Mailer::new(outbox.clone()))
+            .launch();
+        (client, conn, outbox)
+    }
+
+    fn confirm(client: &Client, token: &str) -> Status {
+        client
+            .get(format!("/go/register/confirm?token={token}"))
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn is_disabled_by_default() {
+        let (client, mut conn) = launch_with("");
+
+        assert_eq!(register(&client, "some_mail@mail.go"), Status::Conflict);
+        assert_eq!(get_user("some_mail@mail.go", &mut conn), None);
+    }
+
+    #[test]
+    fn creates_the_user_with_the_default_capabilities_once_the_mailed_link_is_opened() {
+        let (client, mut conn, outbox) = launch();
+        registration(true, &[], &mut conn);
+        default_capabilities(&[Capability::TeamsWrite], &mut conn);
+
+        assert_eq!(register(&client, "some_mail@mail.go"), Status::Accepted);
+        assert_eq!(get_user("some_mail@mail.go", &mut conn), None);
+        assert!(login(&client, "some_mail@mail.go", "some_pwd").is_none());
+        assert_eq!(outbox.mails(&client)[0].to, "some_mail@mail.go");
+
+        let response = client
+            .get(format!("/go/register/confirm?token={}", outbox.last_token(&client, CONFIRM_URL)))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/go/login"));
+
+        assert_eq!(
+            get_user("some_mail@mail.go", &mut conn).unwrap().capabilities,
+            vec![Capability::TeamsWrite]
+        );
+        assert!(login(&client, "some_mail@mail.go", "some_pwd").is_some());
+    }
+
+    #[test]
+    fn links_are_used_once() {
+        let (client, mut conn, outbox) = launch();
+        registration(true, &[], &mut conn);
+
+        register(&client, "some_mail@mail.go");
+        let first = outbox.last_token(&client, CONFIRM_URL);
+        register(&client, "some_mail@mail.go");
+        let second = outbox.last_token(&client, CONFIRM_URL);
+
+        assert_eq!(confirm(&client, &second), Status::SeeOther);
+        assert_eq!(confirm(&client, &second), Status::BadRequest);
+        assert_eq!(confirm(&client, &first), Status::BadRequest);
+        assert_eq!(confirm(&client, "not_a_token"), Status::BadRequest);
+    }
+
+    #[test]
+    fn does_not_take_over_users_created_meanwhile() {
+        let (client, mut conn, outbox) = launch();
+        registration(true, &[], &mut conn);
+
+        register(&client, "some_mail@mail.go");
+        // Like the owner of the mail logging in with an identity provider
+        user("some_mail@mail.go", "", &[], &[], &mut conn);
+
+        assert_eq!(confirm(&client, &outbox.last_token(&client, CONFIRM_URL)), Status::Conflict);
+        assert!(login(&client, "some_mail@mail.go", "some_pwd").is_none());
+    }
+
+    #[test]
+    fn mails_an_address_a_few_times_only() {
+        let (client, mut conn, outbox) = launch();
+        registration(true, &[], &mut conn);
+
+        for _ in 0..3 {
+            assert_eq!(register(&client, "some_mail@mail.go"), Status::Accepted);
+        }
+        assert_eq!(register(&client, "some_mail@mail.go"), Status::TooManyRequests);
+        assert_eq!(outbox.mails(&client).len(), 3);
+    }
+
+    #[test]
+    fn only_allows_the_listed_mail_domains() {
+        let (client, mut conn) = launch_with("");
+        registration(true, &["mail.go"], &mut conn);
+
+        assert_eq!(register(&client, "some_mail@other.go"), Status::Unauthorized);
+        assert_eq!(register(&client, "some_mail@mail.go"), Status::Accepted);
+    }
+
+    #[test]
+    fn refuses_used_mails() {
+        let (client, mut conn) = launch_with("");
+        registration(true, &[], &mut conn);
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        assert_eq!(register(&client, "some_mail@mail.go"), Status::Conflict);
+    }
+
+    #[test]
+    fn settings_are_changed_by_features_admins() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        let put = |client: &Client| {
+            client
+                .put("/go/settings/registration")
+                .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+                .body(json!({ "enabled": true, "allowed_domains": [" @Mail.go "] }).to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(put(&client), Status::Unauthorized);
+
+        diesel::update(users::table)
+            .set(users::capabilities.eq(vec![Capability::Features.to_string()]))
+            .execute(&mut conn)
+            .unwrap();
+        assert_eq!(put(&client), Status::Ok);
+
+        assert_eq!(register(&client, "another_mail@mail.go"), Status::Accepted);
+    }
+}
+
+mod change {
+    use super::*;
+
+    fn change(client: &Client, body: serde_json::Value) -> Status {
+        client
+            .put("/go/api/v1/user/pwd")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(body.to_string())
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn needs_the_current_pwd() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user(
+            "some_mail@mail.go",
+            "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+            &[],
+            &[],
+            &mut conn,
+        );
+
+        assert_eq!(
+            change(&client, json!({ "current_pwd": "wrong_pwd", "new_pwd": "new_pwd" })),
+            Status::Unauthorized
+        );
+        assert_eq!(change(&client, json!({ "new_pwd": "new_pwd" })), Status::Unauthorized);
+        assert_eq!(
+            change(&client, json!({ "current_pwd": "some_pwd", "new_pwd": "new_pwd" })),
+            Status::NoContent
+        );
+
+        assert!(login(&client, "some_mail@mail.go", "some_pwd").is_none());
+        assert!(login(&client, "some_mail@mail.go", "new_pwd").is_some());
+    }
+
+    #[test]
+    fn sets_a_first_pwd_without_current_one() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+        diesel::update(users::table)
+            .set(users::pwd.eq(None::<String>))
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(change(&client, json!({ "new_pwd": "new_pwd" })), Status::NoContent);
+
+        assert!(login(&client, "some_mail@mail.go", "new_pwd").is_some());
+    }
+
+    #[test]
+    fn ends_the_other_sessions() {
+        let (client, mut conn) = launch_with(
+            "some_session_id: some_mail@mail.go\nother_session_id: some_mail@mail.go",
+        );
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+        diesel::update(users::table)
+            .set(users::pwd.eq(None::<String>))
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(change(&client, json!({ "new_pwd": "new_pwd" })), Status::NoContent);
+
+        assert_eq!(user_status(&client, "some_session_id"), Status::Ok);
+        assert_eq!(user_status(&client, "other_session_id"), Status::Unauthorized);
+    }
+
+    #[test]
+    fn refuses_an_empty_pwd() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+        diesel::update(users::table)
+            .set(users::pwd.eq(None::<String>))
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(change(&client, json!({ "new_pwd": "" })), Status::BadRequest);
+    }
+}
+
+mod temporary {
+    use super::*;
+
+    fn set_temporary_pwd(client: &Client, mail: &str) -> (Status, Option<TemporaryPwd>) {
+        let response = client
+            .post(format!("/go/api/v1/users/{mail}/temporary_pwd"))
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+        (response.status(), response.into_json::<TemporaryPwd>())
+    }
+
+    #[test]
+    fn is_set_by_users_admins() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+        user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        assert_eq!(set_temporary_pwd(&client, "another_mail@mail.go").0, Status::Unauthorized);
+    }
+
+    #[test]
+    fn logs_the_user_out_everywhere() {
+        let (client, mut conn) = launch_with(
+            "some_session_id: some_mail@mail.go\nanother_session_id: another_mail@mail.go",
+        );
+        user("some_mail@mail.go", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+        user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        assert_eq!(set_temporary_pwd(&client, "another_mail@mail.go").0, Status::Created);
+
+        assert_eq!(user_status(&client, "another_session_id"), Status::Unauthorized);
+        assert_eq!(user_status(&client, "some_session_id"), Status::Ok);
+    }
+
+    #[test]
+    fn must_be_changed_after_login() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        user("some_mail@mail.go", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+        user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        assert_eq!(set_temporary_pwd(&client, "unknown@mail.go").0, Status::NotFound);
+        let (status, temporary) = set_temporary_pwd(&client, "another_mail@mail.go");
+        assert_eq!(status, Status::Created);
+
+        // As hashed by the login page
+        let sent = digest(format!("{}salt1", temporary.unwrap().pwd));
+        let logged_in = login(&client, "another_mail@mail.go", &sent).unwrap();
+        assert!(logged_in.pwd_is_temporary);
+
+        let response = client
+            .put("/go/api/v1/user/pwd")
+            .cookie(Cookie::new(SESSION_COOKIE, logged_in.token))
+            .body(json!({ "current_pwd": sent, "new_pwd": "new_pwd" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+
+        assert!(!login(&client, "another_mail@mail.go", "new_pwd").unwrap().pwd_is_temporary);
+    }
+}
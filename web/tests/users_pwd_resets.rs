@@ -0,0 +1,220 @@
+use chrono::{TimeDelta, Utc};
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::login::LoginSuccessfull;
+use go_web::schema::{pwd_resets, sessions};
+use go_web::services::mailer::{Mail, MailTransport, Mailer, MailerError};
+use go_web::users::SessionExpiry;
+use rocket::http::Cookie;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::{json, Value};
+use sha256::digest;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use utils::*;
+
+fn launch() -> (Client, PgConnection, Outbox) {
+    let outbox = Outbox::default();
+    let (client, conn) = TestServer::default()
+        .db_sessions(SessionExpiry::default())
+        .mailer(Mailer::new(outbox.clone()))
+        .launch();
+    (client, conn, outbox)
+}
+
+fn forgot(client: &Client, mail: &str) -> Status {
+    client
+        .post("/go/password/forgot")
+        .body(json!({ "mail": mail }).to_string())
+        .dispatch()
+        .status()
+}
+
+fn reset(client: &Client, token: &str, pwd: &str) -> Status {
+    client
+        .post("/go/password/reset")
+        .body(json!({ "token": token, "pwd": pwd }).to_string())
+        .dispatch()
+        .status()
+}
+
+fn login(client: &Client, mail: &str, pwd: &str) -> Status {
+    client
+        .post("/go/login")
+        .body(json!({ "mail": mail, "pwd": pwd }).to_string())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn mail_a_link_setting_a_new_pwd() {
+    let (client, mut conn, outbox) = launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+    assert_eq!(forgot(&client, "some_mail@mail.go"), Status::Accepted);
+
+    let mails = outbox.mails(&client);
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "some_mail@mail.go");
+    let token = outbox.last_token(&client, "http://go.test/go/password/reset");
+    let response = client
+        .get(format!("/go/password/reset?token={token}"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(reset(&client, &token, "new_pwd"), Status::NoContent);
+    assert_eq!(login(&client, "some_mail@mail.go", "new_pwd"), Status::Ok);
+}
+
+#[test]
+fn form_refuses_what_is_not_a_token() {
+    let (client, _conn, _outbox) = launch();
+
+    for token in ["</script><script>alert(1)</script>", "0123456789ABCDEF0123456789abcdef", "0123"] {
+        let response = client
+            .get(format!("/go/password/reset?token={}", rocket::http::RawStr::new(token).percent_encode()))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "{token}");
+        assert!(!response.into_string().unwrap().contains("<script>alert"));
+    }
+}
+
+#[test]
+fn links_are_single_use() {
+    let (client, mut conn, outbox) = launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+    forgot(&client, "some_mail@mail.go");
+    let token = outbox.last_token(&client, "http://go.test/go/password/reset");
+
+    assert_eq!(reset(&client, &token, "new_pwd"), Status::NoContent);
+    let response = client
+        .post("/go/password/reset")
+        .body(json!({ "token": token, "pwd": "other_pwd" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.into_json::<Value>().unwrap()["error"],
+        json!("Invalid or expired reset link.")
+    );
+    assert_eq!(login(&client, "some_mail@mail.go", "new_pwd"), Status::Ok);
+}
+
+#[test]
+fn links_expire() {
+    let (client, mut conn, _outbox) = launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    diesel::insert_into(pwd_resets::table)
+        .values((
+            pwd_resets::hash.eq(digest("some_token")),
+            pwd_resets::user_mail.eq("some_mail@mail.go"),
+            pwd_resets::created_at.eq(Utc::now().naive_utc() - TimeDelta::hours(2)),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+
+    assert_eq!(reset(&client, "some_token", "new_pwd"), Status::BadRequest);
+    assert_eq!(reset(&client, "unknown_token", "new_pwd"), Status::BadRequest);
+}
+
+#[test]
+fn unknown_mails_get_the_same_answer_but_no_mail() {
+    let (client, _conn, outbox) = launch();
+
+    assert_eq!(forgot(&client, "unknown@mail.go"), Status::Accepted);
+    assert_eq!(forgot(&client, "not a mail"), Status::BadRequest);
+
+    assert!(outbox.mails(&client).is_empty());
+}
+
+#[test]
+fn are_rate_limited_per_address() {
+    let (client, mut conn, outbox) = launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+    for _ in 0..4 {
+        assert_eq!(forgot(&client, "some_mail@mail.go"), Status::Accepted);
+    }
+    assert_eq!(forgot(&client, "another_mail@mail.go"), Status::Accepted);
+
+    let mails = outbox.mails(&client);
+    assert_eq!(mails.iter().filter(|m| m.to == "some_mail@mail.go").count(), 3);
+    assert_eq!(mails.iter().filter(|m| m.to == "another_mail@mail.go").count(), 1);
+}
+
+#[test]
+fn end_every_session_of_the_user() {
+    let (client, mut conn, outbox) = launch();
+    user(
+        "some_mail@mail.go",
+        "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+        &[],
+        &[],
+        &mut conn,
+    );
+    let token = client
+        .post("/go/login")
+        .body(json!({ "mail": "some_mail@mail.go", "pwd": "some_pwd" }).to_string())
+        .dispatch()
+        .into_json::<LoginSuccessfull>()
+        .unwrap()
+        .token;
+
+    forgot(&client, "some_mail@mail.go");
+    assert_eq!(reset(&client, &outbox.last_token(&client, "http://go.test/go/password/reset"), "new_pwd"), Status::NoContent);
+
+    let response = client
+        .get("/go/api/v1/user")
+        .cookie(Cookie::new(SESSION_COOKIE, token))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let remaining: i64 = sessions::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[test]
+fn can_be_written_to_a_file() {
+    let path = std::env::temp_dir().join(format!("go_mails_{}", uuid::Uuid::new_v4()));
+    let (client, mut conn) = TestServer::default()
+        .db_sessions(SessionExpiry::default())
+        .mailer(Mailer::file(&path))
+        .launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+    forgot(&client, "some_mail@mail.go");
+    client.rocket().state::<Mailer>().unwrap().flush(Duration::from_secs(5));
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(written.starts_with("To: some_mail@mail.go\nSubject: Reset your password\n"));
+    assert!(written.contains("http://go.test/go/password/reset?token="));
+}
+
+/// Sends once the test lets it
+struct Held(Arc<Mutex<()>>, Outbox);
+
+impl MailTransport for Held {
+    fn send(&self, mail: &Mail) -> Result<(), MailerError> {
+        let _released = self.0.lock().unwrap();
+        self.1.send(mail)
+    }
+}
+
+#[test]
+fn are_mailed_after_answering() {
+    let (held, outbox) = (Arc::new(Mutex::new(())), Outbox::default());
+    let (client, mut conn) = TestServer::default()
+        .db_sessions(SessionExpiry::default())
+        .mailer(Mailer::new(Held(held.clone(), outbox.clone())))
+        .launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+    let release = held.lock().unwrap();
+    assert_eq!(forgot(&client, "some_mail@mail.go"), Status::Accepted);
+    drop(release);
+
+    assert_eq!(outbox.mails(&client).len(), 1);
+}
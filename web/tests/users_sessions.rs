@@ -0,0 +1,243 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+use go_web::guards::SESSION_COOKIE;
+use go_web::login::LoginSuccessfull;
+use go_web::schema::sessions;
+use go_web::users::{Capability, SessionExpiry, Sessions};
+use go_web::DbPool;
+use rocket::http::Cookie;
+use rocket::http::Header;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::{json, Value};
+use sha256::digest;
+use utils::*;
+
+fn db_session(session_id: &str, mail: &str, created_ago: TimeDelta, seen_ago: TimeDelta, conn: &mut PgConnection) {
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(sessions::table)
+        .values((
+            sessions::id.eq(digest(session_id)),
+            sessions::value.eq(mail),
+            sessions::created_at.eq(now - created_ago),
+            sessions::last_seen_at.eq(now - seen_ago),
+        ))
+        .execute(conn)
+        .unwrap();
+}
+
+fn user_status(client: &Client, session_id: &str) -> Status {
+    client
+        .get("/go/api/v1/user")
+        .cookie(Cookie::new(SESSION_COOKIE, session_id.to_string()))
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn are_stored_hashed_in_the_database() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user(
+        "some_mail@mail.go",
+        "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+        &[],
+        &[],
+        &mut conn,
+    );
+
+    let token = client
+        .post("/go/login")
+        .body(json!({ "mail": "some_mail@mail.go", "pwd": "some_pwd" }).to_string())
+        .dispatch()
+        .into_json::<LoginSuccessfull>()
+        .unwrap()
+        .token;
+
+    assert_eq!(user_status(&client, &token), Status::Ok);
+    let stored: Vec<(String, String)> = sessions::table
+        .select((sessions::id, sessions::value))
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(stored, vec![(digest(token.as_str()), "some_mail@mail.go".to_string())]);
+}
+
+#[test]
+fn are_marked_as_seen_when_used() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("some_session_id", "some_mail@mail.go", TimeDelta::days(2), TimeDelta::days(1), &mut conn);
+
+    assert_eq!(user_status(&client, "some_session_id"), Status::Ok);
+
+    let last_seen_at: NaiveDateTime = sessions::table
+        .select(sessions::last_seen_at)
+        .first(&mut conn)
+        .unwrap();
+    assert!(Utc::now().naive_utc() - last_seen_at < TimeDelta::minutes(1));
+}
+
+#[test]
+fn expire_when_idle_for_too_long() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry {
+        idle: TimeDelta::hours(1),
+        absolute: TimeDelta::days(1),
+    }).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("idle_session_id", "some_mail@mail.go", TimeDelta::hours(2), TimeDelta::hours(2), &mut conn);
+    db_session("used_session_id", "some_mail@mail.go", TimeDelta::hours(2), TimeDelta::minutes(30), &mut conn);
+
+    assert_eq!(user_status(&client, "idle_session_id"), Status::Unauthorized);
+    assert_eq!(user_status(&client, "used_session_id"), Status::Ok);
+}
+
+#[test]
+fn expire_after_their_max_age_even_when_used() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry {
+        idle: TimeDelta::hours(1),
+        absolute: TimeDelta::days(1),
+    }).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("old_session_id", "some_mail@mail.go", TimeDelta::days(2), TimeDelta::minutes(1), &mut conn);
+
+    assert_eq!(user_status(&client, "old_session_id"), Status::Unauthorized);
+}
+
+#[test]
+fn expired_ones_are_purged() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry {
+        idle: TimeDelta::hours(1),
+        absolute: TimeDelta::days(1),
+    }).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("idle_session_id", "some_mail@mail.go", TimeDelta::hours(2), TimeDelta::hours(2), &mut conn);
+    db_session("old_session_id", "some_mail@mail.go", TimeDelta::days(2), TimeDelta::minutes(1), &mut conn);
+    db_session("live_session_id", "some_mail@mail.go", TimeDelta::hours(2), TimeDelta::minutes(1), &mut conn);
+
+    let sessions = client.rocket().state::<Sessions>().unwrap();
+    let pool = client.rocket().state::<DbPool>().unwrap();
+    let purged = sessions.purge_expired(&mut pool.get().unwrap()).unwrap();
+
+    assert_eq!(purged, 2);
+    let remaining: Vec<String> = sessions::table
+        .select(sessions::id)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, vec![digest("live_session_id")]);
+}
+
+#[test]
+fn logout_ends_the_session_and_clears_the_cookie() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("some_session_id", "some_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+
+    let response = client
+        .post("/go/logout")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some("/go/login"));
+    assert_eq!(response.cookies().get(SESSION_COOKIE).map(|c| c.value()), Some(""));
+    assert_eq!(user_status(&client, "some_session_id"), Status::Unauthorized);
+}
+
+#[test]
+fn logout_without_session_only_redirects() {
+    let (client, _conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+
+    let response = client.post("/go/logout").dispatch();
+
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[test]
+fn are_listed_with_their_client() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user(
+        "some_mail@mail.go",
+        "b112aa82a7aafb32aea966cafd2f6bb2562c34d2f08bb1dee9fab4b2b223ea20",
+        &[],
+        &[],
+        &mut conn,
+    );
+    user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("old_session_id", "some_mail@mail.go", TimeDelta::days(1), TimeDelta::hours(1), &mut conn);
+    db_session("another_session_id", "another_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+
+    let token = client
+        .post("/go/login")
+        .header(Header::new("User-Agent", "some browser"))
+        .body(json!({ "mail": "some_mail@mail.go", "pwd": "some_pwd" }).to_string())
+        .dispatch()
+        .into_json::<LoginSuccessfull>()
+        .unwrap()
+        .token;
+
+    let listed = client
+        .get("/go/api/v1/user/sessions")
+        .cookie(Cookie::new(SESSION_COOKIE, token.clone()))
+        .dispatch()
+        .into_json::<Value>()
+        .unwrap();
+
+    let listed = listed.as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["id"], json!(digest(token.as_str())));
+    assert_eq!(listed[0]["user_agent"], json!("some browser"));
+    assert_eq!(listed[0]["current"], json!(true));
+    assert_eq!(listed[1]["id"], json!(digest("old_session_id")));
+    assert_eq!(listed[1]["user_agent"], Value::Null);
+    assert_eq!(listed[1]["current"], json!(false));
+}
+
+#[test]
+fn can_be_revoked_one_by_one_by_their_user() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user("some_mail@mail.go", "pwd", &[], &[], &mut conn);
+    user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("some_session_id", "some_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+    db_session("other_session_id", "some_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+    db_session("another_session_id", "another_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+
+    let revoke = |id: &str| {
+        client
+            .delete(format!("/go/api/v1/user/sessions/{id}"))
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch()
+            .status()
+    };
+
+    assert_eq!(revoke(&digest("other_session_id")), Status::NoContent);
+    assert_eq!(revoke(&digest("another_session_id")), Status::NotFound);
+    assert_eq!(user_status(&client, "other_session_id"), Status::Unauthorized);
+    assert_eq!(user_status(&client, "another_session_id"), Status::Ok);
+    assert_eq!(user_status(&client, "some_session_id"), Status::Ok);
+}
+
+#[test]
+fn can_all_be_revoked_by_an_admin() {
+    let (client, mut conn) = TestServer::default().db_sessions(SessionExpiry::default()).launch();
+    user("some_mail@mail.go", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+    user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+    db_session("some_session_id", "some_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+    db_session("another_session_id", "another_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+    db_session("other_session_id", "another_mail@mail.go", TimeDelta::zero(), TimeDelta::zero(), &mut conn);
+
+    let response = client
+        .delete("/go/api/v1/users/some_mail@mail.go/sessions")
+        .cookie(Cookie::new(SESSION_COOKIE, "another_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .delete("/go/api/v1/users/another_mail@mail.go/sessions")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(user_status(&client, "another_session_id"), Status::Unauthorized);
+    assert_eq!(user_status(&client, "other_session_id"), Status::Unauthorized);
+    assert_eq!(user_status(&client, "some_session_id"), Status::Ok);
+}
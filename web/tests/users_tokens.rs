@@ -0,0 +1,140 @@
+use go_web::guards::SESSION_COOKIE;
+use go_web::teams::TeamCapability;
+use go_web::users::Capability;
+use rocket::http::ContentType;
+use rocket::http::Cookie;
+use rocket::http::Header;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+mod utils;
+use serde_json::{json, Value};
+use utils::*;
+
+fn create_token(client: &Client, body: Value) -> Value {
+    let response = client
+        .post("/go/api/v1/user/tokens")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(body.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Created);
+
+    response.into_json::<Value>().unwrap()
+}
+
+fn bearer(secret: &Value) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", secret.as_str().unwrap()))
+}
+
+#[test]
+fn authenticate_as_their_user() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user("some_mail@mail.com", "pwd", &[], &[Capability::UsersAdmin], &mut conn);
+
+    let created = create_token(&client, json!({"name": " cli "}));
+    assert_eq!(created["name"], "cli");
+    assert_eq!(created["scopes"], json!([]));
+    assert!(created["secret"].as_str().unwrap().starts_with("go_"));
+
+    let response = client
+        .get("/go/api/v1/user")
+        .header(bearer(&created["secret"]))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_json::<Value>(),
+        Some(json!({"mail": "some_mail@mail.com", "capabilities": ["UsersAdmin"]}))
+    );
+
+    let response = client
+        .get("/go/api/v1/user/tokens")
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    let tokens = response.into_json::<Value>().unwrap();
+    assert_eq!(tokens[0]["name"], "cli");
+    assert!(tokens[0]["last_used_at"].is_string());
+    assert!(tokens[0].get("secret").is_none());
+
+    let response = client
+        .post("/go/api/v1/user/tokens")
+        .header(ContentType::JSON)
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .body(json!({"name": "cli"}).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[test]
+fn unknown_or_revoked_are_unauthorized() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user("some_mail@mail.com", "pwd", &[], &[], &mut conn);
+
+    let response = client
+        .get("/go/api/v1/user")
+        .header(Header::new("Authorization", "Bearer go_unknown"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let created = create_token(&client, json!({"name": "cli"}));
+
+    let response = client
+        .delete(format!("/go/api/v1/user/tokens/{}", created["id"]))
+        .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client
+        .get("/go/api/v1/user")
+        .header(bearer(&created["secret"]))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn are_restricted_to_their_scopes() {
+    let (client, mut conn) = launch_with("some_session_id: some_mail@mail.com");
+    user(
+        "some_mail@mail.com",
+        "pwd",
+        &[("", &[TeamCapability::ShortcutsWrite], 0, true)],
+        &[Capability::TeamsWrite],
+        &mut conn,
+    );
+
+    let read = create_token(&client, json!({"name": "read", "scopes": ["Read"]}));
+    let write = create_token(&client, json!({"name": "write", "scopes": ["ShortcutsWrite"]}));
+
+    let put_shortcut = |secret: &Value| {
+        client
+            .put("/go/api/v1/shortcuts/docs")
+            .header(ContentType::JSON)
+            .header(bearer(secret))
+            .body(r#"{"url": "http://localhost/docs"}"#)
+            .dispatch()
+            .status()
+    };
+    assert_eq!(put_shortcut(&read["secret"]), Status::Unauthorized);
+    assert_eq!(put_shortcut(&write["secret"]), Status::Ok);
+
+    let response = client
+        .get("/go/api/v1/shortcuts")
+        .header(bearer(&read["secret"]))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/go/api/v1/teams")
+        .header(ContentType::JSON)
+        .header(bearer(&write["secret"]))
+        .body(r#"{"slug": "slug1", "title": "team1", "is_private": false}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/go/api/v1/user/tokens")
+        .header(ContentType::JSON)
+        .header(bearer(&write["secret"]))
+        .body(json!({"name": "other"}).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
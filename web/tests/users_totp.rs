@@ -0,0 +1,420 @@
+use chrono::{TimeDelta, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use go_web::guards::SESSION_COOKIE;
+use go_web::login::{LoginSuccessfull, SecondFactorRequired};
+use go_web::schema::{second_factor_logins, totps};
+use go_web::services::mailer::Mailer;
+use go_web::users::{Capability, RecoveryCodes, SessionExpiry, TotpEnrollment};
+use rocket::http::{Cookie, Header, Status};
+use rocket::local::blocking::Client;
+use serde_json::{json, Value};
+use sha256::digest;
+use totp_rs::{Algorithm, Secret, TOTP};
+mod utils;
+use utils::*;
+
+const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+/// The code shown `steps` periods from now
+fn code(secret: &str, steps: i64) -> String {
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        None,
+        "some_mail@mail.go".to_string(),
+    )
+    .unwrap();
+    totp.generate((Utc::now().timestamp() + steps * 30) as u64)
+}
+
+/// `some_pwd` as stored for `some_mail@mail.go`
+fn password_user(capabilities: &[Capability], conn: &mut PgConnection) {
+    user("some_mail@mail.go", &digest("some_pwdsalt2"), &[], capabilities, conn);
+}
+
+fn login(client: &Client) -> (Status, Option<SecondFactorRequired>) {
+    let response = client
+        .post("/go/login")
+        .body(json!({ "mail": "some_mail@mail.go", "pwd": "some_pwd" }).to_string())
+        .dispatch();
+
+    (response.status(), response.into_json())
+}
+
+fn post_code(client: &Client, token: &str, code: &str) -> (Status, Option<LoginSuccessfull>) {
+    let response = client
+        .post("/go/login/second_factor")
+        .body(json!({ "token": token, "code": code }).to_string())
+        .dispatch();
+
+    (response.status(), response.into_json())
+}
+
+fn has_totp(conn: &mut PgConnection) -> bool {
+    diesel::select(diesel::dsl::exists(totps::table.find("some_mail@mail.go").filter(totps::enabled)))
+        .get_result(conn)
+        .unwrap()
+}
+
+fn user_status(client: &Client, session_id: &str) -> Status {
+    client
+        .get("/go/api/v1/user")
+        .cookie(Cookie::new(SESSION_COOKIE, session_id.to_string()))
+        .dispatch()
+        .status()
+}
+
+mod enrollment {
+    use super::*;
+
+    #[test]
+    fn is_confirmed_by_a_code_of_the_authenticator() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[], &mut conn);
+
+        let response = client
+            .post("/go/api/v1/user/totp")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let enrollment = response.into_json::<TotpEnrollment>().unwrap();
+        assert!(enrollment
+            .provisioning_uri
+            .starts_with("otpauth://totp/go:some_mail%40mail.go?secret="));
+        assert!(enrollment.qr_code.contains("<svg"));
+        // Not enabled until confirmed
+        assert_eq!(login(&client).0, Status::Ok);
+
+        let confirm = |code: &str| {
+            client
+                .put("/go/api/v1/user/totp")
+                .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+                .body(json!({ "code": code }).to_string())
+                .dispatch()
+        };
+        assert_eq!(confirm("abcdef").status(), Status::BadRequest);
+        let response = confirm(&code(&enrollment.secret, -1));
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<RecoveryCodes>().unwrap().recovery_codes.len(), 10);
+        assert_eq!(confirm(&code(&enrollment.secret, 0)).status(), Status::Conflict);
+
+        let (status, step) = login(&client);
+        assert_eq!(status, Status::Accepted);
+        assert!(step.unwrap().enrollment.is_none());
+    }
+
+    #[test]
+    fn is_refused_to_scoped_tokens() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[], &mut conn);
+        let created = client
+            .post("/go/api/v1/user/tokens")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(json!({ "name": "read", "scopes": ["Read"] }).to_string())
+            .dispatch()
+            .into_json::<Value>()
+            .unwrap();
+
+        let response = client
+            .post("/go/api/v1/user/totp")
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", created["secret"].as_str().unwrap()),
+            ))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(!has_totp(&mut conn));
+    }
+}
+
+mod login {
+    use super::*;
+
+    #[test]
+    fn takes_a_code_after_the_password() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let (status, step) = login(&client);
+        assert_eq!(status, Status::Accepted);
+        let token = step.unwrap().token;
+        assert_eq!(user_status(&client, &token), Status::Unauthorized);
+
+        assert_eq!(post_code(&client, &token, "000000").0, Status::Unauthorized);
+        let (status, logged_in) = post_code(&client, &token, &code(SECRET, 0));
+        assert_eq!(status, Status::Ok);
+        let logged_in = logged_in.unwrap();
+        assert!(logged_in.recovery_codes.is_empty());
+        assert_eq!(user_status(&client, &logged_in.token), Status::Ok);
+
+        // The login is done
+        assert_eq!(post_code(&client, &token, &code(SECRET, 1)).0, Status::BadRequest);
+    }
+
+    #[test]
+    fn never_takes_a_code_twice() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let token = login(&client).1.unwrap().token;
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::Ok);
+
+        let token = login(&client).1.unwrap().token;
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::Unauthorized);
+        assert_eq!(post_code(&client, &token, &code(SECRET, -1)).0, Status::Unauthorized);
+        assert_eq!(post_code(&client, &token, &code(SECRET, 1)).0, Status::Ok);
+    }
+
+    #[test]
+    fn stops_after_too_many_codes() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let token = login(&client).1.unwrap().token;
+        for _ in 0..5 {
+            assert_eq!(post_code(&client, &token, "abcdef").0, Status::Unauthorized);
+        }
+
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::BadRequest);
+    }
+
+    #[test]
+    fn new_logins_do_not_give_more_guesses() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        for _ in 0..2 {
+            let token = login(&client).1.unwrap().token;
+            for _ in 0..5 {
+                assert_eq!(post_code(&client, &token, "abcdef").0, Status::Unauthorized);
+            }
+        }
+
+        let token = login(&client).1.unwrap().token;
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::TooManyRequests);
+        assert_eq!(post_code(&client, &token, "abcdef").0, Status::TooManyRequests);
+    }
+
+    #[test]
+    fn right_codes_forget_the_wrong_ones() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let token = login(&client).1.unwrap().token;
+        for _ in 0..4 {
+            assert_eq!(post_code(&client, &token, "abcdef").0, Status::Unauthorized);
+        }
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::Ok);
+
+        for _ in 0..2 {
+            let token = login(&client).1.unwrap().token;
+            for _ in 0..4 {
+                assert_eq!(post_code(&client, &token, "abcdef").0, Status::Unauthorized);
+            }
+        }
+        let token = login(&client).1.unwrap().token;
+        assert_eq!(post_code(&client, &token, &code(SECRET, 1)).0, Status::Ok);
+    }
+
+    #[test]
+    fn expires() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let token = login(&client).1.unwrap().token;
+        diesel::update(second_factor_logins::table)
+            .set(second_factor_logins::created_at.eq(Utc::now().naive_utc() - TimeDelta::minutes(6)))
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(post_code(&client, &token, &code(SECRET, 0)).0, Status::BadRequest);
+    }
+
+    #[test]
+    fn takes_each_recovery_code_once() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[], &mut conn);
+        let enrollment = client
+            .post("/go/api/v1/user/totp")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .dispatch()
+            .into_json::<TotpEnrollment>()
+            .unwrap();
+        let recovery_codes = client
+            .put("/go/api/v1/user/totp")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(json!({ "code": code(&enrollment.secret, 0) }).to_string())
+            .dispatch()
+            .into_json::<RecoveryCodes>()
+            .unwrap()
+            .recovery_codes;
+
+        let token = login(&client).1.unwrap().token;
+        let typed = recovery_codes[0].to_uppercase().replace('-', " ");
+        assert_eq!(post_code(&client, &token, &typed).0, Status::Ok);
+
+        let token = login(&client).1.unwrap().token;
+        assert_eq!(post_code(&client, &token, &recovery_codes[0]).0, Status::Unauthorized);
+        assert_eq!(post_code(&client, &token, &recovery_codes[1]).0, Status::Ok);
+    }
+
+    #[test]
+    fn without_authenticator_is_unchanged() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+
+        assert_eq!(login(&client).0, Status::Ok);
+    }
+}
+
+mod required {
+    use super::*;
+
+    #[test]
+    fn is_set_by_users_admins() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[Capability::Features], &mut conn);
+
+        let put = |client: &Client| {
+            client
+                .put("/go/settings/second_factor")
+                .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+                .body(json!({ "required": true }).to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(put(&client), Status::Unauthorized);
+
+        diesel::update(go_web::schema::users::table)
+            .set(go_web::schema::users::capabilities.eq(vec![Capability::UsersAdmin.to_string()]))
+            .execute(&mut conn)
+            .unwrap();
+        assert_eq!(put(&client), Status::Ok);
+
+        let (status, step) = login(&client);
+        assert_eq!(status, Status::Accepted);
+        assert!(step.unwrap().enrollment.is_some());
+    }
+
+    #[test]
+    fn enrolls_users_without_authenticator_on_login() {
+        let (client, mut conn) = launch_with("");
+        password_user(&[], &mut conn);
+        second_factor(true, &mut conn);
+
+        let (status, step) = login(&client);
+        assert_eq!(status, Status::Accepted);
+        let step = step.unwrap();
+        let enrollment = step.enrollment.unwrap();
+        assert_eq!(user_status(&client, &step.token), Status::Unauthorized);
+
+        let (status, logged_in) = post_code(&client, &step.token, &code(&enrollment.secret, 0));
+        assert_eq!(status, Status::Ok);
+        let logged_in = logged_in.unwrap();
+        assert_eq!(logged_in.recovery_codes.len(), 10);
+        assert_eq!(user_status(&client, &logged_in.token), Status::Ok);
+
+        let (status, step) = login(&client);
+        assert_eq!(status, Status::Accepted);
+        assert!(step.unwrap().enrollment.is_none());
+    }
+
+    #[test]
+    fn applies_to_registered_users() {
+        let outbox = Outbox::default();
+        let (client, mut conn) = TestServer::default()
+            .db_sessions(SessionExpiry::default())
+            .mailer(Mailer::new(outbox.clone()))
+            .launch();
+        registration(true, &[], &mut conn);
+        second_factor(true, &mut conn);
+
+        let credentials = json!({ "mail": "another_mail@mail.go", "pwd": "some_pwd" }).to_string();
+        client.post("/go/register").body(&credentials).dispatch();
+        let token = outbox.last_token(&client, "http://go.test/go/register/confirm");
+        client.get(format!("/go/register/confirm?token={token}")).dispatch();
+
+        let response = client.post("/go/login").body(&credentials).dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        assert!(response.into_json::<SecondFactorRequired>().unwrap().enrollment.is_some());
+    }
+
+    #[test]
+    fn the_authenticator_cant_be_removed() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+        second_factor(true, &mut conn);
+
+        let response = client
+            .delete("/go/api/v1/user/totp")
+            .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+            .body(json!({ "code": code(SECRET, 0) }).to_string())
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Conflict);
+        assert!(has_totp(&mut conn));
+    }
+}
+
+mod removal {
+    use super::*;
+
+    #[test]
+    fn takes_a_code() {
+        let (client, mut conn) = launch_with("some_session_id: some_mail@mail.go");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+
+        let delete = |code: &str| {
+            client
+                .delete("/go/api/v1/user/totp")
+                .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+                .body(json!({ "code": code }).to_string())
+                .dispatch()
+                .status()
+        };
+        assert_eq!(delete("abcdef"), Status::Unauthorized);
+        assert_eq!(delete(&code(SECRET, 0)), Status::NoContent);
+        assert_eq!(delete(&code(SECRET, 1)), Status::NotFound);
+
+        assert_eq!(login(&client).0, Status::Ok);
+    }
+
+    #[test]
+    fn by_users_admins_for_lost_authenticators() {
+        let (client, mut conn) = launch_with("some_session_id: another_mail@mail.go");
+        password_user(&[], &mut conn);
+        totp("some_mail@mail.go", SECRET, &mut conn);
+        user("another_mail@mail.go", "pwd", &[], &[], &mut conn);
+
+        let reset = |client: &Client| {
+            client
+                .delete("/go/api/v1/users/some_mail@mail.go/totp")
+                .cookie(Cookie::new(SESSION_COOKIE, "some_session_id"))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(reset(&client), Status::Unauthorized);
+
+        diesel::update(go_web::schema::users::table.find("another_mail@mail.go"))
+            .set(go_web::schema::users::capabilities.eq(vec![Capability::UsersAdmin.to_string()]))
+            .execute(&mut conn)
+            .unwrap();
+        assert_eq!(reset(&client), Status::NoContent);
+        assert_eq!(reset(&client), Status::NotFound);
+
+        assert_eq!(login(&client).0, Status::Ok);
+    }
+}
@@ -7,13 +7,16 @@ mod no_dead_code {
     use diesel::prelude::*;
     use go_web::{
         guards::SESSION_COOKIE,
-            settings::DEFAULT_CAPABILITIES,
-            shortcuts::NewShortcut,
+            settings::{
+                Features, Registration, SecondFactorPolicy, CLAIM_RULES, DEFAULT_CAPABILITIES, DEFAULT_REDIRECT, FEATURES,
+                REGISTRATION, SECOND_FACTOR,
+            },
+            shortcuts::{NewShortcut, RedirectMode},
             teams::{Team, TeamCapability},
             users::{Capability, UserTeam, UserWithPwd},
         schema::users,
         schema::{settings, teams},
-        schema::{shortcuts, users_teams},
+        schema::{shortcuts, totps, users_teams},
     };
     use serde_json::json;
     use thirtyfour::Cookie;
@@ -24,6 +27,9 @@ mod no_dead_code {
                 shortcut: shortcut.to_string(),
                 url: url.to_string(),
                 team_slug: team_slug.to_string(),
+                redirect: None,
+                description: None,
+                tags: vec![],
             })
             .execute(db_con)
             .unwrap();
@@ -67,6 +73,70 @@ mod no_dead_code {
             .unwrap();
     }
 
+    pub fn default_redirect(redirect: RedirectMode, db_con: &mut PgConnection) {
+        diesel::update(settings::table)
+            .set(settings::content.eq(json!(redirect).to_string()))
+            .filter(settings::title.eq(DEFAULT_REDIRECT))
+            .execute(db_con)
+            .unwrap();
+    }
+
+    pub fn registration(enabled: bool, allowed_domains: &[&str], db_con: &mut PgConnection) {
+        let registration = Registration {
+            enabled,
+            allowed_domains: allowed_domains.iter().map(|d| d.to_string()).collect(),
+        };
+        diesel::update(settings::table)
+            .set(settings::content.eq(json!(registration).to_string()))
+            .filter(settings::title.eq(REGISTRATION))
+            .execute(db_con)
+            .unwrap();
+    }
+
+    /// As JSON, to be written like admins would
+    pub fn claim_rules(rules: serde_json::Value, db_con: &mut PgConnection) {
+        diesel::update(settings::table)
+            .set(settings::content.eq(rules.to_string()))
+            .filter(settings::title.eq(CLAIM_RULES))
+            .execute(db_con)
+            .unwrap();
+    }
+
+    pub fn features(features: Features, db_con: &mut PgConnection) {
+        diesel::update(settings::table)
+            .set(settings::content.eq(json!(features).to_string()))
+            .filter(settings::title.eq(FEATURES))
+            .execute(db_con)
+            .unwrap();
+    }
+
+    /// Reading shortcuts without login is allowed when `false`
+    pub fn read_private(read_private: bool, db_con: &mut PgConnection) {
+        let mut all = Features::default();
+        all.login.read_private = read_private;
+        features(all, db_con);
+    }
+
+    pub fn second_factor(required: bool, db_con: &mut PgConnection) {
+        diesel::update(settings::table)
+            .set(settings::content.eq(json!(SecondFactorPolicy { required }).to_string()))
+            .filter(settings::title.eq(SECOND_FACTOR))
+            .execute(db_con)
+            .unwrap();
+    }
+
+    /// Enabled authenticator of `mail`, `secret` being base32
+    pub fn totp(mail: &str, secret: &str, db_con: &mut PgConnection) {
+        diesel::insert_into(totps::table)
+            .values((
+                totps::user_mail.eq(mail),
+                totps::secret.eq(secret),
+                totps::enabled.eq(true),
+            ))
+            .execute(db_con)
+            .unwrap();
+    }
+
     pub fn team(
         slug: &str,
         title: &str,
@@ -3,10 +3,13 @@ pub use no_dead_code::*;
 #[allow(dead_code)]
 mod no_dead_code {
     use diesel::{prelude::*, sql_types::Bool, Connection, PgConnection};
-    use go_web::{users::Sessions, server, services::oidc::OidcService, AppConfig};
+    use go_web::{
+        users::{PasswordHashing, ProxyAuth, SessionExpiry, Sessions}, server,
+        services::{ldap::LdapService, mailer::Mailer, oidc::{OidcClient, OidcService}}, AppConfig, Services,
+    };
     use lazy_static::lazy_static;
     use openidconnect::{
-        core::{CoreClient, CoreProviderMetadata},
+        core::CoreProviderMetadata,
         AuthUrl, ClientId, ClientSecret, IssuerUrl, JsonWebKeySetUrl, RedirectUrl, TokenUrl,
     };
     use rocket::{
@@ -65,52 +68,96 @@ mod no_dead_code {
     }
 
     pub fn launch_with(sessions: &str) -> (Client, PgConnection) {
-        let (db_path, db) = random_pg_url();
-        let db_conn = setup_db_conn(&db_path, &db);
+        TestServer::default().sessions(sessions).launch()
+    }
 
-        (
-            Client::tracked(server(
-                PORT,
-                ADDR,
-                &db_path,
-                Sessions::from(sessions),
-                conf(),
-                true,
-                true,
-                google_oauth(),
-            ))
-            .expect("valid rocket instance"),
-            db_conn,
-        )
+    /// Test instance: sessions in memory, the Google provider, mails logged and no LDAP unless told otherwise
+    pub struct TestServer {
+        sessions: Sessions,
+        config: AppConfig,
+        services: Services,
     }
 
-    pub fn launch_with_sessions_and_mock(
-        sessions: &str,
-        oidc_service: OidcService,
-    ) -> (Client, PgConnection) {
-        let (db_path, db) = random_pg_url();
-        let db_conn = setup_db_conn(&db_path, &db);
+    impl Default for TestServer {
+        fn default() -> Self {
+            TestServer {
+                sessions: Sessions::from(""),
+                config: conf(),
+                services: Services {
+                    oidc: google_oauth(),
+                    mailer: Mailer::log(),
+                    ldap: LdapService::default(),
+                },
+            }
+        }
+    }
 
-        (
-            Client::tracked(server(
-                PORT,
-                ADDR,
-                &db_path,
-                Sessions::from(sessions),
-                conf(),
-                true,
-                true,
-                oidc_service,
-            ))
-            .expect("valid rocket instance"),
-            db_conn,
-        )
+    impl TestServer {
+        pub fn sessions(mut self, sessions: &str) -> Self {
+            self.sessions = Sessions::from(sessions);
+            self
+        }
+
+        /// Sessions stored in the database, as in production
+        pub fn db_sessions(mut self, expiry: SessionExpiry) -> Self {
+            self.sessions = Sessions::postgres(expiry);
+            self
+        }
+
+        pub fn oidc(mut self, oidc: OidcService) -> Self {
+            self.services.oidc = oidc;
+            self
+        }
+
+        pub fn mailer(mut self, mailer: Mailer) -> Self {
+            self.services.mailer = mailer;
+            self
+        }
+
+        /// Simple login binding to `ldap` first
+        pub fn ldap(mut self, ldap: LdapService) -> Self {
+            self.services.ldap = ldap;
+            self
+        }
+
+        /// Users told by `proxy_auth`
+        pub fn proxy_auth(mut self, proxy_auth: ProxyAuth) -> Self {
+            self.config.proxy_auth = Some(proxy_auth);
+            self
+        }
+
+        pub fn launch(self) -> (Client, PgConnection) {
+            let (db_path, db) = random_pg_url();
+            let db_conn = setup_db_conn(&db_path, &db);
+
+            (
+                Client::tracked(server(
+                    PORT,
+                    ADDR,
+                    &db_path,
+                    self.sessions,
+                    self.config,
+                    true,
+                    true,
+                    self.services,
+                ))
+                .expect("valid rocket instance"),
+                db_conn,
+            )
+        }
     }
 
     fn conf() -> AppConfig {
         AppConfig {
             simple_login_salt1: "salt1".to_owned(),
             simple_login_salt2: "salt2".to_owned(),
+            password_hashing: PasswordHashing {
+                memory_kib: 1024,
+                iterations: 1,
+                parallelism: 1,
+            },
+            public_url: "http://go.test".to_owned(),
+            proxy_auth: None,
         }
     }
 
@@ -131,7 +178,7 @@ mod no_dead_code {
         .set_token_endpoint(Some(
             TokenUrl::new("http://token_url/g".to_string()).unwrap(),
         ));
-        let client = CoreClient::from_provider_metadata(
+        let client = OidcClient::from_provider_metadata(
             provider_metadata,
             ClientId::new(client_id),
             Some(ClientSecret::new(client_secret)),
@@ -140,7 +187,7 @@ mod no_dead_code {
             RedirectUrl::new(format!("http://{hostname}/go/login/redirect/google")).unwrap(),
         );
 
-        OidcService::new(client)
+        OidcService::new().with_provider("google", "Google", client)
     }
 
     pub async fn in_browser<F>(sessions: &str, f: F)
@@ -219,7 +266,7 @@ mod no_dead_code {
                 conf(),
                 true,
                 true,
-                google_oauth(),
+                TestServer::default().services,
             )
             .launch()
             .await
@@ -0,0 +1,217 @@
+pub use no_dead_code::*;
+
+#[allow(dead_code)]
+mod no_dead_code {
+    use chrono::{TimeDelta, Utc};
+    use go_web::services::oidc::{OidcProviderConfig, OidcService, OtherClaims};
+    use openidconnect::{
+        core::{
+            CoreGenderClaim, CoreJsonWebKeySet, CoreJsonWebKeyType, CoreJweContentEncryptionAlgorithm,
+            CoreJwsSigningAlgorithm, CoreRsaPrivateSigningKey,
+        },
+        Audience, EndUserEmail, IdToken, IdTokenClaims, IssuerUrl, JsonWebKeyId, Nonce,
+        PkceCodeChallenge, PkceCodeVerifier, PrivateSigningKey, StandardClaims, SubjectIdentifier,
+    };
+    use rocket::{
+        config::LogLevel, form::Form, get, post, routes, serde::json::Json, Config, FromForm, State,
+    };
+    use serde_json::{json, Value};
+    use std::{
+        collections::HashMap,
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    const KEY: &str = include_str!("mock_issuer_key.pem");
+    pub const MOCK_CLIENT_ID: &str = "go_client";
+
+    /// Codes the issuer will exchange, with the mail and nonce of their ID token, `None` to send none
+    type Codes = Arc<Mutex<HashMap<String, Authorization>>>;
+
+    struct Authorization {
+        mail: Option<String>,
+        /// Besides the standard ones, like `groups`
+        claims: HashMap<String, Value>,
+        nonce: String,
+        pkce_challenge: String,
+    }
+
+    struct Issuer {
+        url: String,
+        codes: Codes,
+    }
+
+    /// OpenID Connect issuer listening on a random local port, signing ID tokens for the
+    /// codes it was told about
+    pub struct MockIssuer {
+        pub url: String,
+        codes: Codes,
+    }
+
+    impl MockIssuer {
+        pub fn start() -> Self {
+            let port = TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let url = format!("http://127.0.0.1:{port}");
+            let codes = Codes::default();
+
+            let issuer = Issuer {
+                url: url.clone(),
+                codes: codes.clone(),
+            };
+            thread::spawn(move || {
+                let config = Config {
+                    port,
+                    address: "127.0.0.1".parse().unwrap(),
+                    log_level: LogLevel::Off,
+                    ..Config::debug_default()
+                };
+                rocket::execute(
+                    rocket::custom(config)
+                        .mount("/", routes![configuration, jwks, token])
+                        .manage(issuer)
+                        .launch(),
+                )
+            });
+            while TcpStream::connect(("127.0.0.1", port)).is_err() {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            Self { url, codes }
+        }
+
+        pub fn config(&self, id: &str) -> OidcProviderConfig {
+            OidcProviderConfig {
+                id: id.to_string(),
+                name: format!("Mock {id}"),
+                issuer_url: self.url.clone(),
+                client_id: MOCK_CLIENT_ID.to_string(),
+                client_secret: "go_secret".to_string(),
+                scopes: vec![],
+                trust_unverified_email: false,
+            }
+        }
+
+        /// Discovered like in production, a provider per id
+        pub fn service(&self, ids: &[&str]) -> OidcService {
+            let configs: Vec<_> = ids.iter().map(|id| self.config(id)).collect();
+            rocket::execute(OidcService::discover(&configs, "http://go.test"))
+        }
+
+        /// What the issuer does once `mail` logged in, `auth_url` holding the nonce and PKCE
+        /// challenge of the app. Returns the state to send back
+        pub fn authorize(&self, code: &str, mail: &str, auth_url: &str) -> String {
+            self.authorize_as(code, Some(mail), json!({}), auth_url)
+        }
+
+        /// With more claims in the ID token, `claims` being a JSON object.
+        /// `email_verified` is `true` unless given
+        pub fn authorize_with_claims(&self, code: &str, mail: &str, claims: Value, auth_url: &str) -> String {
+            self.authorize_as(code, Some(mail), claims, auth_url)
+        }
+
+        /// Like a broken issuer
+        pub fn authorize_without_id_token(&self, code: &str, auth_url: &str) -> String {
+            self.authorize_as(code, None, json!({}), auth_url)
+        }
+
+        fn authorize_as(&self, code: &str, mail: Option<&str>, claims: Value, auth_url: &str) -> String {
+            let auth_url = openidconnect::url::Url::parse(auth_url).unwrap();
+            let param = |name: &str| {
+                auth_url
+                    .query_pairs()
+                    .find(|(param, _)| param == name)
+                    .unwrap()
+                    .1
+                    .to_string()
+            };
+            assert_eq!(param("code_challenge_method"), "S256");
+
+            self.codes.lock().unwrap().insert(
+                code.to_string(),
+                Authorization {
+                    mail: mail.map(str::to_string),
+                    claims: serde_json::from_value(claims).unwrap(),
+                    nonce: param("nonce"),
+                    pkce_challenge: param("code_challenge"),
+                },
+            );
+            param("state")
+        }
+    }
+
+    fn signing_key() -> CoreRsaPrivateSigningKey {
+        CoreRsaPrivateSigningKey::from_pem(KEY, Some(JsonWebKeyId::new("mock".to_string()))).unwrap()
+    }
+
+    #[get("/.well-known/openid-configuration")]
+    fn configuration(issuer: &State<Issuer>) -> Json<Value> {
+        let url = &issuer.url;
+        Json(json!({
+            "issuer": url,
+            "authorization_endpoint": format!("{url}/authorize"),
+            "token_endpoint": format!("{url}/token"),
+            "jwks_uri": format!("{url}/jwks"),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+        }))
+    }
+
+    #[get("/jwks")]
+    fn jwks() -> Json<CoreJsonWebKeySet> {
+        Json(CoreJsonWebKeySet::new(vec![signing_key().as_verification_key()]))
+    }
+
+    #[derive(FromForm)]
+    struct TokenRequest {
+        code: String,
+        code_verifier: String,
+    }
+
+    #[post("/token", data = "<request>")]
+    fn token(request: Form<TokenRequest>, issuer: &State<Issuer>) -> Option<Json<Value>> {
+        let authorization = issuer.codes.lock().unwrap().remove(&request.code)?;
+        let verifier = PkceCodeVerifier::new(request.code_verifier.clone());
+        if PkceCodeChallenge::from_code_verifier_sha256(&verifier).as_str() != authorization.pkce_challenge {
+            return None;
+        }
+        let mut claims = authorization.claims;
+        let email_verified = claims.remove("email_verified").and_then(|verified| verified.as_bool());
+        let Some(mail) = authorization.mail else {
+            return Some(Json(json!({ "access_token": "some_access_token", "token_type": "bearer" })));
+        };
+
+        let now = Utc::now();
+        let claims = IdTokenClaims::<OtherClaims, CoreGenderClaim>::new(
+            IssuerUrl::new(issuer.url.clone()).unwrap(),
+            vec![Audience::new(MOCK_CLIENT_ID.to_string())],
+            now + TimeDelta::minutes(5),
+            now,
+            StandardClaims::new(SubjectIdentifier::new(mail.clone()))
+                .set_email(Some(EndUserEmail::new(mail)))
+                .set_email_verified(Some(email_verified.unwrap_or(true))),
+            OtherClaims { claims },
+        )
+        .set_nonce(Some(Nonce::new(authorization.nonce)));
+        let id_token = IdToken::<_, _, CoreJweContentEncryptionAlgorithm, _, CoreJsonWebKeyType>::new(
+            claims,
+            &signing_key(),
+            CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+            None,
+            None,
+        )
+        .unwrap();
+
+        Some(Json(json!({
+            "access_token": "some_access_token",
+            "token_type": "bearer",
+            "id_token": id_token,
+        })))
+    }
+}
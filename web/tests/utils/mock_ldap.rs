@@ -0,0 +1,250 @@
+pub use no_dead_code::*;
+
+#[allow(dead_code)]
+mod no_dead_code {
+    use bytes::BytesMut;
+    use go_web::services::ldap::{LdapConfig, LdapService};
+    use lber::{
+        common::TagClass,
+        parse::{parse_tag, parse_uint},
+        structure::{StructureTag, PL},
+        structures::{ASNTag, Enumerated, Integer, OctetString},
+        universal::Types,
+        write::encode_into,
+    };
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    pub const BASE_DN: &str = "dc=go,dc=test";
+
+    const SUCCESS: i64 = 0;
+    const INVALID_CREDENTIALS: i64 = 49;
+    const INSUFFICIENT_ACCESS_RIGHTS: i64 = 50;
+
+    struct Entry {
+        dn: String,
+        pwd: String,
+        attributes: Vec<(String, Vec<String>)>,
+    }
+
+    type Directory = Arc<Mutex<Vec<Entry>>>;
+
+    /// LDAP server listening on a random local port, answering simple binds and searches with
+    /// equality filters like OpenLDAP would
+    pub struct MockLdap {
+        pub url: String,
+        directory: Directory,
+    }
+
+    impl MockLdap {
+        pub fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("ldap://{}", listener.local_addr().unwrap());
+            let directory = Directory::default();
+
+            let served = directory.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let directory = served.clone();
+                    thread::spawn(move || serve(stream, directory));
+                }
+            });
+
+            Self { url, directory }
+        }
+
+        /// `uid={uid},ou=people,dc=go,dc=test`, member of `cn={group},ou=groups,dc=go,dc=test`
+        pub fn add_user(&self, uid: &str, mail: &str, pwd: &str, groups: &[&str]) {
+            self.directory.lock().unwrap().push(Entry {
+                dn: format!("uid={uid},ou=people,{BASE_DN}"),
+                pwd: pwd.to_string(),
+                attributes: vec![
+                    ("uid".to_string(), vec![uid.to_string()]),
+                    ("mail".to_string(), vec![mail.to_string()]),
+                    (
+                        "memberOf".to_string(),
+                        groups
+                            .iter()
+                            .map(|group| format!("cn={group},ou=groups,{BASE_DN}"))
+                            .collect(),
+                    ),
+                ],
+            });
+        }
+
+        pub fn config(&self) -> LdapConfig {
+            LdapConfig {
+                url: self.url.clone(),
+                bind_dn: format!("uid={{user}},ou=people,{BASE_DN}"),
+                base_dn: BASE_DN.to_string(),
+                user_filter: "(mail={mail})".to_string(),
+            }
+        }
+
+        pub fn service(&self) -> LdapService {
+            LdapService::new(self.config())
+        }
+    }
+
+    fn serve(mut stream: TcpStream, directory: Directory) {
+        let mut bound: Option<String> = None;
+        let mut received: Vec<u8> = vec![];
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            let message = match parse_tag(&received) {
+                Ok((rest, message)) => {
+                    let consumed = received.len() - rest.len();
+                    received.drain(..consumed);
+                    message
+                }
+                Err(lber::Err::Incomplete(_)) => match stream.read(&mut chunk) {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => {
+                        received.extend_from_slice(&chunk[..read]);
+                        continue;
+                    }
+                },
+                Err(_) => return,
+            };
+
+            let mut parts = message.expect_constructed().unwrap().into_iter();
+            let id = primitive(parts.next().unwrap());
+            let (_, id) = parse_uint(&id).unwrap();
+            let operation = parts.next().unwrap();
+
+            let responses = match operation.id {
+                0 => bind(operation, &directory, &mut bound),
+                3 => search(operation, &directory, &bound),
+                _ => return,
+            };
+            for response in responses {
+                let mut buf = BytesMut::new();
+                let message = constructed(TagClass::Universal, Types::Sequence as u64, vec![
+                    Integer {
+                        id: Types::Integer as u64,
+                        class: TagClass::Universal,
+                        inner: id as i64,
+                    }
+                    .into_structure(),
+                    response,
+                ]);
+                encode_into(&mut buf, message).unwrap();
+                if stream.write_all(&buf).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn bind(operation: StructureTag, directory: &Directory, bound: &mut Option<String>) -> Vec<StructureTag> {
+        let mut parts = operation.expect_constructed().unwrap().into_iter().skip(1);
+        let dn = text(parts.next().unwrap());
+        let pwd = text(parts.next().unwrap());
+
+        let known = directory
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|entry| entry.dn.eq_ignore_ascii_case(&dn) && entry.pwd == pwd && !pwd.is_empty());
+        *bound = known.then_some(dn);
+
+        vec![result(1, if known { SUCCESS } else { INVALID_CREDENTIALS })]
+    }
+
+    fn search(operation: StructureTag, directory: &Directory, bound: &Option<String>) -> Vec<StructureTag> {
+        if bound.is_none() {
+            return vec![result(5, INSUFFICIENT_ACCESS_RIGHTS)];
+        }
+        let mut parts = operation.expect_constructed().unwrap().into_iter();
+        let base = text(parts.next().unwrap()).to_lowercase();
+        let filter = parts.nth(5).unwrap();
+
+        // Only equality filters, matching nothing otherwise
+        let (attribute, value) = match filter.class == TagClass::Context && filter.id == 3 {
+            true => {
+                let mut assertion = filter.expect_constructed().unwrap().into_iter();
+                (text(assertion.next().unwrap()), text(assertion.next().unwrap()))
+            }
+            false => (String::new(), String::new()),
+        };
+
+        let mut responses: Vec<StructureTag> = directory
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.dn.to_lowercase().ends_with(&base))
+            .filter(|entry| {
+                entry.attributes.iter().any(|(name, values)| {
+                    name.eq_ignore_ascii_case(&attribute)
+                        && values.iter().any(|one| one.eq_ignore_ascii_case(&value))
+                })
+            })
+            .map(|entry| {
+                let attributes = entry
+                    .attributes
+                    .iter()
+                    .map(|(name, values)| {
+                        constructed(TagClass::Universal, Types::Sequence as u64, vec![
+                            octets(name),
+                            constructed(
+                                TagClass::Universal,
+                                Types::Set as u64,
+                                values.iter().map(|value| octets(value)).collect(),
+                            ),
+                        ])
+                    })
+                    .collect();
+                constructed(TagClass::Application, 4, vec![
+                    octets(&entry.dn),
+                    constructed(TagClass::Universal, Types::Sequence as u64, attributes),
+                ])
+            })
+            .collect();
+        responses.push(result(5, SUCCESS));
+
+        responses
+    }
+
+    /// LDAPResult of the `operation` response
+    fn result(operation: u64, code: i64) -> StructureTag {
+        constructed(TagClass::Application, operation, vec![
+            Enumerated {
+                id: Types::Enumerated as u64,
+                class: TagClass::Universal,
+                inner: code,
+            }
+            .into_structure(),
+            octets(""),
+            octets(""),
+        ])
+    }
+
+    fn constructed(class: TagClass, id: u64, tags: Vec<StructureTag>) -> StructureTag {
+        StructureTag {
+            class,
+            id,
+            payload: PL::C(tags),
+        }
+    }
+
+    fn octets(value: &str) -> StructureTag {
+        OctetString {
+            inner: value.as_bytes().to_vec(),
+            ..Default::default()
+        }
+        .into_structure()
+    }
+
+    fn primitive(tag: StructureTag) -> Vec<u8> {
+        tag.expect_primitive().unwrap()
+    }
+
+    fn text(tag: StructureTag) -> String {
+        String::from_utf8(primitive(tag)).unwrap()
+    }
+}
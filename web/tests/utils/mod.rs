@@ -1,9 +1,18 @@
 mod factories;
 mod helpers;
+mod mock_issuer;
+mod mock_ldap;
+mod outbox;
 mod selectors;
 
 #[allow(unused_imports)]
 pub use factories::*;
 pub use helpers::*;
 #[allow(unused_imports)]
+pub use mock_issuer::*;
+#[allow(unused_imports)]
+pub use mock_ldap::*;
+#[allow(unused_imports)]
+pub use outbox::*;
+#[allow(unused_imports)]
 pub use selectors::*;
\ No newline at end of file
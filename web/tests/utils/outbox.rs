@@ -0,0 +1,35 @@
+pub use no_dead_code::*;
+
+#[allow(dead_code)]
+mod no_dead_code {
+    use go_web::services::mailer::{Mail, MailTransport, Mailer, MailerError};
+    use rocket::local::blocking::Client;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Keeps the mails sent by the app
+    #[derive(Clone, Default)]
+    pub struct Outbox(Arc<Mutex<Vec<Mail>>>);
+
+    impl MailTransport for Outbox {
+        fn send(&self, mail: &Mail) -> Result<(), MailerError> {
+            self.0.lock().unwrap().push(mail.clone());
+            Ok(())
+        }
+    }
+
+    impl Outbox {
+        /// Once the mails queued by `client` are sent
+        pub fn mails(&self, client: &Client) -> Vec<Mail> {
+            client.rocket().state::<Mailer>().unwrap().flush(Duration::from_secs(5));
+            self.0.lock().unwrap().clone()
+        }
+
+        /// Of the link starting with `url` in the last mail
+        pub fn last_token(&self, client: &Client, url: &str) -> String {
+            let body = self.mails(client).last().unwrap().body.clone();
+            let (_, token) = body.split_once(&format!("{url}?token=")).unwrap();
+            token.split_whitespace().next().unwrap().to_string()
+        }
+    }
+}
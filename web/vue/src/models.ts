@@ -8,6 +8,13 @@ export type Capability =
 
 export type TeamCapability = "ShortcutsWrite" | "TeamsWrite";
 
+export type RedirectMode =
+  | "Found"
+  | "Temporary"
+  | "Moved"
+  | "Permanent"
+  | "Interstitial";
+
 export interface Team {
   slug: string;
   title: string;
@@ -49,3 +56,50 @@ export const ALL_TEAM_CAPABILITIES = [
   "ShortcutsWrite",
   "TeamsWrite",
 ].sort() as TeamCapability[];
+
+export const ALL_REDIRECT_MODES = [
+  "Found",
+  "Temporary",
+  "Moved",
+  "Permanent",
+  "Interstitial",
+] as RedirectMode[];
+
+export type VersionAction = "Create" | "Update" | "Delete";
+
+export interface ShortcutVersion {
+  shortcut: string;
+  team_slug: string;
+  version: number;
+  action: VersionAction;
+  old_url?: string;
+  new_url?: string;
+  redirect?: RedirectMode;
+  user_mail: string;
+  created_at: string;
+}
+
+export type TokenScope = "Read" | "ShortcutsWrite" | "TeamsAdmin";
+
+export const ALL_TOKEN_SCOPES = [
+  "Read",
+  "ShortcutsWrite",
+  "TeamsAdmin",
+] as TokenScope[];
+
+export interface UserToken {
+  id: number;
+  name: string;
+  scopes: TokenScope[];
+  created_at: string;
+  last_used_at?: string;
+}
+
+export interface ActiveSession {
+  id: string;
+  created_at: string;
+  last_seen_at: string;
+  user_agent?: string;
+  ip?: string;
+  current: boolean;
+}
@@ -0,0 +1,4 @@
+import { createApp } from "vue";
+import Partial from "./Partial.vue";
+
+createApp(Partial).mount("#vue-partial");
@@ -27,5 +27,25 @@ module.exports = {
       template: "public/index.html",
       filename: "users.html.hbs",
     },
+    tokens: {
+      entry: "src/partials/tokens/main.ts",
+      template: "public/index.html",
+      filename: "tokens.html.hbs",
+    },
+    sessions: {
+      entry: "src/partials/sessions/main.ts",
+      template: "public/index.html",
+      filename: "sessions.html.hbs",
+    },
+    password: {
+      entry: "src/partials/password/main.ts",
+      template: "public/index.html",
+      filename: "password.html.hbs",
+    },
+    reset: {
+      entry: "src/partials/reset/main.ts",
+      template: "public/index.html",
+      filename: "reset.html.hbs",
+    },
   },
 };